@@ -0,0 +1,148 @@
+//! Continuous view buffer against the polars implementation it replaced
+//!
+//! Run with `cargo bench --bench continuous_buffer`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use polars::prelude::{col, lit, Column, DataFrame, DataType, IntoLazy, SortMultipleOptions};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/continuous_buffer.rs"]
+mod continuous_buffer;
+
+use continuous_buffer::{ContinuousBatch, ContinuousBuffer, MAX_BUFFER_TIME};
+
+/// Same as `CONTINUOUS_SAMPLE_RATE_HZ`
+const SAMPLE_RATE_HZ: u32 = 51_436;
+/// Samples per batch the worker sends
+const BATCH_LENGTH: usize = 512;
+const PLOT_WIDTH: u32 = 1000;
+
+/// The former buffer: a `DataFrame` grown by `vstack` and binned with a lazy `group_by`
+struct PolarsBuffer {
+    data: DataFrame,
+    last_t: f64,
+}
+
+impl PolarsBuffer {
+    fn new() -> Self {
+        Self {
+            data: DataFrame::new(vec![
+                Column::new("time".into(), [0.0].as_ref()),
+                Column::new("bnc".into(), [0.0].as_ref()),
+            ])
+            .unwrap(),
+            last_t: 0.0,
+        }
+    }
+
+    fn add_batch(&mut self, batch: Vec<f64>, keep_time: f64) {
+        let time_step = 1.0 / SAMPLE_RATE_HZ as f64;
+        let time: Vec<f64> = (0..batch.len())
+            .map(|i| self.last_t + i as f64 * time_step)
+            .collect();
+        self.last_t += batch.len() as f64 * time_step;
+        let batch = DataFrame::new(vec![
+            Column::new("time".into(), time),
+            Column::new("bnc".into(), batch),
+        ])
+        .unwrap();
+        self.data = self.data.vstack(&batch).unwrap();
+
+        let keep_samples = (keep_time * SAMPLE_RATE_HZ as f64) as usize;
+        if self.data.height() > keep_samples {
+            let remove = self.data.height() - keep_samples;
+            self.data = self.data.slice(remove as i64, keep_samples);
+        }
+    }
+
+    fn get_data_in_window(&self, window_duration: f64, plot_width: u32) -> DataFrame {
+        let time_bin_size = window_duration / plot_width as f64;
+        self.data
+            .clone()
+            .lazy()
+            .filter(col("time").gt_eq(lit(self.last_t - window_duration)))
+            .with_column(
+                (col("time") / lit(time_bin_size))
+                    .cast(DataType::Int32)
+                    .alias("time_bin"),
+            )
+            .group_by([col("time_bin")])
+            .agg([
+                col("time").min().alias("time_min"),
+                col("time").max().alias("time_max"),
+                col("bnc").min().alias("bnc_min"),
+                col("bnc").median().alias("bnc_median"),
+                col("bnc").mean().alias("bnc_mean"),
+                col("bnc").max().alias("bnc_max"),
+            ])
+            .sort(["time_min"], SortMultipleOptions::default())
+            .with_row_index("idx", None)
+            .filter(col("idx").gt(lit(0)).and(col("idx").lt(col("idx").max())))
+            .sort(["time_min"], SortMultipleOptions::default())
+            .select([
+                col("time_min").alias("time"),
+                col("bnc_mean"),
+                col("bnc_min"),
+                col("bnc_max"),
+            ])
+            .collect()
+            .unwrap()
+    }
+}
+
+fn batch(index: usize) -> ContinuousBatch {
+    let samples = (index * BATCH_LENGTH..(index + 1) * BATCH_LENGTH).map(|i| i as f64);
+    ContinuousBatch {
+        analog: samples.clone().map(|i| (i * 0.01).sin()).collect(),
+        bitmap: Some(samples.map(|i| (i / 64.0) as u16 & 0x1ff).collect()),
+    }
+}
+
+/// Both buffers holding `MAX_BUFFER_TIME` of samples
+fn full_buffers() -> (PolarsBuffer, ContinuousBuffer) {
+    let mut polars = PolarsBuffer::new();
+    let mut ring = ContinuousBuffer::new(SAMPLE_RATE_HZ, MAX_BUFFER_TIME);
+    let batches = (MAX_BUFFER_TIME * SAMPLE_RATE_HZ as f64) as usize / BATCH_LENGTH + 1;
+    for i in 0..batches {
+        polars.add_batch(batch(i).analog, MAX_BUFFER_TIME);
+        ring.add_batch(batch(i));
+    }
+    (polars, ring)
+}
+
+fn add_batch(c: &mut Criterion) {
+    let (mut polars, mut ring) = full_buffers();
+    let mut group = c.benchmark_group("add_batch");
+    group.bench_function("polars", |b| {
+        b.iter_batched(
+            || batch(0),
+            |batch| polars.add_batch(batch.analog, MAX_BUFFER_TIME),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("ring", |b| {
+        b.iter_batched(
+            || batch(0),
+            |batch| ring.add_batch(batch),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn window(c: &mut Criterion) {
+    let (polars, ring) = full_buffers();
+    for window in [0.01, 1.0, MAX_BUFFER_TIME] {
+        let mut group = c.benchmark_group(format!("window_{}s", window));
+        group.bench_function("polars", |b| {
+            b.iter(|| polars.get_data_in_window(black_box(window), PLOT_WIDTH))
+        });
+        group.bench_function("ring", |b| {
+            b.iter(|| ring.get_data_in_window(black_box(window), false, PLOT_WIDTH))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, add_batch, window);
+criterion_main!(benches);
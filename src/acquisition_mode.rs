@@ -0,0 +1,330 @@
+use egui::{Color32, RichText};
+use std::collections::VecDeque;
+
+/// Captures averaged or enveloped unless changed
+const DEFAULT_COUNT: usize = 16;
+pub const MAX_COUNT: usize = 256;
+
+/// How successive triggered captures are combined on top of the live trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquisitionMode {
+    Normal,
+    /// Mean of the last N captures, sample by sample
+    Average,
+    /// Lowest and highest value of the last N captures, sample by sample
+    Envelope,
+    /// Lowest and highest sample of each plot column, so spikes survive the pixel grid
+    PeakDetect,
+}
+
+impl AcquisitionMode {
+    pub const ALL: [AcquisitionMode; 4] = [
+        AcquisitionMode::Normal,
+        AcquisitionMode::Average,
+        AcquisitionMode::Envelope,
+        AcquisitionMode::PeakDetect,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AcquisitionMode::Normal => "NORM",
+            AcquisitionMode::Average => "AVG",
+            AcquisitionMode::Envelope => "ENV",
+            AcquisitionMode::PeakDetect => "PEAK",
+        }
+    }
+
+    /// Whether the mode combines several captures
+    pub fn uses_count(&self) -> bool {
+        matches!(self, AcquisitionMode::Average | AcquisitionMode::Envelope)
+    }
+}
+
+/// Trace computed by an acquisition mode
+pub struct ModeTrace {
+    pub name: String,
+    pub points: Vec<[f64; 2]>,
+}
+
+/// Acquisition mode of one device and the captures it combines
+pub struct Acquisition {
+    pub mode: AcquisitionMode,
+    pub count: usize,
+    history: VecDeque<Vec<f64>>,
+    /// Running sum of `history`, so averaging stays cheap for large counts
+    sum: Vec<f64>,
+    time: Vec<f64>,
+    traces: Vec<ModeTrace>,
+}
+
+impl Default for Acquisition {
+    fn default() -> Self {
+        Self {
+            mode: AcquisitionMode::Normal,
+            count: DEFAULT_COUNT,
+            history: VecDeque::new(),
+            sum: Vec::new(),
+            time: Vec::new(),
+            traces: Vec::new(),
+        }
+    }
+}
+
+impl Acquisition {
+    /// Forget the combined captures, e.g. because the capture settings changed
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.sum.clear();
+        self.time.clear();
+        self.traces.clear();
+    }
+
+    pub fn set_mode(&mut self, mode: AcquisitionMode) {
+        if self.mode != mode {
+            self.mode = mode;
+            self.reset();
+        }
+    }
+
+    /// Add a capture, `columns` is the plot width for peak detection
+    pub fn accumulate(&mut self, time: &[f64], volts: &[f64], columns: u32) {
+        profiling::scope!("Acquisition::accumulate");
+
+        match self.mode {
+            AcquisitionMode::Normal => {}
+            AcquisitionMode::Average | AcquisitionMode::Envelope => {
+                // A capture of another length or timebase cannot be combined with the earlier ones
+                if !same_timebase(&self.time, time) {
+                    self.reset();
+                    self.time = time.to_vec();
+                    self.sum = vec![0.0; volts.len()];
+                }
+                self.history.push_back(volts.to_vec());
+                self.sum.iter_mut().zip(volts).for_each(|(s, v)| *s += v);
+                while self.history.len() > self.count {
+                    if let Some(oldest) = self.history.pop_front() {
+                        self.sum.iter_mut().zip(oldest).for_each(|(s, v)| *s -= v);
+                    }
+                }
+                self.traces = if self.mode == AcquisitionMode::Average {
+                    self.average()
+                } else {
+                    self.envelope()
+                };
+            }
+            AcquisitionMode::PeakDetect => {
+                self.traces = peak_detect(time, volts, columns.max(1) as usize);
+            }
+        }
+    }
+
+    fn average(&self) -> Vec<ModeTrace> {
+        let n = self.history.len() as f64;
+        vec![ModeTrace {
+            name: format!("Average ({})", self.history.len()),
+            points: self
+                .time
+                .iter()
+                .zip(&self.sum)
+                .map(|(t, s)| [*t, s / n])
+                .collect(),
+        }]
+    }
+
+    fn envelope(&self) -> Vec<ModeTrace> {
+        let mut low = vec![f64::INFINITY; self.time.len()];
+        let mut high = vec![f64::NEG_INFINITY; self.time.len()];
+        for capture in &self.history {
+            for (i, v) in capture.iter().enumerate().filter(|(_, v)| v.is_finite()) {
+                low[i] = low[i].min(*v);
+                high[i] = high[i].max(*v);
+            }
+        }
+        let trace = |name: String, values: Vec<f64>| ModeTrace {
+            name,
+            points: self
+                .time
+                .iter()
+                .zip(values)
+                .filter(|(_, v)| v.is_finite())
+                .map(|(t, v)| [*t, v])
+                .collect(),
+        };
+        vec![
+            trace(format!("Max ({})", self.history.len()), high),
+            trace(format!("Min ({})", self.history.len()), low),
+        ]
+    }
+
+    pub fn traces(&self) -> &[ModeTrace] {
+        &self.traces
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if !self.mode.uses_count() {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(self.mode.label()).strong());
+            ui.label("Captures:");
+            // A smaller count takes effect with the next capture
+            ui.add(egui::Slider::new(&mut self.count, 2..=MAX_COUNT).logarithmic(true));
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+            ui.label(
+                RichText::new(format!("{}/{} captures", self.history.len(), self.count))
+                    .size(9.0)
+                    .color(Color32::LIGHT_GRAY),
+            );
+        });
+    }
+}
+
+/// Whether two captures sample the same instants, up to rounding
+fn same_timebase(a: &[f64], b: &[f64]) -> bool {
+    match (a, b) {
+        ([a0, a1, ..], [b0, .., bn]) if a.len() == b.len() => {
+            let tolerance = (a1 - a0) / 2.0;
+            (a0 - b0).abs() < tolerance && (a[a.len() - 1] - bn).abs() < tolerance
+        }
+        _ => false,
+    }
+}
+
+/// Lowest and highest sample per plot column, drawn at the column's first instant
+fn peak_detect(time: &[f64], volts: &[f64], columns: usize) -> Vec<ModeTrace> {
+    let per_column = volts.len().div_ceil(columns).max(1);
+    let (high, low): (Vec<[f64; 2]>, Vec<[f64; 2]>) = time
+        .chunks(per_column)
+        .zip(volts.chunks(per_column))
+        .filter_map(|(t, v)| {
+            let (lo, hi) =
+                v.iter()
+                    .filter(|v| v.is_finite())
+                    .fold(None, |range, &v| match range {
+                        None => Some((v, v)),
+                        Some((lo, hi)) => Some((f64::min(lo, v), f64::max(hi, v))),
+                    })?;
+            Some(([t[0], hi], [t[0], lo]))
+        })
+        .unzip();
+    vec![
+        ModeTrace {
+            name: "Peak max".to_string(),
+            points: high,
+        },
+        ModeTrace {
+            name: "Peak min".to_string(),
+            points: low,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(n: usize) -> Vec<f64> {
+        (0..n).map(|i| i as f64 * 1e-3).collect()
+    }
+
+    fn acquisition(mode: AcquisitionMode, count: usize) -> Acquisition {
+        let mut acquisition = Acquisition::default();
+        acquisition.set_mode(mode);
+        acquisition.count = count;
+        acquisition
+    }
+
+    fn values(trace: &ModeTrace) -> Vec<f64> {
+        trace.points.iter().map(|[_, v]| *v).collect()
+    }
+
+    #[test]
+    fn averages_the_last_captures() {
+        let mut acquisition = acquisition(AcquisitionMode::Average, 3);
+        let time = time(2);
+        for level in [100.0, 1.0, 2.0, 3.0] {
+            acquisition.accumulate(&time, &[level, -level], 10);
+        }
+
+        // The first capture left the running sum when the fourth came in
+        let trace = &acquisition.traces()[0];
+        assert_eq!(trace.name, "Average (3)");
+        assert_eq!(values(trace), [2.0, -2.0]);
+        assert_eq!(trace.points[1][0], time[1]);
+    }
+
+    #[test]
+    fn shrinks_to_a_smaller_count_with_the_next_capture() {
+        let mut acquisition = acquisition(AcquisitionMode::Average, 4);
+        let time = time(3);
+        for level in [1.0, 2.0, 3.0, 4.0] {
+            acquisition.accumulate(&time, &[level; 3], 10);
+        }
+        assert_eq!(values(&acquisition.traces()[0]), [2.5; 3]);
+
+        acquisition.count = 2;
+        acquisition.accumulate(&time, &[5.0; 3], 10);
+        assert_eq!(acquisition.traces()[0].name, "Average (2)");
+        assert_eq!(values(&acquisition.traces()[0]), [4.5; 3]);
+    }
+
+    #[test]
+    fn envelopes_the_captures() {
+        let mut acquisition = acquisition(AcquisitionMode::Envelope, 8);
+        let time = time(3);
+        acquisition.accumulate(&time, &[1.0, 5.0, f64::NAN], 10);
+        acquisition.accumulate(&time, &[3.0, -1.0, 2.0], 10);
+
+        let traces = acquisition.traces();
+        assert_eq!(values(&traces[0]), [3.0, 5.0, 2.0]);
+        assert_eq!(values(&traces[1]), [1.0, -1.0, 2.0]);
+    }
+
+    #[test]
+    fn restarts_on_another_timebase() {
+        let mut acquisition = acquisition(AcquisitionMode::Average, 8);
+        acquisition.accumulate(&time(4), &[1.0; 4], 10);
+        acquisition.accumulate(&time(4), &[3.0; 4], 10);
+        assert_eq!(values(&acquisition.traces()[0]), [2.0; 4]);
+
+        // Same length, but twice the time frame
+        let slower: Vec<f64> = time(4).iter().map(|t| t * 2.0).collect();
+        acquisition.accumulate(&slower, &[5.0; 4], 10);
+        assert_eq!(acquisition.traces()[0].name, "Average (1)");
+        assert_eq!(values(&acquisition.traces()[0]), [5.0; 4]);
+
+        acquisition.reset();
+        assert!(acquisition.traces().is_empty());
+    }
+
+    #[test]
+    fn compares_timebases_up_to_rounding() {
+        let a = time(100);
+        let shifted: Vec<f64> = a.iter().map(|t| t + 0.4e-3).collect();
+        let late: Vec<f64> = a.iter().map(|t| t + 0.6e-3).collect();
+        assert!(same_timebase(&a, &a));
+        assert!(same_timebase(&a, &shifted));
+        assert!(!same_timebase(&a, &late));
+        assert!(!same_timebase(&a, &a[..99]));
+        assert!(!same_timebase(&[], &[]));
+        assert!(!same_timebase(&[0.0], &[0.0]));
+    }
+
+    #[test]
+    fn keeps_the_extremes_of_every_column() {
+        let time = time(10);
+        let mut volts = vec![0.0; 10];
+        // A single-sample spike in the second column
+        volts[6] = 9.0;
+        volts[2] = f64::NAN;
+        let traces = peak_detect(&time, &volts, 3);
+
+        // Four samples per column, the last column holds the remaining two
+        let columns: Vec<f64> = traces[0].points.iter().map(|[t, _]| *t).collect();
+        assert_eq!(columns, [time[0], time[4], time[8]]);
+        assert_eq!(values(&traces[0]), [0.0, 9.0, 0.0]);
+        assert_eq!(values(&traces[1]), [0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Local};
+use egui::{Color32, RichText};
+use polars::error::PolarsError;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Entries kept in the per-device error log
+const LOG_CAPACITY: usize = 200;
+
+/// The same kind of error is notified at most this often, the log keeps every occurrence
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Why a frame from the device was dropped
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("Malformed capture data: {0}")]
+    Parse(#[source] PolarsError),
+    #[error("Failed to apply calibration: {0}")]
+    Calibration(#[source] PolarsError),
+    #[error("Column '{0}' is missing")]
+    MissingColumn(&'static str),
+    #[error("Column '{column}' has type {found}, expected {expected}")]
+    ColumnType {
+        column: &'static str,
+        found: String,
+        expected: &'static str,
+    },
+    #[error("Column '{0}' contains empty values")]
+    NullValue(&'static str),
+    #[error("Invalid digital bitmap '{0}'")]
+    InvalidBitmap(String),
+}
+
+impl CaptureError {
+    /// Short name the errors are counted by
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CaptureError::Parse(_) => "parse",
+            CaptureError::Calibration(_) => "calibration",
+            CaptureError::MissingColumn(_) => "missing column",
+            CaptureError::ColumnType { .. } => "column type",
+            CaptureError::NullValue(_) => "null value",
+            CaptureError::InvalidBitmap(_) => "bitmap",
+        }
+    }
+}
+
+struct LoggedError {
+    at: DateTime<Local>,
+    kind: &'static str,
+    message: String,
+}
+
+/// Capture errors of one device, counted by kind
+#[derive(Default)]
+pub struct CaptureErrorLog {
+    counts: BTreeMap<&'static str, usize>,
+    entries: VecDeque<LoggedError>,
+    last_notified: BTreeMap<&'static str, Instant>,
+}
+
+impl CaptureErrorLog {
+    /// Log the error and tell whether it is worth a notification
+    pub fn record(&mut self, error: &CaptureError) -> bool {
+        let kind = error.kind();
+        *self.counts.entry(kind).or_default() += 1;
+        if self.entries.len() == LOG_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LoggedError {
+            at: Local::now(),
+            kind,
+            message: error.to_string(),
+        });
+
+        let now = Instant::now();
+        let notify = self
+            .last_notified
+            .get(kind)
+            .is_none_or(|last| now - *last >= NOTIFY_INTERVAL);
+        if notify {
+            self.last_notified.insert(kind, now);
+        }
+        notify
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, idx: usize) {
+        if self.total() == 0 {
+            return;
+        }
+        egui::CollapsingHeader::new(
+            RichText::new(format!("⚠ {} DROPPED FRAMES", self.total()))
+                .size(8.0)
+                .color(Color32::ORANGE),
+        )
+        .id_salt(format!("capture_errors_{}", idx))
+        .show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (kind, count) in &self.counts {
+                    ui.label(
+                        RichText::new(format!("{}: {}", kind, count))
+                            .size(8.0)
+                            .color(Color32::LIGHT_GRAY),
+                    );
+                }
+            });
+            egui::ScrollArea::vertical()
+                .id_salt(format!("capture_error_log_{}", idx))
+                .max_height(80.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for entry in &self.entries {
+                        ui.label(
+                            RichText::new(format!(
+                                "{} [{}] {}",
+                                entry.at.format("%H:%M:%S"),
+                                entry.kind,
+                                entry.message
+                            ))
+                            .size(8.0)
+                            .family(egui::FontFamily::Monospace)
+                            .color(Color32::LIGHT_RED),
+                        );
+                    }
+                });
+            if ui.small_button("Clear").clicked() {
+                self.clear();
+            }
+        });
+    }
+}
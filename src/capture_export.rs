@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use fleascope_rs::{AnalogTriggerBehavior, DigitalTriggerBehavior, ProbeType};
+use polars::prelude::{Column, CsvWriter, DataFrame, KeyValueMetadata, ParquetWriter, SerWriter};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::cli;
+use crate::device::{DataPoint, DeviceData, TriggerConfig, TriggerSource, WaveformConfig};
+use crate::worker_interface::FleaScopeDevice;
+
+/// Default directory for exported captures, relative to the working directory
+pub const CAPTURE_DIR: &str = "captures";
+
+// Column names of exported captures
+pub const TIME_COLUMN: &str = "time";
+pub const ANALOG_COLUMN: &str = "analog";
+pub const DIGITAL_COLUMNS: [&str; 9] = ["d0", "d1", "d2", "d3", "d4", "d5", "d6", "d7", "d8"];
+
+/// Prefix of the metadata lines at the top of exported CSV files
+pub const CSV_COMMENT_PREFIX: &str = "# ";
+
+/// Acquisition settings stored alongside an exported capture
+pub struct CaptureMetadata {
+    pub device: String,
+    pub probe: ProbeType,
+    pub time_frame: f64,
+    pub trigger: String,
+    pub waveform: String,
+    pub captured_at: DateTime<Utc>,
+}
+
+impl CaptureMetadata {
+    /// Metadata of a triggered capture, from the settings it was taken with
+    pub fn from_capture(device: &str, data: &DeviceData) -> Result<Self> {
+        let settings = data
+            .settings
+            .as_ref()
+            .with_context(|| format!("No capture available for {}", device))?;
+        Ok(Self {
+            device: device.to_string(),
+            probe: settings.probe,
+            time_frame: settings.time_frame,
+            trigger: describe_trigger(&settings.trigger),
+            waveform: describe_waveform(&settings.waveform),
+            captured_at: settings.captured_at,
+        })
+    }
+
+    pub fn entries(&self) -> Vec<(String, String)> {
+        vec![
+            ("device".to_string(), self.device.clone()),
+            (
+                "probe".to_string(),
+                format!("x{}", self.probe.to_multiplier()),
+            ),
+            ("time_frame_s".to_string(), self.time_frame.to_string()),
+            ("trigger".to_string(), self.trigger.clone()),
+            ("waveform".to_string(), self.waveform.clone()),
+            ("captured_at".to_string(), self.captured_at.to_rfc3339()),
+        ]
+    }
+}
+
+pub fn describe_trigger(trigger: &TriggerConfig) -> String {
+    match trigger.source {
+        TriggerSource::Analog => {
+            let slope = match trigger.analog.behavior {
+                AnalogTriggerBehavior::Auto => "auto",
+                AnalogTriggerBehavior::Level => "level",
+                AnalogTriggerBehavior::Rising => "rising",
+                AnalogTriggerBehavior::Falling => "falling",
+            };
+            format!("analog {} {:.3}V", slope, trigger.analog.volts)
+        }
+        TriggerSource::Digital => {
+            let mode = match trigger.digital.behavior {
+                DigitalTriggerBehavior::Auto => "auto",
+                DigitalTriggerBehavior::While => "while",
+                DigitalTriggerBehavior::Start => "start",
+                DigitalTriggerBehavior::Stop => "stop",
+            };
+            // D0 first, like the pattern buttons in the rack
+            let pattern = cli::format_pattern(&trigger.digital.bit_states);
+            format!("digital {} {}", mode, pattern)
+        }
+    }
+}
+
+pub fn describe_waveform(waveform: &WaveformConfig) -> String {
+    if waveform.enabled {
+        format!(
+            "{} {}Hz",
+            waveform.waveform_type.as_str(),
+            waveform.frequency_hz
+        )
+    } else {
+        "off".to_string()
+    }
+}
+
+/// One row per sample with time, analog voltage and the nine digital channels
+pub fn capture_to_dataframe(data: &DeviceData) -> Result<DataFrame> {
+    samples_to_dataframe(&data.x_values, &data.data_points)
+}
+
+pub fn samples_to_dataframe(x_values: &[f64], data_points: &[DataPoint]) -> Result<DataFrame> {
+    let mut columns = vec![
+        Column::new(TIME_COLUMN.into(), x_values),
+        Column::new(
+            ANALOG_COLUMN.into(),
+            data_points
+                .iter()
+                .map(|p| p.analog_channel)
+                .collect::<Vec<_>>(),
+        ),
+    ];
+    for (ch, name) in DIGITAL_COLUMNS.iter().enumerate() {
+        columns.push(Column::new(
+            (*name).into(),
+            data_points
+                .iter()
+                .map(|p| p.digital_channels[ch])
+                .collect::<Vec<_>>(),
+        ));
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Write CSV with the metadata as comment lines above the header
+pub fn write_csv(path: &Path, df: &mut DataFrame, metadata: &CaptureMetadata) -> Result<()> {
+    let mut file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    for (key, value) in metadata.entries() {
+        writeln!(file, "{}{}: {}", CSV_COMMENT_PREFIX, key, value)?;
+    }
+    CsvWriter::new(&mut file).include_header(true).finish(df)?;
+    Ok(())
+}
+
+/// Write Parquet with the metadata as key-value file metadata
+pub fn write_parquet(path: &Path, df: &mut DataFrame, metadata: &CaptureMetadata) -> Result<()> {
+    write_parquet_entries(path, df, metadata.entries())
+}
+
+/// Write Parquet with arbitrary key-value file metadata
+pub fn write_parquet_entries(
+    path: &Path,
+    df: &mut DataFrame,
+    entries: Vec<(String, String)>,
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    ParquetWriter::new(file)
+        .with_key_value_metadata(Some(KeyValueMetadata::from_static(entries)))
+        .finish(df)?;
+    Ok(())
+}
+
+/// File name stem that is safe on all platforms, e.g. `sim___sine-1k_20250101-120000-250`
+///
+/// Milliseconds keep captures saved in quick succession apart.
+pub fn file_stem(device: &str, timestamp: DateTime<Utc>) -> String {
+    format!(
+        "{}_{}",
+        safe_file_name(device),
+        timestamp.format("%Y%m%d-%H%M%S-%3f")
+    )
+}
+
+/// `name` with everything but ASCII letters, digits and dashes replaced by underscores
+pub fn safe_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Save the latest triggered capture of `device` as CSV and Parquet into `dir`
+pub fn save_capture(device: &FleaScopeDevice, dir: &Path) -> Result<Vec<PathBuf>> {
+    let data = device.data.load();
+    if data.data_points.is_empty() {
+        anyhow::bail!("No capture available for {}", device.name);
+    }
+
+    let metadata = CaptureMetadata::from_capture(&device.name, &data)?;
+    let mut df = capture_to_dataframe(&data)?;
+
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let stem = file_stem(&device.name, metadata.captured_at);
+    let csv_path = dir.join(format!("{}.csv", stem));
+    let parquet_path = dir.join(format!("{}.parquet", stem));
+
+    write_csv(&csv_path, &mut df, &metadata)?;
+    write_parquet(&parquet_path, &mut df, &metadata)?;
+    tracing::info!(
+        "Saved capture of {} to {} and {}",
+        device.name,
+        csv_path.display(),
+        parquet_path.display()
+    );
+    Ok(vec![csv_path, parquet_path])
+}
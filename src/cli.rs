@@ -0,0 +1,475 @@
+use anyhow::{Context, Result};
+use fleascope_rs::{
+    AnalogTrigger, AnalogTriggerBehavior, BitState, DigitalTrigger, DigitalTriggerBehavior,
+    ProbeType,
+};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::capture_export::{self, CaptureMetadata, CAPTURE_DIR};
+use crate::device::{
+    CaptureConfig, CaptureMode, DeviceManager, Notification, TriggerConfig, TriggerSource,
+    MAX_TIME_FRAME, MIN_TIME_FRAME,
+};
+use crate::live_stream::StreamFrame;
+use crate::remote;
+
+pub const USAGE: &str = "\
+Usage: fleascope-monitor [--remote [ADDR:]PORT] [--stream [ADDR:]PORT] [COMMAND]
+
+Without a command the oscilloscope window opens.
+  --remote [ADDR:]PORT   Accept SCPI-style remote control in the window, e.g. --remote 5025
+                         (binds to 127.0.0.1 unless an address is given)
+  --stream [ADDR:]PORT   Publish captures to WebSocket clients in the window, e.g. --stream 8765
+
+Commands:
+  list                   List connected FleaScopes
+  capture [OPTIONS]      Record triggered captures without opening a window
+
+Capture options:
+  --device NAME          Device name or sim:// hostname (required)
+  --time-frame TIME      Capture window, e.g. 10ms or 1.5s [default: 100ms]
+  --trigger SPEC         analog:<auto|level|rising|falling>:<volts>, e.g. analog:rising:1.2V
+                         digital:<auto|while|start|stop>:<pattern>, e.g. digital:start:1X0
+                         (pattern from D0 on, 1 = high, 0 = low, X = don't care)
+                         [default: digital:while, fires right away]
+  --probe x1|x10         Probe multiplier [default: x1]
+  --count N              Number of captures [default: 1]
+  --timeout TIME         Give up if a capture takes longer [default: 10s]
+  --out PATH             .parquet or .csv file [default: captures/<device>_<timestamp>.parquet]
+";
+
+/// Column telling the captures of a multi-capture file apart
+pub const CAPTURE_INDEX_COLUMN: &str = "capture";
+
+/// How often `capture` checks for notifications while it waits for the trigger
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub enum Command {
+    Gui {
+        remote: Option<SocketAddr>,
+        stream: Option<SocketAddr>,
+    },
+    Help,
+    List,
+    Capture(CaptureArgs),
+}
+
+pub struct CaptureArgs {
+    pub device: String,
+    pub time_frame: f64,
+    pub trigger: TriggerConfig,
+    pub probe: ProbeType,
+    pub count: usize,
+    pub timeout: Duration,
+    pub out: Option<PathBuf>,
+}
+
+pub fn parse() -> Result<Command> {
+    let mut args = pico_args::Arguments::from_env();
+    if args.contains(["-h", "--help"]) {
+        return Ok(Command::Help);
+    }
+    let remote = args.opt_value_from_fn("--remote", remote::parse_address)?;
+    let stream = args.opt_value_from_fn("--stream", remote::parse_address)?;
+    let command = match args.subcommand()?.as_deref() {
+        None => Command::Gui { remote, stream },
+        Some(_) if remote.is_some() => anyhow::bail!("--remote only applies to the window"),
+        Some(_) if stream.is_some() => anyhow::bail!("--stream only applies to the window"),
+        Some("help") => Command::Help,
+        Some("list") => Command::List,
+        Some("capture") => Command::Capture(CaptureArgs {
+            device: args.value_from_str("--device")?,
+            time_frame: args
+                .opt_value_from_fn("--time-frame", parse_seconds)?
+                .unwrap_or(0.1),
+            trigger: args
+                .opt_value_from_fn("--trigger", parse_trigger)?
+                .unwrap_or_default(),
+            probe: args
+                .opt_value_from_fn("--probe", parse_probe)?
+                .unwrap_or(ProbeType::X1),
+            count: args.opt_value_from_str("--count")?.unwrap_or(1),
+            timeout: Duration::from_secs_f64(
+                args.opt_value_from_fn("--timeout", parse_seconds)?
+                    .unwrap_or(10.0),
+            ),
+            out: args.opt_value_from_str("--out")?,
+        }),
+        Some(other) => anyhow::bail!("Unknown command '{}'", other),
+    };
+
+    let unused = args.finish();
+    if !unused.is_empty() {
+        anyhow::bail!("Unexpected arguments {:?}", unused);
+    }
+    if let Command::Capture(capture) = &command {
+        if !(MIN_TIME_FRAME..=MAX_TIME_FRAME).contains(&capture.time_frame) {
+            anyhow::bail!(
+                "Time frame must be between {}s and {}s",
+                MIN_TIME_FRAME,
+                MAX_TIME_FRAME
+            );
+        }
+    }
+    Ok(command)
+}
+
+/// Run a command line command, everything but [`Command::Gui`]
+pub async fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Gui { .. } => unreachable!("the window is opened by main"),
+        Command::Help => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Command::List => list(),
+        Command::Capture(args) => capture(args).await,
+    }
+}
+
+fn list() -> Result<()> {
+    let devices = fleascope_rs::FleaConnector::get_available_devices(None)
+        .context("Failed to list devices")?;
+    for device in devices {
+        println!("{}\t{}", device.name, device.port);
+    }
+    Ok(())
+}
+
+async fn capture(args: CaptureArgs) -> Result<()> {
+    let mut manager = DeviceManager::default();
+    manager
+        .add_device_with_config(
+            args.device.clone(),
+            CaptureConfig {
+                probe_multiplier: args.probe,
+                mode: CaptureMode::Triggered {
+                    trigger_config: args.trigger.clone(),
+                    time_frame: args.time_frame,
+                },
+            },
+        )
+        .with_context(|| format!("Failed to connect to {}", args.device))?;
+    let result = record(&mut manager, &args).await;
+    manager.remove_device(0);
+    let (path, captures) = result?;
+    println!("Saved {} captures to {}", captures, path.display());
+    Ok(())
+}
+
+/// Collect `args.count` captures into one file, numbered in [`CAPTURE_INDEX_COLUMN`]
+///
+/// Captures are taken from the device's stream, so each one is recorded exactly once.
+async fn record(manager: &mut DeviceManager, args: &CaptureArgs) -> Result<(PathBuf, usize)> {
+    let device = &mut manager.get_devices_mut()[0];
+    let mut captures = device.subscribe_stream();
+    let mut frames = None;
+    let mut waiting_since = Instant::now();
+    let mut captured = 0;
+    let mut last_error = None;
+
+    while captured < args.count {
+        while let Ok(notification) = device.notification_rx.try_recv() {
+            match notification {
+                Notification::Success(message) => tracing::info!("{}", message),
+                Notification::Error(message) => {
+                    tracing::warn!("{}", message);
+                    last_error = Some(message);
+                }
+            }
+        }
+        while let Ok(error) = device.error_rx.try_recv() {
+            tracing::warn!("Dropped frame: {}", error);
+        }
+
+        if !device.data.load().running {
+            // The worker pauses itself on settings the device rejects
+            anyhow::bail!(
+                "Acquisition stopped: {}",
+                last_error.as_deref().unwrap_or("unknown reason")
+            );
+        }
+        // Wake up now and then to pick up notifications while waiting for the trigger
+        let data = match tokio::time::timeout(POLL_INTERVAL, captures.recv()).await {
+            Ok(Ok(StreamFrame::Capture(data))) if !data.data_points.is_empty() => data,
+            Ok(Ok(_)) => continue,
+            Ok(Err(RecvError::Lagged(skipped))) => {
+                tracing::warn!("Fell behind, {} captures were not recorded", skipped);
+                continue;
+            }
+            Ok(Err(RecvError::Closed)) => anyhow::bail!("{} stopped capturing", args.device),
+            Err(_) => {
+                if waiting_since.elapsed() > args.timeout + Duration::from_secs_f64(args.time_frame)
+                {
+                    anyhow::bail!(
+                        "No capture from {} within {:?} ({} of {} done)",
+                        args.device,
+                        args.timeout,
+                        captured,
+                        args.count
+                    );
+                }
+                continue;
+            }
+        };
+        waiting_since = Instant::now();
+
+        let mut df = capture_export::capture_to_dataframe(&data)?;
+        df.with_column(polars::prelude::Column::new(
+            CAPTURE_INDEX_COLUMN.into(),
+            vec![captured as u32; df.height()],
+        ))?;
+        match &mut frames {
+            None => frames = Some((df, data)),
+            Some((all, _)) => {
+                all.vstack_mut(&df)?;
+            }
+        }
+        captured += 1;
+        tracing::info!("Captured {}/{}", captured, args.count);
+    }
+
+    let Some((mut df, first)) = frames else {
+        anyhow::bail!("Nothing to save, --count is 0");
+    };
+    let metadata = CaptureMetadata::from_capture(&device.name, &first)?;
+    let path = match &args.out {
+        Some(path) => path.clone(),
+        None => {
+            std::fs::create_dir_all(CAPTURE_DIR)
+                .with_context(|| format!("Failed to create {}", CAPTURE_DIR))?;
+            PathBuf::from(CAPTURE_DIR).join(format!(
+                "{}.parquet",
+                capture_export::file_stem(&args.device, metadata.captured_at)
+            ))
+        }
+    };
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+    {
+        capture_export::write_csv(&path, &mut df, &metadata)?;
+    } else {
+        capture_export::write_parquet(&path, &mut df, &metadata)?;
+    }
+    Ok((path, captured))
+}
+
+/// Seconds from `10ms`, `250us`, `1.5s` or a plain number of seconds
+pub fn parse_seconds(value: &str) -> Result<f64> {
+    let (number, factor) = [
+        ("ns", 1e-9),
+        ("us", 1e-6),
+        ("µs", 1e-6),
+        ("ms", 1e-3),
+        ("s", 1.0),
+    ]
+    .iter()
+    .find_map(|(suffix, factor)| value.strip_suffix(suffix).map(|n| (n, *factor)))
+    .unwrap_or((value, 1.0));
+    let seconds = number
+        .trim()
+        .parse::<f64>()
+        .with_context(|| format!("Invalid time '{}'", value))?
+        * factor;
+    if !(seconds.is_finite() && seconds > 0.0) {
+        anyhow::bail!("Time must be positive, got '{}'", value);
+    }
+    Ok(seconds)
+}
+
+pub fn parse_probe(value: &str) -> Result<ProbeType> {
+    match value.to_ascii_lowercase().as_str() {
+        "x1" | "1" => Ok(ProbeType::X1),
+        "x10" | "10" => Ok(ProbeType::X10),
+        _ => anyhow::bail!("Unknown probe '{}', expected x1 or x10", value),
+    }
+}
+
+/// Trigger from `analog:rising:1.2V` or `digital:start:1X0`, see [`USAGE`]
+pub fn parse_trigger(value: &str) -> Result<TriggerConfig> {
+    let value = value.to_ascii_lowercase();
+    let mut parts = value.split(':');
+    let source = parts.next().unwrap_or_default();
+    let mode = parts.next().unwrap_or("auto");
+    let argument = parts.next();
+    if parts.next().is_some() {
+        anyhow::bail!("Too many fields in trigger '{}'", value);
+    }
+
+    let mut trigger = TriggerConfig::default();
+    match source {
+        "analog" => {
+            let volts = match argument {
+                Some(volts) => parse_volts(volts)?,
+                None => 0.0,
+            };
+            let mut analog = AnalogTrigger::start_capturing_when(volts);
+            analog.behavior = parse_analog_behavior(mode)?;
+            trigger.source = TriggerSource::Analog;
+            trigger.analog = analog;
+        }
+        "digital" => {
+            let bit_states = parse_pattern(argument.unwrap_or_default())?;
+            let behavior = match mode {
+                "auto" => DigitalTriggerBehavior::Auto,
+                "while" => DigitalTriggerBehavior::While,
+                "start" => DigitalTriggerBehavior::Start,
+                "stop" => DigitalTriggerBehavior::Stop,
+                _ => anyhow::bail!("Unknown digital trigger mode '{}'", mode),
+            };
+            trigger.source = TriggerSource::Digital;
+            trigger.digital = DigitalTrigger::new(bit_states, behavior);
+        }
+        _ => anyhow::bail!(
+            "Unknown trigger source '{}', expected analog or digital",
+            source
+        ),
+    }
+    Ok(trigger)
+}
+
+pub fn parse_volts(value: &str) -> Result<f64> {
+    value
+        .trim()
+        .trim_end_matches(['V', 'v'])
+        .parse::<f64>()
+        .with_context(|| format!("Invalid voltage '{}'", value))
+}
+
+pub fn parse_analog_behavior(value: &str) -> Result<AnalogTriggerBehavior> {
+    match value.to_ascii_lowercase().as_str() {
+        "auto" => Ok(AnalogTriggerBehavior::Auto),
+        "level" => Ok(AnalogTriggerBehavior::Level),
+        "rising" => Ok(AnalogTriggerBehavior::Rising),
+        "falling" => Ok(AnalogTriggerBehavior::Falling),
+        _ => anyhow::bail!("Unknown analog trigger mode '{}'", value),
+    }
+}
+
+/// Digital trigger pattern from D0 on, missing bits are don't care
+pub fn parse_pattern(pattern: &str) -> Result<[BitState; 9]> {
+    if pattern.chars().count() > 9 {
+        anyhow::bail!("Digital trigger pattern '{}' has more than 9 bits", pattern);
+    }
+    let mut bit_states = [BitState::DontCare; 9];
+    for (state, c) in bit_states.iter_mut().zip(pattern.chars()) {
+        *state = match c {
+            '1' | 'H' | 'h' => BitState::High,
+            '0' | 'L' | 'l' => BitState::Low,
+            'X' | 'x' | '-' => BitState::DontCare,
+            _ => anyhow::bail!("Invalid bit '{}' in pattern '{}'", c, pattern),
+        };
+    }
+    Ok(bit_states)
+}
+
+/// Inverse of [`parse_pattern`], all nine bits from D0 on
+pub fn format_pattern(bit_states: &[BitState; 9]) -> String {
+    bit_states
+        .iter()
+        .map(|state| match state {
+            BitState::High => '1',
+            BitState::Low => '0',
+            BitState::DontCare => 'X',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= b.abs() * 1e-12
+    }
+
+    #[test]
+    fn parses_seconds_with_units() {
+        for (text, seconds) in [
+            ("2", 2.0),
+            ("1.5s", 1.5),
+            ("250ms", 0.25),
+            ("40us", 40e-6),
+            ("40µs", 40e-6),
+            ("100ns", 100e-9),
+            ("1e-3s", 1e-3),
+            ("0.5 ms", 0.5e-3),
+        ] {
+            assert!(close(parse_seconds(text).unwrap(), seconds), "{}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_seconds() {
+        for text in ["", "ms", "fast", "0", "-1s", "inf", "NaN", "1h"] {
+            assert!(parse_seconds(text).is_err(), "{}", text);
+        }
+        let error = format!("{:#}", parse_seconds("-5ms").unwrap_err());
+        assert_eq!(error, "Time must be positive, got '-5ms'");
+    }
+
+    #[test]
+    fn parses_patterns_from_d0() {
+        use BitState::{DontCare as X, High as H, Low as L};
+        assert_eq!(parse_pattern("").unwrap(), [X; 9]);
+        assert_eq!(parse_pattern("1X0").unwrap(), [H, X, L, X, X, X, X, X, X]);
+        assert_eq!(
+            parse_pattern("hL-x10lH1").unwrap(),
+            [H, L, X, X, H, L, L, H, H]
+        );
+        assert_eq!(
+            format_pattern(&parse_pattern("hL-x1").unwrap()),
+            "10XX1XXXX"
+        );
+        let error = format!("{:#}", parse_pattern("1012").unwrap_err());
+        assert_eq!(error, "Invalid bit '2' in pattern '1012'");
+        assert!(parse_pattern("1010101010").is_err());
+    }
+
+    #[test]
+    fn parses_analog_triggers() {
+        let trigger = parse_trigger("analog:rising:1.2V").unwrap();
+        assert_eq!(trigger.source, TriggerSource::Analog);
+        assert_eq!(trigger.analog.behavior, AnalogTriggerBehavior::Rising);
+        assert_eq!(trigger.analog.volts, 1.2);
+
+        let trigger = parse_trigger("Analog:Falling:-0.5").unwrap();
+        assert_eq!(trigger.analog.behavior, AnalogTriggerBehavior::Falling);
+        assert_eq!(trigger.analog.volts, -0.5);
+
+        let trigger = parse_trigger("analog").unwrap();
+        assert_eq!(trigger.analog.behavior, AnalogTriggerBehavior::Auto);
+        assert_eq!(trigger.analog.volts, 0.0);
+    }
+
+    #[test]
+    fn parses_digital_triggers() {
+        let trigger = parse_trigger("digital:start:1X0").unwrap();
+        assert_eq!(trigger.source, TriggerSource::Digital);
+        assert_eq!(trigger.digital.behavior, DigitalTriggerBehavior::Start);
+        assert_eq!(trigger.digital.bit_states, parse_pattern("1x0").unwrap());
+
+        let trigger = parse_trigger("digital:while").unwrap();
+        assert_eq!(trigger.digital.behavior, DigitalTriggerBehavior::While);
+        assert_eq!(trigger.digital.bit_states, [BitState::DontCare; 9]);
+    }
+
+    #[test]
+    fn rejects_invalid_triggers() {
+        for text in [
+            "",
+            "edge:rising",
+            "analog:sideways:1V",
+            "analog:rising:high",
+            "analog:rising:1V:extra",
+            "digital:rising:1",
+            "digital:start:102",
+        ] {
+            assert!(parse_trigger(text).is_err(), "{}", text);
+        }
+    }
+}
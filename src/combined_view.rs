@@ -0,0 +1,192 @@
+use egui::{Color32, RichText};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Height of a digital trace in the combined plot, which shares the voltage axis
+const DIGITAL_TRACE_HEIGHT: f64 = 1.0;
+
+/// Vertical distance between digital traces placed below the analog ones
+const DIGITAL_TRACE_SPACING: f64 = 1.2;
+
+/// Signal of a device drawn in the combined view
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CombinedChannel {
+    Analog,
+    Digital(usize),
+}
+
+impl CombinedChannel {
+    const ALL: [CombinedChannel; 10] = [
+        CombinedChannel::Analog,
+        CombinedChannel::Digital(0),
+        CombinedChannel::Digital(1),
+        CombinedChannel::Digital(2),
+        CombinedChannel::Digital(3),
+        CombinedChannel::Digital(4),
+        CombinedChannel::Digital(5),
+        CombinedChannel::Digital(6),
+        CombinedChannel::Digital(7),
+        CombinedChannel::Digital(8),
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            CombinedChannel::Analog => "A".to_string(),
+            CombinedChannel::Digital(n) => format!("D{}", n),
+        }
+    }
+}
+
+/// One channel of one device in the combined view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CombinedTrace {
+    pub device: String,
+    pub channel: CombinedChannel,
+    /// Added to the values, keeps digital traces apart from each other and the analog ones
+    pub offset: f64,
+    pub color: [u8; 3],
+}
+
+impl CombinedTrace {
+    pub fn name(&self) -> String {
+        format!("{} {}", self.device, self.channel.label())
+    }
+
+    pub fn color32(&self) -> Color32 {
+        let [r, g, b] = self.color;
+        Color32::from_rgb(r, g, b)
+    }
+
+    /// Shift samples of this trace's device onto the shared axis
+    ///
+    /// Digital values are 0 or 1 and get scaled to the trace height.
+    pub fn place(&self, skew: f64, points: impl IntoIterator<Item = [f64; 2]>) -> Vec<[f64; 2]> {
+        let scale = match self.channel {
+            CombinedChannel::Analog => 1.0,
+            CombinedChannel::Digital(_) => DIGITAL_TRACE_HEIGHT,
+        };
+        points
+            .into_iter()
+            .map(|[t, value]| [t + skew, value * scale + self.offset])
+            .collect()
+    }
+}
+
+/// Channels of several devices drawn on one plot with a shared time axis.
+///
+/// Triggered captures share the trigger instant as time zero, continuous streams the latest
+/// sample. Each device can be shifted by a skew to line up devices whose clocks or cabling differ.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CombinedView {
+    pub enabled: bool,
+    pub traces: Vec<CombinedTrace>,
+    /// Seconds added to the time axis of a device, by device name
+    pub skew: HashMap<String, f64>,
+}
+
+impl CombinedView {
+    pub fn skew(&self, device: &str) -> f64 {
+        self.skew.get(device).copied().unwrap_or(0.0)
+    }
+
+    /// Trace list and per-device skew editor
+    pub fn ui(&mut self, ui: &mut egui::Ui, devices: &[String], palette: &[Color32]) {
+        let mut remove = None;
+        let mut retyped = None;
+        for (idx, trace) in self.traces.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut trace.color);
+                egui::ComboBox::from_id_salt(format!("combined_device_{}", idx))
+                    .selected_text(&trace.device)
+                    .show_ui(ui, |ui| {
+                        for device in devices {
+                            ui.selectable_value(&mut trace.device, device.clone(), device);
+                        }
+                    });
+                egui::ComboBox::from_id_salt(format!("combined_channel_{}", idx))
+                    .selected_text(trace.channel.label())
+                    .width(50.0)
+                    .show_ui(ui, |ui| {
+                        for channel in CombinedChannel::ALL {
+                            if ui
+                                .selectable_value(&mut trace.channel, channel, channel.label())
+                                .changed()
+                            {
+                                retyped = Some(idx);
+                            }
+                        }
+                    });
+                ui.label("Offset:");
+                ui.add(
+                    egui::DragValue::new(&mut trace.offset)
+                        .speed(0.05)
+                        .suffix("V"),
+                );
+                if ui.button("✖").on_hover_text("Remove").clicked() {
+                    remove = Some(idx);
+                }
+            });
+        }
+        if let Some(idx) = retyped {
+            self.place_below(idx);
+        }
+        if let Some(idx) = remove {
+            self.traces.remove(idx);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("＋ Add trace").clicked() {
+                self.add_trace(devices, palette);
+            }
+            if !self.traces.is_empty() {
+                ui.separator();
+                ui.label(RichText::new("Skew").strong())
+                    .on_hover_text("Shift a device's time axis to line it up with the others");
+            }
+            for device in devices {
+                if !self.traces.iter().any(|trace| &trace.device == device) {
+                    continue;
+                }
+                ui.label(device);
+                let skew = self.skew.entry(device.clone()).or_insert(0.0);
+                let mut micros = *skew * 1e6;
+                let response = ui.add(egui::DragValue::new(&mut micros).speed(1.0).suffix("µs"));
+                if response.changed() {
+                    *skew = micros * 1e-6;
+                }
+            }
+        });
+    }
+
+    /// Give a trace that became digital its own lane below zero, analog traces go back to zero
+    fn place_below(&mut self, idx: usize) {
+        let lane = self.traces[..idx]
+            .iter()
+            .filter(|trace| matches!(trace.channel, CombinedChannel::Digital(_)))
+            .count();
+        let trace = &mut self.traces[idx];
+        trace.offset = match trace.channel {
+            CombinedChannel::Analog => 0.0,
+            CombinedChannel::Digital(_) => -DIGITAL_TRACE_SPACING * (lane + 1) as f64,
+        };
+    }
+
+    /// Start with the analog channel of the first device not shown yet
+    fn add_trace(&mut self, devices: &[String], palette: &[Color32]) {
+        let Some(first) = devices.first() else {
+            return;
+        };
+        let device = devices
+            .iter()
+            .find(|device| !self.traces.iter().any(|trace| &trace.device == *device))
+            .unwrap_or(first);
+        let color = palette[self.traces.len() % palette.len()];
+        self.traces.push(CombinedTrace {
+            device: device.clone(),
+            channel: CombinedChannel::Analog,
+            offset: 0.0,
+            color: [color.r(), color.g(), color.b()],
+        });
+    }
+}
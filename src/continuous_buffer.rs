@@ -0,0 +1,355 @@
+/// Longest `buffer_time` the continuous view can show
+pub const MAX_BUFFER_TIME: f64 = 10.0;
+
+/// Samples combined into one bucket of the next coarser mipmap level, as a power of two
+const LEVEL_SHIFT: u32 = 2;
+
+/// Mask of the nine digital channels in a bitmap
+const DIGITAL_MASK: u16 = 0x1ff;
+
+/// Samples of one continuous read
+#[derive(Clone)]
+pub struct ContinuousBatch {
+    /// Calibrated volts
+    pub analog: Vec<f64>,
+    /// Digital channel states, bit `n` is D`n`; `None` if the stream only carries the ADC
+    pub bitmap: Option<Vec<u16>>,
+}
+
+/// Continuous samples reduced to one bin per plot column
+#[derive(Default)]
+pub struct BinnedWindow {
+    pub time: Vec<f64>,
+    pub mean: Vec<f64>,
+    pub min: Vec<f64>,
+    pub max: Vec<f64>,
+    /// Digital channels that were high at some point of each bin, empty without digital data
+    pub high: Vec<u16>,
+    /// Digital channels that were low at some point of each bin, empty without digital data
+    pub low: Vec<u16>,
+}
+
+impl BinnedWindow {
+    /// Time, min and max of every bin, the outline of the band the samples spread over
+    pub fn envelope(&self) -> Vec<[f64; 3]> {
+        self.time
+            .iter()
+            .zip(self.min.iter().zip(&self.max))
+            .map(|(&t, (&lo, &hi))| [t, lo, hi])
+            .collect()
+    }
+
+    /// Level of digital channel `channel` per bin, bins in which it toggled become vertical edges
+    ///
+    /// The edge leaves the level of the previous bin, so even a single-sample pulse shows up.
+    pub fn digital_lane(&self, channel: usize) -> Vec<[f64; 2]> {
+        let mut level = 0.0;
+        let mut points = Vec::with_capacity(self.high.len());
+        for (&t, (&high, &low)) in self.time.iter().zip(self.high.iter().zip(&self.low)) {
+            let (high, low) = ((high >> channel) & 1 == 1, (low >> channel) & 1 == 1);
+            if high && low {
+                points.push([t, level]);
+                level = 1.0 - level;
+            } else {
+                level = if high { 1.0 } else { 0.0 };
+            }
+            points.push([t, level]);
+        }
+        points
+    }
+}
+
+/// Min, max and sum of a run of samples, and which digital channels were high or low in it
+#[derive(Clone, Copy)]
+struct Bucket {
+    min: f64,
+    max: f64,
+    sum: f64,
+    high: u16,
+    low: u16,
+}
+
+impl Bucket {
+    const EMPTY: Bucket = Bucket {
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+        sum: 0.0,
+        high: 0,
+        low: 0,
+    };
+
+    fn sample(value: f64, bitmap: u16) -> Self {
+        Bucket {
+            min: value,
+            max: value,
+            sum: value,
+            high: bitmap & DIGITAL_MASK,
+            low: !bitmap & DIGITAL_MASK,
+        }
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.high |= other.high;
+        self.low |= other.low;
+    }
+}
+
+/// The last `MAX_BUFFER_TIME` of continuous samples with a min/max/sum pyramid over them
+///
+/// Level `k` of the pyramid holds one bucket per `4^k` samples, aligned to the sample count,
+/// so any range is covered by a handful of buckets per level and binning a window for the
+/// plot costs about the number of columns instead of the number of samples.
+pub struct ContinuousBuffer {
+    sample_rate_hz: u32,
+    /// Ring of raw samples, sample `i` lives at `i % capacity`
+    samples: Vec<f64>,
+    /// Ring of digital bitmaps next to `samples`
+    bitmaps: Vec<u16>,
+    /// Whether the latest batch carried digital channels
+    has_digital: bool,
+    /// Ring of buckets per level, starting with `4` samples per bucket
+    levels: Vec<Vec<Bucket>>,
+    /// Samples received since the start, the time of sample `i` is `i / sample_rate_hz`
+    written: u64,
+}
+
+impl ContinuousBuffer {
+    pub fn new(sample_rate_hz: u32, buffer_time: f64) -> Self {
+        profiling::scope!("ContinuousBuffer::new");
+
+        let capacity = ((buffer_time * sample_rate_hz as f64).ceil() as usize).max(1);
+        // Down to a few buckets for the whole buffer, two spare ones for the partial ends
+        let levels = (1..)
+            .map(|level| capacity >> (level * LEVEL_SHIFT))
+            .take_while(|&buckets| buckets > 0)
+            .map(|buckets| vec![Bucket::EMPTY; buckets + 2])
+            .collect();
+        Self {
+            sample_rate_hz,
+            samples: vec![0.0; capacity],
+            bitmaps: vec![0; capacity],
+            has_digital: false,
+            levels,
+            written: 0,
+        }
+    }
+
+    pub fn add_batch(&mut self, batch: ContinuousBatch) {
+        profiling::scope!("ContinuousBuffer::add_batch");
+
+        self.has_digital = batch.bitmap.is_some();
+        let bitmaps = batch
+            .bitmap
+            .into_iter()
+            .flatten()
+            .chain(std::iter::repeat(0));
+        let capacity = self.samples.len() as u64;
+        for (value, bitmap) in batch.analog.into_iter().zip(bitmaps) {
+            let index = self.written;
+            let position = (index % capacity) as usize;
+            self.samples[position] = value;
+            self.bitmaps[position] = bitmap;
+            let sample = Bucket::sample(value, bitmap);
+            for (level, buckets) in self.levels.iter_mut().enumerate() {
+                let shift = (level as u32 + 1) * LEVEL_SHIFT;
+                let slot = ((index >> shift) % buckets.len() as u64) as usize;
+                // The first sample of a bucket replaces whatever the ring held there before
+                if index & ((1 << shift) - 1) == 0 {
+                    buckets[slot] = sample;
+                } else {
+                    buckets[slot].merge(&sample);
+                }
+            }
+            self.written += 1;
+        }
+    }
+
+    /// Whether the latest batch carried digital channels
+    pub fn has_digital(&self) -> bool {
+        self.has_digital
+    }
+
+    /// Time of the sample after the latest one, where the next batch starts
+    pub fn latest_time(&self) -> f64 {
+        self.written as f64 / self.sample_rate_hz as f64
+    }
+
+    /// Index of the oldest sample still held
+    fn oldest(&self) -> u64 {
+        self.written.saturating_sub(self.samples.len() as u64)
+    }
+
+    /// Index of the first sample of the last `window_duration` seconds
+    fn window_start(&self, window_duration: f64) -> u64 {
+        let window_samples = (window_duration * self.sample_rate_hz as f64).max(0.0) as u64;
+        self.written
+            .saturating_sub(window_samples)
+            .max(self.oldest())
+    }
+
+    /// Min, max and sum of samples `start..end`, which must still be held
+    fn aggregate(&self, mut start: u64, end: u64) -> Bucket {
+        let capacity = self.samples.len() as u64;
+        let mut total = Bucket::EMPTY;
+        while start < end {
+            // Largest bucket that starts at `start` and ends within the range
+            let aligned = start.trailing_zeros() / LEVEL_SHIFT;
+            let mut level = aligned.min(self.levels.len() as u32);
+            while level > 0 && start + (1 << (level * LEVEL_SHIFT)) > end {
+                level -= 1;
+            }
+            if level == 0 {
+                let slot = (start % capacity) as usize;
+                total.merge(&Bucket::sample(self.samples[slot], self.bitmaps[slot]));
+                start += 1;
+            } else {
+                let buckets = &self.levels[level as usize - 1];
+                let shift = level * LEVEL_SHIFT;
+                total.merge(&buckets[((start >> shift) % buckets.len() as u64) as usize]);
+                start += 1 << shift;
+            }
+        }
+        total
+    }
+
+    pub fn get_data_in_window(
+        &self,
+        window_duration: f64,
+        wrap: bool,
+        plot_width: u32,
+    ) -> BinnedWindow {
+        profiling::scope!("ContinuousBuffer::get_data_in_window");
+
+        let start = self.window_start(window_duration);
+        if start >= self.written || plot_width == 0 {
+            return BinnedWindow::default();
+        }
+
+        // Bins are aligned to absolute time, so they do not shimmer while the plot scrolls
+        let rate = self.sample_rate_hz as f64;
+        let bin_samples = window_duration / plot_width as f64 * rate;
+        let bin_start = |bin: u64| (bin as f64 * bin_samples).ceil() as u64;
+        let first_bin = (start as f64 / bin_samples) as u64;
+        let last_bin = ((self.written - 1) as f64 / bin_samples) as u64;
+
+        let mut bins: Vec<(f64, Bucket, u64)> = Vec::with_capacity(plot_width as usize + 2);
+        // The first and last bin are only partly filled and would show a false dip
+        for bin in first_bin + 1..last_bin {
+            let (from, to) = (
+                bin_start(bin).max(start),
+                bin_start(bin + 1).min(self.written),
+            );
+            if from >= to {
+                continue;
+            }
+            let bucket = self.aggregate(from, to);
+            let mut time = from as f64 / rate;
+            if wrap {
+                time %= window_duration;
+            }
+            bins.push((time, bucket, to - from));
+        }
+        if wrap {
+            bins.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+
+        let mut window = BinnedWindow::default();
+        for (time, bucket, samples) in bins {
+            window.time.push(time);
+            window.mean.push(bucket.sum / samples as f64);
+            window.min.push(bucket.min);
+            window.max.push(bucket.max);
+            if self.has_digital {
+                window.high.push(bucket.high);
+                window.low.push(bucket.low);
+            }
+        }
+        window
+    }
+
+    /// All samples of the last `window_duration` seconds, without resampling
+    pub fn get_samples_in_window(&self, window_duration: f64) -> (Vec<f64>, Vec<f64>) {
+        profiling::scope!("ContinuousBuffer::get_samples_in_window");
+
+        let capacity = self.samples.len() as u64;
+        let rate = self.sample_rate_hz as f64;
+        (self.window_start(window_duration)..self.written)
+            .map(|i| (i as f64 / rate, self.samples[(i % capacity) as usize]))
+            .unzip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Xorshift, enough randomness for ranges and samples without pulling in a crate
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    #[test]
+    fn aggregate_matches_brute_force_across_the_wrap() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        // Not a power of four, so the ring and the pyramid levels wrap at different samples
+        let mut buffer = ContinuousBuffer::new(1000, 1.0);
+        let capacity = buffer.samples.len() as u64;
+        let mut history: Vec<(f64, u16)> = Vec::new();
+
+        while (history.len() as u64) < capacity * 5 {
+            let len = 1 + rng.below(300) as usize;
+            // Quarter volts keep the sums exact
+            let analog: Vec<f64> = (0..len)
+                .map(|_| (rng.below(4001) as f64 - 2000.0) / 4.0)
+                .collect();
+            let bitmap: Vec<u16> = (0..len).map(|_| rng.next() as u16).collect();
+            history.extend(analog.iter().copied().zip(bitmap.iter().copied()));
+            buffer.add_batch(ContinuousBatch {
+                analog,
+                bitmap: Some(bitmap),
+            });
+
+            let oldest = buffer.oldest();
+            for _ in 0..20 {
+                let start = oldest + rng.below(buffer.written - oldest);
+                let end = start + 1 + rng.below(buffer.written - start);
+                let bucket = buffer.aggregate(start, end);
+
+                let mut expected = Bucket::EMPTY;
+                for &(value, bitmap) in &history[start as usize..end as usize] {
+                    expected.merge(&Bucket::sample(value, bitmap));
+                }
+                let range = format!("{}..{} of {}", start, end, buffer.written);
+                assert_eq!(bucket.min, expected.min, "{}", range);
+                assert_eq!(bucket.max, expected.max, "{}", range);
+                assert_eq!(bucket.sum, expected.sum, "{}", range);
+                assert_eq!(bucket.high, expected.high, "{}", range);
+                assert_eq!(bucket.low, expected.low, "{}", range);
+            }
+        }
+
+        // The whole held window, which spans the ring's seam
+        let (start, end) = (buffer.oldest(), buffer.written);
+        assert_ne!(start % capacity, 0);
+        let bucket = buffer.aggregate(start, end);
+        let held = &history[start as usize..];
+        assert_eq!(
+            bucket.sum,
+            held.iter().map(|&(value, _)| value).sum::<f64>()
+        );
+    }
+}
@@ -1,7 +1,14 @@
+use crate::acquisition_mode::AcquisitionMode;
+use crate::capture_export::{self, CAPTURE_DIR};
+use crate::continuous_buffer::MAX_BUFFER_TIME;
 use crate::device::{
-    cycle_bitstate, waveform_to_icon, DeviceManager, Notification, MAX_TIME_FRAME, MIN_TIME_FRAME,
+    cycle_bitstate, waveform_to_icon, AcquisitionState, DeviceManager, Notification,
+    CONTINUOUS_SAMPLE_RATE_HZ, MAX_TIME_FRAME, MAX_TRIGGER_OFFSET, MAX_TRIGGER_POSITION,
+    MIN_TIME_FRAME,
 };
 use crate::notifications::NotificationManager;
+use crate::pre_trigger;
+use crate::recorder::{RecordingConfig, RECORDING_DIR};
 use crate::worker_interface::{CaptureModeFlat, FleaScopeDevice};
 use egui::{Color32, RichText};
 use fleascope_rs::{
@@ -11,8 +18,11 @@ use fleascope_rs::{
 #[derive(Default)]
 pub struct ControlPanel {
     available_devices: Vec<String>,
+    simulated_hostname: String,
 }
 
+const DEFAULT_SIMULATED_HOSTNAME: &str = "sim://sine-1k";
+
 /// Custom dial widget with optional label and value display
 fn dial_widget(
     ui: &mut egui::Ui,
@@ -200,7 +210,7 @@ fn exponential_dial_widget(
     response
 }
 
-fn pretty_print_number(value: f64, unit: Option<&str>, significant_digits: usize) -> String {
+pub fn pretty_print_number(value: f64, unit: Option<&str>, significant_digits: usize) -> String {
     if value == 0.0 {
         return format!("0{}", unit.unwrap_or(""));
     }
@@ -228,13 +238,13 @@ fn pretty_print_number(value: f64, unit: Option<&str>, significant_digits: usize
     // Calculate decimal places needed to show the requested significant digits
     let decimal_places = if abs_scaled >= 100.0 {
         // For 100+ : show as integer (e.g., "123k" not "123.k")
-        (significant_digits.saturating_sub(3)).max(0)
+        significant_digits.saturating_sub(3)
     } else if abs_scaled >= 10.0 {
         // For 10-99: show 1 less decimal place (e.g., "12.3k" for 3 sig digits)
-        (significant_digits.saturating_sub(2)).max(0)
+        significant_digits.saturating_sub(2)
     } else {
         // For 1-9.99: show full decimal places (e.g., "1.23k" for 3 sig digits)
-        (significant_digits.saturating_sub(1)).max(0)
+        significant_digits.saturating_sub(1)
     };
 
     format!(
@@ -281,17 +291,33 @@ impl ControlPanel {
                         continue;
                     }
                     if ui.small_button(hostname).clicked() {
-                        match device_manager.add_device(hostname.to_string()) {
-                            Ok(_) => {
-                                notifications
-                                    .add_success(format!("Connected to device: {}", hostname));
-                            }
-                            Err(e) => {
-                                notifications
-                                    .add_error(format!("Failed to connect to {}: {}", hostname, e));
-                                tracing::error!("Failed to add device: {}", e);
-                            }
-                        }
+                        Self::connect_device(device_manager, notifications, hostname);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.simulated_hostname)
+                        .hint_text(DEFAULT_SIMULATED_HOSTNAME)
+                        .desired_width(150.0),
+                )
+                .on_hover_text(
+                    "sim://<sine|square|triangle|ekg>-<freq>[?noise=V&disconnect_after=s]",
+                );
+                if ui.small_button("Add simulated").clicked() {
+                    let hostname = if self.simulated_hostname.is_empty() {
+                        DEFAULT_SIMULATED_HOSTNAME
+                    } else {
+                        self.simulated_hostname.as_str()
+                    };
+                    if device_manager
+                        .get_devices()
+                        .iter()
+                        .any(|d| d.name == hostname)
+                    {
+                        notifications.add_error(format!("{} is already connected", hostname));
+                    } else {
+                        Self::connect_device(device_manager, notifications, hostname);
                     }
                 }
             });
@@ -350,6 +376,36 @@ impl ControlPanel {
         });
     }
 
+    fn connect_device(
+        device_manager: &mut DeviceManager,
+        notifications: &mut NotificationManager,
+        hostname: &str,
+    ) {
+        match device_manager.add_device(hostname.to_string()) {
+            Ok(_) => {
+                notifications.add_success(format!("Connected to device: {}", hostname));
+            }
+            Err(e) => {
+                notifications.add_error(format!("Failed to connect to {}: {}", hostname, e));
+                tracing::error!("Failed to add device: {}", e);
+            }
+        }
+    }
+
+    /// Save the latest capture of `device` and report the outcome
+    pub fn save_capture(device: &FleaScopeDevice, notifications: &mut NotificationManager) {
+        match capture_export::save_capture(device, std::path::Path::new(CAPTURE_DIR)) {
+            Ok(paths) => {
+                let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                notifications.add_success(format!("Capture saved: {}", names.join(", ")));
+            }
+            Err(e) => {
+                tracing::error!("Failed to save capture of {}: {:#}", device.name, e);
+                notifications.add_error(format!("Save failed - {}: {:#}", device.name, e));
+            }
+        }
+    }
+
     fn render_device_rack(
         &self,
         ui: &mut egui::Ui,
@@ -368,6 +424,11 @@ impl ControlPanel {
                 Notification::Error(msg) => notifications.add_error(msg),
             })
             .ok();
+        while let Ok(error) = device.error_rx.try_recv() {
+            if device.errors.record(&error) {
+                notifications.add_error(format!("{}: dropped frame - {}", device.name, error));
+            }
+        }
 
         // Device Header - Retro Style with LED Status
         ui.horizontal(|ui| {
@@ -665,10 +726,76 @@ impl ControlPanel {
                         )
                         .clicked()
                     {
-                        if is_paused {
-                            device.resume();
+                        let result = if is_paused {
+                            device.resume()
                         } else {
-                            device.pause();
+                            device.pause()
+                        };
+                        if let Err(e) = result {
+                            notifications.add_error(format!("{}: {}", device.name, e));
+                        }
+                    }
+
+                    // Single-shot button, arms the trigger for one capture
+                    let triggered = matches!(device.get_capture_mode(), CaptureModeFlat::Triggered);
+                    if ui
+                        .add_enabled(
+                            triggered,
+                            egui::Button::new(
+                                RichText::new("SINGLE").size(8.0).color(Color32::YELLOW),
+                            )
+                            .min_size(egui::vec2(25.0, 20.0)),
+                        )
+                        .on_hover_text("Capture one triggered frame, then stop")
+                        .clicked()
+                    {
+                        if let Err(e) = device.single() {
+                            notifications
+                                .add_error(format!("Single failed - {}: {}", device.name, e));
+                        }
+                    }
+
+                    let state = device.acquisition_state();
+                    ui.label(
+                        RichText::new(state.label())
+                            .size(8.0)
+                            .strong()
+                            .color(match state {
+                                AcquisitionState::Stopped => Color32::RED,
+                                AcquisitionState::Reconnecting => Color32::ORANGE,
+                                AcquisitionState::Armed => Color32::YELLOW,
+                                AcquisitionState::Triggered | AcquisitionState::Rolling => {
+                                    Color32::GREEN
+                                }
+                            }),
+                    );
+
+                    ui.end_row();
+
+                    // Row 4: How successive captures are combined
+                    ui.label(
+                        RichText::new("ACQUIRE")
+                            .size(8.0)
+                            .color(Color32::LIGHT_GRAY),
+                    );
+
+                    for mode in AcquisitionMode::ALL {
+                        let is_selected = device.acquisition.mode == mode;
+                        if ui
+                            .add_enabled(
+                                triggered,
+                                egui::Button::new(RichText::new(mode.label()).size(7.0).color(
+                                    if is_selected {
+                                        Color32::YELLOW
+                                    } else {
+                                        Color32::LIGHT_GRAY
+                                    },
+                                ))
+                                .min_size(egui::vec2(25.0, 18.0)),
+                            )
+                            .clicked()
+                        {
+                            device.acquisition.set_mode(mode);
                         }
                     }
 
@@ -751,9 +878,38 @@ impl ControlPanel {
                         }
                     }
                     ui.end_row();
+
+                    // Row 2: Capture export
+                    ui.label(
+                        RichText::new("CAPTURE")
+                            .size(8.0)
+                            .color(Color32::LIGHT_GRAY),
+                    );
+
+                    if ui
+                        .add_sized(
+                            [30.0, 18.0],
+                            egui::Button::new(
+                                RichText::new("SAVE").size(7.0).color(Color32::LIGHT_GREEN),
+                            ),
+                        )
+                        .on_hover_text("Save the latest capture as CSV and Parquet")
+                        .clicked()
+                    {
+                        Self::save_capture(device, notifications);
+                    }
+                    ui.end_row();
                 });
         });
 
+        // A failed recorder has stopped writing, so close it and tell the user
+        if device
+            .recording_status()
+            .is_some_and(|status| status.error.is_some())
+        {
+            Self::stop_recording(device, notifications);
+        }
+
         // Retro Capture Mode Panel
         ui.add_space(3.0);
         egui::CollapsingHeader::new(
@@ -833,6 +989,8 @@ impl ControlPanel {
                     ui.label(""); // Empty label instead of add_space
                     ui.end_row();
                 });
+
+            device.errors.ui(ui, idx);
         });
 
         // Hardware-Style Footer with Model Info and Calibration Status
@@ -1109,6 +1267,90 @@ impl ControlPanel {
                         }
                         ui.end_row();
                     }
+
+                    // Horizontal placement of the window around the trigger, for both sources
+                    ui.label(
+                        RichText::new("PRE-TRIG")
+                            .size(8.0)
+                            .color(Color32::LIGHT_GRAY),
+                    );
+
+                    let triggered = device.get_triggered_config();
+                    let position = triggered.trigger_config.position;
+                    // Long time frames leave less of the free-running capture to place the window in
+                    let max_position = pre_trigger::max_position(
+                        triggered.time_frame,
+                        triggered.trigger_config.offset,
+                    );
+                    for preset in [0.0, 0.1, 0.5, MAX_TRIGGER_POSITION] {
+                        let is_selected = (position - preset).abs() < 1e-6;
+                        let fits = preset <= max_position + 1e-6;
+                        let color = match (is_selected, fits) {
+                            (true, true) => Color32::YELLOW,
+                            (true, false) => Color32::ORANGE,
+                            (false, _) => Color32::LIGHT_GRAY,
+                        };
+                        let hover = if preset == 0.0 {
+                            "Trigger at the left edge, uses the hardware trigger".to_string()
+                        } else if fits {
+                            format!(
+                                "{:.0}% of the window before the trigger. The scope free-runs and \
+                                 the trigger is searched in the samples, so events between \
+                                 captures are missed",
+                                preset * 100.0
+                            )
+                        } else {
+                            format!(
+                                "Only {:.0}% fits before the trigger at this time frame",
+                                max_position.max(0.0) * 100.0
+                            )
+                        };
+                        if ui
+                            .add_enabled_ui(fits || is_selected, |ui| {
+                                ui.add_sized(
+                                    [25.0, 18.0],
+                                    egui::Button::new(
+                                        RichText::new(format!("{:.0}%", preset * 100.0))
+                                            .size(7.0)
+                                            .color(color),
+                                    ),
+                                )
+                                .on_hover_text(&hover)
+                                .on_disabled_hover_text(&hover)
+                            })
+                            .inner
+                            .clicked()
+                        {
+                            let mut new_config =
+                                device.get_triggered_config().trigger_config.clone();
+                            new_config.position = preset;
+                            device.set_trigger_config(new_config);
+                        }
+                    }
+                    ui.end_row();
+
+                    ui.label(RichText::new("OFFSET").size(8.0).color(Color32::LIGHT_GRAY));
+
+                    let mut offset = device.get_triggered_config().trigger_config.offset;
+                    let offset_response = ui
+                        .add(
+                            egui::Slider::new(&mut offset, 0.0..=MAX_TRIGGER_OFFSET)
+                                .logarithmic(true)
+                                .custom_formatter(|n, _| {
+                                    if n >= 0.001 || n == 0.0 {
+                                        format!("{:.0}ms", n * 1000.0)
+                                    } else {
+                                        format!("{:.0}μs", n * 1_000_000.0)
+                                    }
+                                }),
+                        )
+                        .on_hover_text("Move the window past the trigger");
+                    if offset_response.changed() {
+                        let mut new_config = device.get_triggered_config().trigger_config.clone();
+                        new_config.offset = offset;
+                        device.set_trigger_config(new_config);
+                    }
+                    ui.end_row();
                 });
         });
     }
@@ -1242,7 +1484,7 @@ impl ControlPanel {
         ui: &mut egui::Ui,
         device: &mut FleaScopeDevice,
         _idx: usize,
-        _notifications: &mut NotificationManager,
+        notifications: &mut NotificationManager,
     ) {
         ui.horizontal(|ui| {
             ui.label(
@@ -1359,19 +1601,140 @@ impl ControlPanel {
                         device.wrap = !device.wrap;
                     }
 
-                    let buffer_response = ui.add(
-                        egui::Slider::new(&mut *device.get_mut_buffer_time_handle(), 0.001..=10.0)
-                            .logarithmic(true)
-                            .custom_formatter(|n, _| {
-                                if n >= 1.0 {
-                                    format!("{:.2}s", n)
+                    if ui
+                        .add_sized(
+                            [30.0, 22.0],
+                            egui::Button::new(RichText::new("Env").size(8.0).color(
+                                if device.envelope {
+                                    Color32::GREEN
                                 } else {
-                                    format!("{:.0}ms", n * 1000.0)
-                                }
-                            }),
+                                    Color32::RED
+                                },
+                            )),
+                        )
+                        .on_hover_text("Show the min/max band of every pixel column")
+                        .clicked()
+                    {
+                        device.envelope = !device.envelope;
+                    }
+
+                    ui.add(
+                        egui::Slider::new(
+                            &mut *device.get_mut_buffer_time_handle(),
+                            0.001..=MAX_BUFFER_TIME,
+                        )
+                        .logarithmic(true)
+                        .custom_formatter(|n, _| {
+                            if n >= 1.0 {
+                                format!("{:.2}s", n)
+                            } else {
+                                format!("{:.0}ms", n * 1000.0)
+                            }
+                        }),
                     );
                 });
             }
         }
+
+        // Keep the recorder visible while it runs, even after leaving continuous mode
+        if matches!(device.get_capture_mode(), CaptureModeFlat::Continuous)
+            || device.recording_status().is_some()
+        {
+            ui.add_space(2.0);
+            Self::render_recording_controls(ui, device, notifications);
+        }
+    }
+
+    fn render_recording_controls(
+        ui: &mut egui::Ui,
+        device: &mut FleaScopeDevice,
+        notifications: &mut NotificationManager,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new("RECORD:")
+                    .size(8.0)
+                    .strong()
+                    .color(Color32::LIGHT_BLUE),
+            );
+
+            ui.add_space(5.0);
+
+            match device.recording_status() {
+                Some(status) => {
+                    if ui
+                        .add_sized(
+                            [40.0, 22.0],
+                            egui::Button::new(
+                                RichText::new("■ STOP").size(8.0).color(Color32::WHITE),
+                            )
+                            .fill(Color32::DARK_RED),
+                        )
+                        .on_hover_text("Stop recording and close the current file")
+                        .clicked()
+                    {
+                        Self::stop_recording(device, notifications);
+                        return;
+                    }
+
+                    let elapsed = status.started.elapsed().as_secs();
+                    ui.label(
+                        RichText::new(format!("● {:02}:{:02}", elapsed / 60, elapsed % 60))
+                            .size(8.0)
+                            .color(Color32::RED)
+                            .family(egui::FontFamily::Monospace),
+                    );
+                    ui.label(
+                        RichText::new(format!("{:.1}MB", status.bytes as f64 / 1_000_000.0))
+                            .size(8.0)
+                            .color(Color32::WHITE)
+                            .family(egui::FontFamily::Monospace),
+                    )
+                    .on_hover_text(match &status.current_file {
+                        Some(path) => {
+                            format!("{} file(s), writing {}", status.files, path.display())
+                        }
+                        None => format!("{} file(s)", status.files),
+                    });
+                }
+                None => {
+                    if ui
+                        .add_sized(
+                            [40.0, 22.0],
+                            egui::Button::new(RichText::new("● REC").size(8.0).color(Color32::RED)),
+                        )
+                        .on_hover_text(format!(
+                            "Record the continuous stream to Parquet files in {}/",
+                            RECORDING_DIR
+                        ))
+                        .clicked()
+                    {
+                        match device.start_recording(RecordingConfig::default()) {
+                            Ok(()) => notifications
+                                .add_info(format!("Recording started - {}", device.name)),
+                            Err(e) => notifications
+                                .add_error(format!("Recording failed - {}: {:#}", device.name, e)),
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn stop_recording(device: &mut FleaScopeDevice, notifications: &mut NotificationManager) {
+        let Some(status) = device.stop_recording() else {
+            return;
+        };
+        match status.error {
+            Some(e) => {
+                notifications.add_error(format!("Recording failed - {}: {}", device.name, e))
+            }
+            None => notifications.add_success(format!(
+                "Recorded {:.1}s to {} file(s) - {}",
+                status.samples as f64 / CONTINUOUS_SAMPLE_RATE_HZ as f64,
+                status.files,
+                device.name
+            )),
+        }
     }
 }
@@ -0,0 +1,212 @@
+use egui::{Color32, PointerButton, RichText};
+use egui_plot::{HLine, LineStyle, PlotPoint, PlotUi, VLine};
+
+use crate::control_panel::pretty_print_number;
+
+/// Screen distance in points within which a cursor can be grabbed
+const GRAB_DISTANCE: f32 = 6.0;
+
+const TIME_CURSOR_COLOR: Color32 = Color32::from_rgb(255, 140, 0);
+const VOLTAGE_CURSOR_COLOR: Color32 = Color32::from_rgb(0, 200, 255);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Handle {
+    Time(usize),
+    Voltage(usize),
+}
+
+/// Two time and two voltage cursors, shared by the analog and digital plot of a device
+#[derive(Debug, Clone, Default)]
+pub struct Cursors {
+    pub enabled: bool,
+    time: Option<[f64; 2]>,
+    voltage: Option<[f64; 2]>,
+    /// Cursor being dragged and the plot it is dragged in
+    dragging: Option<(String, Handle)>,
+    /// Plot with a cursor under the pointer last frame, which must not pan
+    hovered_plot: Option<String>,
+}
+
+impl Cursors {
+    /// Whether `plot` has to disable its own drag so a cursor can be moved instead
+    pub fn blocks_drag(&self, plot: &str) -> bool {
+        self.enabled
+            && (self.hovered_plot.as_deref() == Some(plot)
+                || self.dragging.as_ref().is_some_and(|(p, _)| p == plot))
+    }
+
+    /// Draw the cursors and move them with the pointer. Call from within `Plot::show`.
+    pub fn show(&mut self, plot_ui: &mut PlotUi, plot: &str, with_voltage: bool) {
+        if !self.enabled {
+            return;
+        }
+
+        // Place new cursors at a third and two thirds of the visible range
+        let bounds = plot_ui.plot_bounds();
+        if !bounds.is_valid() {
+            // First frame of a plot, nothing laid out yet
+            return;
+        }
+        let [x0, y0] = bounds.min();
+        let [x1, y1] = bounds.max();
+        let time = *self
+            .time
+            .get_or_insert([x0 + (x1 - x0) / 3.0, x0 + 2.0 * (x1 - x0) / 3.0]);
+        let voltage = if with_voltage {
+            Some(
+                *self
+                    .voltage
+                    .get_or_insert([y0 + (y1 - y0) / 3.0, y0 + 2.0 * (y1 - y0) / 3.0]),
+            )
+        } else {
+            None
+        };
+
+        let response = plot_ui.response().clone();
+        let press_origin = plot_ui.ctx().input(|i| i.pointer.press_origin());
+        let grab_at = |pos: Option<egui::Pos2>| {
+            let pos = pos?;
+            let nearest_time = (0..2)
+                .map(|i| {
+                    let x = plot_ui.screen_from_plot(PlotPoint::new(time[i], 0.0)).x;
+                    ((pos.x - x).abs(), Handle::Time(i))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let nearest_voltage = voltage.and_then(|voltage| {
+                (0..2)
+                    .map(|i| {
+                        let y = plot_ui.screen_from_plot(PlotPoint::new(0.0, voltage[i])).y;
+                        ((pos.y - y).abs(), Handle::Voltage(i))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+            });
+            [nearest_time, nearest_voltage]
+                .into_iter()
+                .flatten()
+                .filter(|(distance, _)| *distance <= GRAB_DISTANCE)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, handle)| handle)
+        };
+
+        if response.drag_started_by(PointerButton::Primary) {
+            if let Some(handle) = grab_at(press_origin) {
+                self.dragging = Some((plot.to_string(), handle));
+            }
+        }
+        let dragged = self
+            .dragging
+            .as_ref()
+            .filter(|(p, _)| p == plot)
+            .map(|(_, handle)| *handle);
+        if let Some(handle) = dragged {
+            if let Some(pointer) = plot_ui.pointer_coordinate() {
+                match handle {
+                    Handle::Time(i) => self.time.get_or_insert(time)[i] = pointer.x,
+                    Handle::Voltage(i) => self.voltage.get_or_insert([0.0; 2])[i] = pointer.y,
+                }
+            }
+            if !response.dragged() {
+                self.dragging = None;
+            }
+        }
+
+        let hovered = grab_at(response.hover_pos());
+        if hovered.is_some() {
+            self.hovered_plot = Some(plot.to_string());
+            plot_ui.ctx().set_cursor_icon(match hovered {
+                Some(Handle::Voltage(_)) => egui::CursorIcon::ResizeVertical,
+                _ => egui::CursorIcon::ResizeHorizontal,
+            });
+        } else if self.hovered_plot.as_deref() == Some(plot) {
+            self.hovered_plot = None;
+        }
+
+        let time = self.time.unwrap_or(time);
+        for (i, t) in time.iter().enumerate() {
+            plot_ui.vline(
+                VLine::new(format!("t{}", i + 1), *t)
+                    .color(TIME_CURSOR_COLOR)
+                    .style(LineStyle::dashed_loose())
+                    .width(1.5),
+            );
+        }
+        if with_voltage {
+            for (i, v) in self.voltage.unwrap_or_default().iter().enumerate() {
+                plot_ui.hline(
+                    HLine::new(format!("V{}", i + 1), *v)
+                        .color(VOLTAGE_CURSOR_COLOR)
+                        .style(LineStyle::dashed_loose())
+                        .width(1.5),
+                );
+            }
+        }
+    }
+
+    /// Readout row with the cursor positions and their differences
+    pub fn readout(&self, ui: &mut egui::Ui) {
+        if !self.enabled {
+            return;
+        }
+
+        let value = |ui: &mut egui::Ui, name: &str, text: String, color: Color32| {
+            ui.label(RichText::new(name).size(9.0).color(Color32::LIGHT_GRAY));
+            ui.label(
+                RichText::new(text)
+                    .size(10.0)
+                    .color(color)
+                    .family(egui::FontFamily::Monospace),
+            );
+        };
+
+        ui.horizontal_wrapped(|ui| {
+            if let Some([t1, t2]) = self.time {
+                let dt = t2 - t1;
+                value(
+                    ui,
+                    "t1",
+                    pretty_print_number(t1, Some("s"), 4),
+                    TIME_CURSOR_COLOR,
+                );
+                value(
+                    ui,
+                    "t2",
+                    pretty_print_number(t2, Some("s"), 4),
+                    TIME_CURSOR_COLOR,
+                );
+                value(
+                    ui,
+                    "Δt",
+                    pretty_print_number(dt, Some("s"), 4),
+                    Color32::WHITE,
+                );
+                let frequency = if dt != 0.0 {
+                    pretty_print_number(1.0 / dt.abs(), Some("Hz"), 4)
+                } else {
+                    "—".to_string()
+                };
+                value(ui, "1/Δt", frequency, Color32::WHITE);
+            }
+            if let Some([v1, v2]) = self.voltage {
+                ui.separator();
+                value(
+                    ui,
+                    "V1",
+                    pretty_print_number(v1, Some("V"), 4),
+                    VOLTAGE_CURSOR_COLOR,
+                );
+                value(
+                    ui,
+                    "V2",
+                    pretty_print_number(v2, Some("V"), 4),
+                    VOLTAGE_CURSOR_COLOR,
+                );
+                value(
+                    ui,
+                    "ΔV",
+                    pretty_print_number(v2 - v1, Some("V"), 4),
+                    Color32::WHITE,
+                );
+            }
+        });
+    }
+}
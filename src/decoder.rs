@@ -0,0 +1,385 @@
+use anyhow::{Context, Result};
+use egui::{Color32, RichText};
+use polars::prelude::{Column, CsvWriter, DataFrame, SerWriter};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::capture_export::{self, CAPTURE_DIR};
+use crate::control_panel::pretty_print_number;
+use crate::device::DataPoint;
+use crate::i2c_decoder::{self, I2cConfig};
+use crate::spi_decoder::{self, SpiConfig};
+use crate::uart_decoder::{self, Parity, UartConfig};
+
+/// Longest decoded frame list shown in the table
+const MAX_LISTED_FRAMES: usize = 1000;
+
+/// One decoded unit on a bus, e.g. a UART byte
+#[derive(Debug, Clone)]
+pub struct DecodedFrame {
+    pub start: f64,
+    pub end: f64,
+    /// Digital channel whose lane the frame is drawn on
+    pub channel: usize,
+    pub value: String,
+    pub info: String,
+    pub error: Option<String>,
+}
+
+impl DecodedFrame {
+    /// Text shown in the box on the digital plot
+    pub fn label(&self) -> String {
+        match &self.error {
+            Some(error) => format!("{} {}!", self.value, error),
+            None if self.info.is_empty() => self.value.clone(),
+            None => format!("{} {}", self.value, self.info),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Protocol {
+    Uart(UartConfig),
+    Spi(SpiConfig),
+    I2c(I2cConfig),
+}
+
+impl Protocol {
+    /// Each protocol with its default settings
+    fn all() -> [Protocol; 3] {
+        [
+            Protocol::Uart(UartConfig::default()),
+            Protocol::Spi(SpiConfig::default()),
+            Protocol::I2c(I2cConfig::default()),
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Uart(_) => "UART",
+            Protocol::Spi(_) => "SPI",
+            Protocol::I2c(_) => "I2C",
+        }
+    }
+}
+
+/// Write the decoded frames as CSV, one row per frame
+pub fn export_frames(path: &Path, protocol: &str, frames: &[DecodedFrame]) -> Result<()> {
+    let column = |name: &str, values: Vec<String>| Column::new(name.into(), values);
+    let mut df = DataFrame::new(vec![
+        Column::new(
+            "start".into(),
+            frames.iter().map(|f| f.start).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "end".into(),
+            frames.iter().map(|f| f.end).collect::<Vec<_>>(),
+        ),
+        column("protocol", vec![protocol.to_string(); frames.len()]),
+        column(
+            "lane",
+            frames.iter().map(|f| format!("D{}", f.channel)).collect(),
+        ),
+        column("value", frames.iter().map(|f| f.value.clone()).collect()),
+        column("info", frames.iter().map(|f| f.info.clone()).collect()),
+        column(
+            "error",
+            frames
+                .iter()
+                .map(|f| f.error.clone().unwrap_or_default())
+                .collect(),
+        ),
+    ])?;
+    let mut file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    CsvWriter::new(&mut file)
+        .include_header(true)
+        .finish(&mut df)?;
+    Ok(())
+}
+
+/// Protocol decoder of one device with its settings and the latest decoded frames
+pub struct DecoderPanel {
+    pub enabled: bool,
+    pub protocol: Protocol,
+    frames: Vec<DecodedFrame>,
+    detected_baud: Option<u32>,
+    /// Outcome of the last export, shown next to the export button
+    export_result: Option<Result<PathBuf, String>>,
+}
+
+impl Default for DecoderPanel {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: Protocol::Uart(UartConfig::default()),
+            frames: Vec::new(),
+            detected_baud: None,
+            export_result: None,
+        }
+    }
+}
+
+impl DecoderPanel {
+    /// Decode `data_points` with the current settings and return the frames
+    pub fn decode(&mut self, time: &[f64], data_points: &[DataPoint]) -> &[DecodedFrame] {
+        profiling::scope!("DecoderPanel::decode");
+
+        self.frames.clear();
+        if !self.enabled {
+            return &self.frames;
+        }
+        match &self.protocol {
+            Protocol::Uart(config) => {
+                let bits: Vec<bool> = data_points
+                    .iter()
+                    .map(|p| p.digital_channels[config.channel])
+                    .collect();
+                self.detected_baud = config
+                    .auto_baud
+                    .then(|| uart_decoder::detect_baud(time, &bits))
+                    .flatten();
+                let baud = self.detected_baud.unwrap_or(config.baud);
+                self.frames = uart_decoder::decode(config, baud, time, &bits);
+            }
+            Protocol::Spi(config) => self.frames = spi_decoder::decode(config, time, data_points),
+            Protocol::I2c(config) => self.frames = i2c_decoder::decode(config, time, data_points),
+        }
+        &self.frames
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, id_salt: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        egui::CollapsingHeader::new(RichText::new("Protocol Decoder").strong())
+            .id_salt(format!("decoder_{}", id_salt))
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt(format!("decoder_protocol_{}", id_salt))
+                        .selected_text(self.protocol.as_str())
+                        .width(60.0)
+                        .show_ui(ui, |ui| {
+                            for protocol in Protocol::all() {
+                                let selected = protocol.as_str() == self.protocol.as_str();
+                                if ui.selectable_label(selected, protocol.as_str()).clicked()
+                                    && !selected
+                                {
+                                    self.protocol = protocol;
+                                }
+                            }
+                        });
+                    match &mut self.protocol {
+                        Protocol::Uart(config) => {
+                            Self::uart_settings(ui, id_salt, config, self.detected_baud)
+                        }
+                        Protocol::Spi(config) => Self::spi_settings(ui, id_salt, config),
+                        Protocol::I2c(config) => Self::i2c_settings(ui, id_salt, config),
+                    }
+                });
+                ui.separator();
+                self.frame_table(ui, id_salt);
+            });
+    }
+
+    fn uart_settings(
+        ui: &mut egui::Ui,
+        id_salt: &str,
+        config: &mut UartConfig,
+        detected_baud: Option<u32>,
+    ) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("on");
+            channel_selector(ui, format!("uart_channel_{}", id_salt), &mut config.channel);
+
+            ui.label("Baud:");
+            ui.add_enabled(
+                !config.auto_baud,
+                egui::DragValue::new(&mut config.baud).range(50..=5_000_000),
+            );
+            ui.checkbox(&mut config.auto_baud, "Auto");
+            if config.auto_baud {
+                ui.label(
+                    RichText::new(match detected_baud {
+                        Some(baud) => format!("{} Bd", baud),
+                        None => "no edges".to_string(),
+                    })
+                    .color(Color32::LIGHT_BLUE),
+                );
+            }
+
+            ui.label("Bits:");
+            ui.add(egui::DragValue::new(&mut config.data_bits).range(5..=9));
+
+            egui::ComboBox::from_id_salt(format!("uart_parity_{}", id_salt))
+                .selected_text(format!("Parity {}", config.parity.as_str()))
+                .show_ui(ui, |ui| {
+                    for parity in [Parity::None, Parity::Even, Parity::Odd] {
+                        ui.selectable_value(&mut config.parity, parity, parity.as_str());
+                    }
+                });
+
+            egui::ComboBox::from_id_salt(format!("uart_stop_{}", id_salt))
+                .selected_text(format!("{} stop", config.stop_bits))
+                .show_ui(ui, |ui| {
+                    for stop_bits in [1.0, 1.5, 2.0] {
+                        ui.selectable_value(
+                            &mut config.stop_bits,
+                            stop_bits,
+                            format!("{} stop", stop_bits),
+                        );
+                    }
+                });
+
+            ui.selectable_value(&mut config.idle_high, true, "Idle high");
+            ui.selectable_value(&mut config.idle_high, false, "Idle low");
+        });
+    }
+
+    fn spi_settings(ui: &mut egui::Ui, id_salt: &str, config: &mut SpiConfig) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("CLK");
+            channel_selector(ui, format!("spi_clk_{}", id_salt), &mut config.clk);
+            ui.label("MOSI");
+            optional_channel_selector(ui, format!("spi_mosi_{}", id_salt), &mut config.mosi);
+            ui.label("MISO");
+            optional_channel_selector(ui, format!("spi_miso_{}", id_salt), &mut config.miso);
+            ui.label("CS");
+            optional_channel_selector(ui, format!("spi_cs_{}", id_salt), &mut config.cs);
+            if config.cs.is_some() {
+                ui.selectable_value(&mut config.cs_active_low, true, "Active low");
+                ui.selectable_value(&mut config.cs_active_low, false, "Active high");
+            }
+
+            ui.separator();
+            ui.checkbox(&mut config.cpol, "CPOL");
+            ui.checkbox(&mut config.cpha, "CPHA");
+            ui.label(RichText::new(format!("Mode {}", config.mode())).color(Color32::LIGHT_BLUE));
+            ui.selectable_value(&mut config.msb_first, true, "MSB first");
+            ui.selectable_value(&mut config.msb_first, false, "LSB first");
+            ui.label("Bits:");
+            ui.add(egui::DragValue::new(&mut config.word_bits).range(4..=32));
+        });
+    }
+
+    fn i2c_settings(ui: &mut egui::Ui, id_salt: &str, config: &mut I2cConfig) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("SCL");
+            channel_selector(ui, format!("i2c_scl_{}", id_salt), &mut config.scl);
+            ui.label("SDA");
+            channel_selector(ui, format!("i2c_sda_{}", id_salt), &mut config.sda);
+        });
+    }
+
+    fn frame_table(&mut self, ui: &mut egui::Ui, id_salt: &str) {
+        let errors = self.frames.iter().filter(|f| f.error.is_some()).count();
+        ui.horizontal(|ui| {
+            ui.label(
+                RichText::new(format!("{} frames, {} errors", self.frames.len(), errors))
+                    .size(9.0)
+                    .color(Color32::LIGHT_GRAY),
+            );
+            if ui
+                .add_enabled(!self.frames.is_empty(), egui::Button::new("💾 Export CSV"))
+                .on_hover_text(format!("Save the decoded frames to {}/", CAPTURE_DIR))
+                .clicked()
+            {
+                self.export_result = Some(self.export(id_salt).map_err(|e| format!("{:#}", e)));
+            }
+            match &self.export_result {
+                Some(Ok(path)) => {
+                    ui.colored_label(Color32::LIGHT_GREEN, path.display().to_string());
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::LIGHT_RED, e);
+                }
+                None => {}
+            }
+        });
+        if self.frames.is_empty() {
+            return;
+        }
+
+        egui::ScrollArea::vertical()
+            .id_salt(format!("decoder_frames_{}", id_salt))
+            .max_height(150.0)
+            .show(ui, |ui| {
+                egui::Grid::new(format!("decoder_grid_{}", id_salt))
+                    .num_columns(6)
+                    .striped(true)
+                    .spacing([12.0, 2.0])
+                    .show(ui, |ui| {
+                        for header in ["#", "Time", "Lane", "Value", "Info", "Error"] {
+                            ui.label(RichText::new(header).strong());
+                        }
+                        ui.end_row();
+
+                        for (idx, frame) in self.frames.iter().take(MAX_LISTED_FRAMES).enumerate() {
+                            let color = if frame.error.is_some() {
+                                Color32::LIGHT_RED
+                            } else {
+                                Color32::WHITE
+                            };
+                            let cell = |ui: &mut egui::Ui, text: String| {
+                                ui.label(
+                                    RichText::new(text)
+                                        .color(color)
+                                        .family(egui::FontFamily::Monospace),
+                                );
+                            };
+                            cell(ui, idx.to_string());
+                            cell(ui, pretty_print_number(frame.start, Some("s"), 6));
+                            cell(ui, format!("D{}", frame.channel));
+                            cell(ui, frame.value.clone());
+                            cell(ui, frame.info.clone());
+                            cell(ui, frame.error.clone().unwrap_or_default());
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn export(&self, name: &str) -> Result<PathBuf> {
+        let dir = Path::new(CAPTURE_DIR);
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let stem = capture_export::file_stem(name, chrono::Utc::now());
+        let path = dir.join(format!(
+            "{}_{}.csv",
+            stem,
+            self.protocol.as_str().to_lowercase()
+        ));
+        export_frames(&path, self.protocol.as_str(), &self.frames)?;
+        tracing::info!(
+            "Exported {} decoded frames to {}",
+            self.frames.len(),
+            path.display()
+        );
+        Ok(path)
+    }
+}
+
+fn channel_selector(ui: &mut egui::Ui, id_salt: String, channel: &mut usize) {
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(format!("D{}", channel))
+        .width(45.0)
+        .show_ui(ui, |ui| {
+            for ch in 0..9 {
+                ui.selectable_value(channel, ch, format!("D{}", ch));
+            }
+        });
+}
+
+fn optional_channel_selector(ui: &mut egui::Ui, id_salt: String, channel: &mut Option<usize>) {
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(channel.map_or("—".to_string(), |ch| format!("D{}", ch)))
+        .width(45.0)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(channel, None, "—");
+            for ch in 0..9 {
+                ui.selectable_value(channel, Some(ch), format!("D{}", ch));
+            }
+        });
+}
@@ -1,25 +1,40 @@
 use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
 use fleascope_rs::{
-    AnalogTrigger, AnalogTriggerBuilder, BitState, DigitalTrigger, FleaConnectorError,
+    AnalogTrigger, AnalogTriggerBuilder, BitState, DigitalTrigger, FleaConnectorError, FleaProbe,
     IdleFleaScope, ProbeType, Waveform,
 };
+use serde::{Deserialize, Serialize};
 use std::{sync::Arc, time::Instant};
 use tokio::sync::watch;
 
-use crate::{device_worker::FleaWorker, worker_interface::FleaScopeDevice};
+use crate::{
+    continuous_buffer::ContinuousBatch,
+    device_worker::{FleaWorker, ScopeBackend},
+    live_stream,
+    simulator::{self, SimulatedScope},
+    worker_interface::FleaScopeDevice,
+};
 
 // Time frame constants for consistent validation
 pub const MIN_TIME_FRAME: f64 = 0.000122; // 122μs
 pub const MAX_TIME_FRAME: f64 = 3.49; // 3.49s
 
+// Trigger placement limits, the firmware delays by at most a second
+pub const MAX_TRIGGER_POSITION: f64 = 0.9;
+pub const MAX_TRIGGER_OFFSET: f64 = 1.0;
+
+// Sample rate of the continuous stream
+pub const CONTINUOUS_SAMPLE_RATE_HZ: u32 = 51_436;
+
 #[derive(Default)]
 pub struct DeviceManager {
     devices: Vec<FleaScopeDevice>,
 }
 
 impl DeviceManager {
+    /// Connect to a device by hostname. Hostnames starting with `sim://` create a simulated device.
     pub fn add_device(&mut self, hostname: String) -> Result<(), FleaConnectorError> {
-        let (scope, x1, x10) = IdleFleaScope::connect(Some(&hostname), None, true)?;
         let initial_config = CaptureConfig {
             probe_multiplier: ProbeType::X1,
             mode: CaptureMode::Triggered {
@@ -27,6 +42,33 @@ impl DeviceManager {
                 time_frame: 0.1,
             },
         };
+        self.add_device_with_config(hostname, initial_config)
+    }
+
+    /// Like [`Self::add_device`], but the first capture already uses `initial_config`
+    pub fn add_device_with_config(
+        &mut self,
+        hostname: String,
+        initial_config: CaptureConfig,
+    ) -> Result<(), FleaConnectorError> {
+        if simulator::is_simulated(&hostname) {
+            let (scope, x1, x10) = SimulatedScope::connect(&hostname)?;
+            self.spawn_device(hostname, scope, x1, x10, initial_config);
+        } else {
+            let (scope, x1, x10) = IdleFleaScope::connect(Some(&hostname), None, true)?;
+            self.spawn_device(hostname, scope, x1, x10, initial_config);
+        }
+        Ok(())
+    }
+
+    fn spawn_device<S: ScopeBackend>(
+        &mut self,
+        hostname: String,
+        scope: S,
+        x1: FleaProbe,
+        x10: FleaProbe,
+        initial_config: CaptureConfig,
+    ) {
         let initial_waveform = WaveformConfig::default();
 
         let (capture_config_tx, capture_config_rx) = watch::channel(initial_config.clone());
@@ -37,7 +79,11 @@ impl DeviceManager {
         let (notification_tx, notification_rx) = tokio::sync::mpsc::channel::<Notification>(32);
 
         // Create continuous batch streaming channel
-        let (batch_tx, batch_rx) = tokio::sync::mpsc::unbounded_channel::<Vec<f64>>();
+        let (batch_tx, batch_rx) = tokio::sync::mpsc::unbounded_channel::<ContinuousBatch>();
+        let (recording_tx, recording_rx) = watch::channel(None);
+        let (error_tx, error_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (state_tx, state_rx) = watch::channel(AcquisitionState::Stopped);
+        let (stream_tx, _) = tokio::sync::broadcast::channel(live_stream::STREAM_CAPACITY);
 
         let data = Arc::new(ArcSwap::new(Arc::new(DeviceData {
             x_values: Vec::new(),
@@ -46,9 +92,11 @@ impl DeviceManager {
             update_rate: 0.0,
             connected: true,
             running: true,
+            settings: None,
         })));
 
         let mut worker = FleaWorker {
+            hostname: hostname.clone(),
             data: data.clone(),
             config_change_rx: capture_config_rx,
             control_rx: calibration_rx,
@@ -57,7 +105,12 @@ impl DeviceManager {
             x10,
             waveform_rx, // Channel for waveform configuration
             running: true,
+            single_shot: false,
+            state_tx,
             batch_tx,
+            error_tx,
+            stream_tx: stream_tx.clone(),
+            recording_rx,
         };
 
         let device = FleaScopeDevice::new(
@@ -70,6 +123,10 @@ impl DeviceManager {
             waveform_tx,
             initial_waveform,
             batch_rx,
+            recording_tx,
+            state_rx,
+            error_rx,
+            stream_tx,
         );
         let _handle = tokio::spawn(async move {
             if let Err(e) = worker.run(scope).await {
@@ -78,7 +135,6 @@ impl DeviceManager {
         });
 
         self.devices.push(device);
-        Ok(())
     }
 
     pub fn get_devices(&self) -> &[FleaScopeDevice] {
@@ -126,16 +182,57 @@ pub enum ControlCommand {
     StoreCalibration(),
     Pause,
     Resume,
+    /// Arm the trigger for exactly one capture, then pause
     Step,
     Exit,
 }
 
+/// What the acquisition is doing, shown next to the RUN/STOP button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquisitionState {
+    /// Paused or disconnected
+    Stopped,
+    /// Waiting for the trigger condition
+    Armed,
+    /// Triggered captures are arriving
+    Triggered,
+    /// Continuous mode is streaming
+    Rolling,
+    /// Connection lost, trying to reach the device again
+    Reconnecting,
+}
+
+impl AcquisitionState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AcquisitionState::Stopped => "STOPPED",
+            AcquisitionState::Armed => "ARMED",
+            AcquisitionState::Triggered => "TRIG'D",
+            AcquisitionState::Rolling => "ROLL",
+            AcquisitionState::Reconnecting => "RECONN",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DataPoint {
     pub analog_channel: f64,
     pub digital_channels: [bool; 9],
 }
 
+/// Settings a triggered capture was taken with, which can differ from the current ones
+#[derive(Debug, Clone)]
+pub struct CaptureSettings {
+    pub probe: ProbeType,
+    pub time_frame: f64,
+    pub trigger: TriggerConfig,
+    pub waveform: WaveformConfig,
+    pub captured_at: DateTime<Utc>,
+}
+
+/// Time and volts of unresampled analog samples, a new `Arc` whenever the samples change
+pub type AnalogSamples = Arc<(Vec<f64>, Vec<f64>)>;
+
 #[derive(Debug, Clone)]
 pub struct DeviceData {
     pub x_values: Vec<f64>,
@@ -144,39 +241,14 @@ pub struct DeviceData {
     pub update_rate: f64,
     pub connected: bool,
     pub running: bool,
-}
-
-fn mean(data: &[f64]) -> Option<f64> {
-    let sum = data.iter().sum::<f64>();
-    let count = data.len();
-
-    match count {
-        positive if positive > 0 => Some(sum / count as f64),
-        _ => None,
-    }
-}
-
-fn std_deviation(data: &[f64]) -> Option<f64> {
-    match (mean(data), data.len()) {
-        (Some(data_mean), count) if count > 0 => {
-            let variance = data.iter().map(|value| {
-                let diff = data_mean - (*value as f64);
-
-                diff * diff
-            }).sum::<f64>() / count as f64;
-
-            Some(variance.sqrt())
-        },
-        _ => None
-    }
+    /// `None` until the first triggered capture arrives
+    pub settings: Option<CaptureSettings>,
 }
 
 impl DeviceData {
     pub fn get_analog_data(&self) -> (Vec<f64>, Vec<f64>) {
         let x = self.x_values.clone();
         let y: Vec<f64> = self.data_points.iter().map(|p| p.analog_channel).collect();
-        let rmse = std_deviation(y.as_slice());
-        tracing::debug!("RMS Error in window: {:?}", rmse);
         (x, y)
     }
 
@@ -201,7 +273,7 @@ impl DeviceData {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum TriggerSource {
     Analog,
     Digital,
@@ -212,6 +284,10 @@ pub struct TriggerConfig {
     pub source: TriggerSource,
     pub analog: AnalogTriggerBuilder,
     pub digital: DigitalTrigger,
+    /// Share of the window before the trigger instant, 0.0 puts the trigger at the left edge
+    pub position: f64,
+    /// Seconds the window is moved past the trigger instant
+    pub offset: f64,
 }
 
 impl Default for TriggerConfig {
@@ -220,6 +296,8 @@ impl Default for TriggerConfig {
             source: TriggerSource::Digital,
             analog: AnalogTrigger::start_capturing_when(0.0).auto(),
             digital: DigitalTrigger::start_capturing_when().is_matching(),
+            position: 0.0,
+            offset: 0.0,
         }
     }
 }
@@ -1,25 +1,193 @@
 use anyhow::{Error, Result};
 use arc_swap::ArcSwap;
+use chrono::Utc;
 use fleascope_rs::flea_scope::{
+    CalibrationError, CaptureConfigError, ReadingFleaScope, ScopeReading, StreamingScope,
     BITMAP_COLUMN_NAME, CALIBRATED_COLUMN_NAME, RAW_COLUMN_NAME, TIME_COLUMN_NAME,
 };
+use fleascope_rs::serial_terminal::ConnectionLostError;
 use fleascope_rs::trigger_config::TriggerConfig as _;
-use fleascope_rs::{FleaProbe, IdleFleaScope, ProbeType};
+use fleascope_rs::{
+    DigitalTrigger, FleaConnectorError, FleaProbe, IdleFleaScope, ProbeType, Trigger, Waveform,
+};
 use polars::frame::DataFrame;
 use polars::prelude::{IntoLazy, UInt16Chunked};
 use polars::series::IntoSeries;
 
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::watch;
+use tokio::sync::{broadcast, watch};
 use tokio::time::sleep;
 
+use crate::capture_error::CaptureError;
+use crate::continuous_buffer::ContinuousBatch;
 use crate::device::{
-    CaptureConfig, CaptureMode, ControlCommand, DataPoint, DeviceData, Notification, TriggerConfig,
-    TriggerSource, WaveformConfig,
+    AcquisitionState, CaptureConfig, CaptureMode, CaptureSettings, ControlCommand, DataPoint,
+    DeviceData, Notification, TriggerConfig, TriggerSource, WaveformConfig,
 };
+use crate::live_stream::StreamFrame;
+use crate::pre_trigger::TriggerPlacement;
+use crate::recorder::{BatchSender, RecordedBatch};
+
+/// Acquisition backend driven by a `FleaWorker`.
+///
+/// Mirrors the typestates of `fleascope_rs`: an idle scope is consumed to start a
+/// reading or a stream and is handed back once that finishes or gets cancelled.
+pub trait ScopeBackend: Sized + Send + 'static {
+    type Reading: PendingReading<Scope = Self>;
+    type Stream: ScopeStream<Scope = Self>;
+
+    /// Connect to the device identified by `hostname` and return it with its probe calibrations
+    fn connect(hostname: &str) -> Result<(Self, FleaProbe, FleaProbe), FleaConnectorError>;
+
+    fn set_waveform(&mut self, waveform: Waveform, hz: i32);
+
+    fn calibrate_0(
+        &mut self,
+        probe_type: ProbeType,
+        probe: &mut FleaProbe,
+    ) -> Result<f64, CalibrationError>;
+
+    fn calibrate_3v3(
+        &mut self,
+        probe_type: ProbeType,
+        probe: &mut FleaProbe,
+    ) -> Result<f64, CalibrationError>;
+
+    fn write_calibration(&mut self, probe: &FleaProbe) -> Result<(), CalibrationError>;
+
+    /// Arm the trigger and start a single capture of `time_frame`, sampling from `delay` after the trigger
+    fn read_async(
+        self,
+        probe: ProbeType,
+        time_frame: Duration,
+        trigger: Trigger,
+        delay: Duration,
+    ) -> Result<Self::Reading, (Self, CaptureConfigError)>;
+
+    fn stream(self, probe: ProbeType) -> Self::Stream;
+
+    fn teardown(self);
+}
+
+/// A capture that has been started but not yet delivered
+pub trait PendingReading: Sized + Send {
+    type Scope;
+
+    fn try_get_result(
+        self,
+    ) -> Result<Result<(Self::Scope, ScopeReading), Self>, ConnectionLostError>;
+
+    fn cancel(self) -> Self::Scope;
+}
+
+/// Raw samples of one continuous read
+pub struct RawBatch {
+    pub adc: Vec<u16>,
+    /// Digital channel states, bit `n` is D`n`; `None` if the stream only carries the ADC
+    pub bitmap: Option<Vec<u16>>,
+}
+
+/// A running continuous stream of raw ADC samples
+pub trait ScopeStream: Send {
+    type Scope;
+
+    fn read(&mut self, n: usize) -> Result<RawBatch, std::io::Error>;
+
+    fn stop(self) -> Self::Scope;
+}
+
+impl ScopeBackend for IdleFleaScope {
+    type Reading = ReadingFleaScope;
+    type Stream = StreamingScope;
+
+    fn connect(hostname: &str) -> Result<(Self, FleaProbe, FleaProbe), FleaConnectorError> {
+        IdleFleaScope::connect(Some(hostname), None, true)
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform, hz: i32) {
+        IdleFleaScope::set_waveform(self, waveform, hz);
+    }
+
+    fn calibrate_0(
+        &mut self,
+        _probe_type: ProbeType,
+        probe: &mut FleaProbe,
+    ) -> Result<f64, CalibrationError> {
+        probe.calibrate_0(self)
+    }
+
+    fn calibrate_3v3(
+        &mut self,
+        _probe_type: ProbeType,
+        probe: &mut FleaProbe,
+    ) -> Result<f64, CalibrationError> {
+        probe.calibrate_3v3(self)
+    }
+
+    fn write_calibration(&mut self, probe: &FleaProbe) -> Result<(), CalibrationError> {
+        probe.write_calibration_to_flash(self)
+    }
+
+    fn read_async(
+        self,
+        _probe: ProbeType, // The physical probe decides, not us
+        time_frame: Duration,
+        trigger: Trigger,
+        delay: Duration,
+    ) -> Result<Self::Reading, (Self, CaptureConfigError)> {
+        let trigger_fields = match trigger {
+            Trigger::Analog(analog) => analog.into_trigger_fields(),
+            Trigger::Digital(digital) => digital.into_trigger_fields(),
+        };
+        IdleFleaScope::read_async(self, time_frame, trigger_fields, Some(delay))
+    }
+
+    fn stream(self, _probe: ProbeType) -> Self::Stream {
+        IdleFleaScope::stream(self)
+    }
+
+    fn teardown(self) {
+        IdleFleaScope::teardown(self);
+    }
+}
+
+impl PendingReading for ReadingFleaScope {
+    type Scope = IdleFleaScope;
+
+    fn try_get_result(
+        self,
+    ) -> Result<Result<(Self::Scope, ScopeReading), Self>, ConnectionLostError> {
+        ReadingFleaScope::try_get_result(self)
+    }
+
+    fn cancel(self) -> Self::Scope {
+        ReadingFleaScope::cancel(self)
+    }
+}
+
+impl ScopeStream for StreamingScope {
+    type Scope = IdleFleaScope;
+
+    fn read(&mut self, n: usize) -> Result<RawBatch, std::io::Error> {
+        // The firmware streams bare ADC words, the digital inputs are not part of it
+        Ok(RawBatch {
+            adc: StreamingScope::read(self, n)?,
+            bitmap: None,
+        })
+    }
+
+    fn stop(self) -> Self::Scope {
+        StreamingScope::stop(self)
+    }
+}
+
+/// Pause between attempts to reach a device that dropped off
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
 
 pub struct FleaWorker {
+    /// Used to reconnect after the connection is lost
+    pub hostname: String,
     pub data: Arc<ArcSwap<DeviceData>>,
     pub config_change_rx: watch::Receiver<CaptureConfig>,
     pub control_rx: tokio::sync::mpsc::Receiver<ControlCommand>,
@@ -28,106 +196,84 @@ pub struct FleaWorker {
     pub x1: FleaProbe,
     pub x10: FleaProbe,
     pub running: bool,
-    pub batch_tx: tokio::sync::mpsc::UnboundedSender<Vec<f64>>,
+    /// Pause again after the next triggered capture
+    pub single_shot: bool,
+    pub state_tx: watch::Sender<AcquisitionState>,
+    pub batch_tx: tokio::sync::mpsc::UnboundedSender<ContinuousBatch>,
+    /// Frames dropped by the capture pipeline, counted and logged per device by the UI
+    pub error_tx: tokio::sync::mpsc::UnboundedSender<CaptureError>,
+    /// Captures and batches for WebSocket subscribers, slow ones lag instead of blocking
+    pub stream_tx: broadcast::Sender<StreamFrame>,
+    pub recording_rx: watch::Receiver<Option<BatchSender>>,
 }
 
 impl FleaWorker {
-    async fn handle_control_command(
+    async fn handle_control_command<S: ScopeBackend>(
         &mut self,
         command: ControlCommand,
-        fleascope: &mut IdleFleaScope,
+        fleascope: &mut S,
     ) -> Result<()> {
         tracing::info!("Handling control command: {:?}", command);
 
         match command {
             ControlCommand::Calibrate0V(probe_multiplier) => match probe_multiplier {
-                ProbeType::X1 => match self.x1.calibrate_0(fleascope) {
+                ProbeType::X1 => match fleascope.calibrate_0(ProbeType::X1, &mut self.x1) {
                     Ok(_) => {
-                        self.notification_tx
-                            .send(Notification::Success(
-                                "X1 probe calibrated at 0V".to_string(),
-                            ))
-                            .await
-                            .expect("Failed to send calibration result");
+                        self.notify(Notification::Success(
+                            "X1 probe calibrated at 0V".to_string(),
+                        ));
+                    }
+                    Err(e) => {
+                        self.notify(Notification::Error(format!("X1 calibration failed: {}", e)))
                     }
-                    Err(e) => self
-                        .notification_tx
-                        .send(Notification::Error(format!("X1 calibration failed: {}", e)))
-                        .await
-                        .expect("Failed to send calibration result"),
                 },
-                ProbeType::X10 => match self.x10.calibrate_0(fleascope) {
+                ProbeType::X10 => match fleascope.calibrate_0(ProbeType::X10, &mut self.x10) {
                     Ok(_) => {
-                        self.notification_tx
-                            .send(Notification::Success(
-                                "X10 probe calibrated at 0V".to_string(),
-                            ))
-                            .await
-                            .expect("Failed to send calibration result");
+                        self.notify(Notification::Success(
+                            "X10 probe calibrated at 0V".to_string(),
+                        ));
                     }
-                    Err(e) => self
-                        .notification_tx
-                        .send(Notification::Error(format!(
-                            "X10 calibration failed: {}",
-                            e
-                        )))
-                        .await
-                        .expect("Failed to send calibration result"),
+                    Err(e) => self.notify(Notification::Error(format!(
+                        "X10 calibration failed: {}",
+                        e
+                    ))),
                 },
             },
             ControlCommand::Calibrate3V(probe_multiplier) => match probe_multiplier {
-                ProbeType::X1 => match self.x1.calibrate_3v3(fleascope) {
+                ProbeType::X1 => match fleascope.calibrate_3v3(ProbeType::X1, &mut self.x1) {
                     Ok(_) => {
-                        self.notification_tx
-                            .send(Notification::Success(
-                                "X1 probe calibrated at 3.3V".to_string(),
-                            ))
-                            .await
-                            .expect("Failed to send calibration result");
+                        self.notify(Notification::Success(
+                            "X1 probe calibrated at 3.3V".to_string(),
+                        ));
+                    }
+                    Err(e) => {
+                        self.notify(Notification::Error(format!("X1 calibration failed: {}", e)))
                     }
-                    Err(e) => self
-                        .notification_tx
-                        .send(Notification::Error(format!("X1 calibration failed: {}", e)))
-                        .await
-                        .expect("Failed to send calibration result"),
                 },
-                ProbeType::X10 => match self.x10.calibrate_3v3(fleascope) {
+                ProbeType::X10 => match fleascope.calibrate_3v3(ProbeType::X10, &mut self.x10) {
                     Ok(_) => {
-                        self.notification_tx
-                            .send(Notification::Success(
-                                "X10 probe calibrated at 3.3V".to_string(),
-                            ))
-                            .await
-                            .expect("Failed to send calibration result");
+                        self.notify(Notification::Success(
+                            "X10 probe calibrated at 3.3V".to_string(),
+                        ));
                     }
-                    Err(e) => self
-                        .notification_tx
-                        .send(Notification::Error(format!(
-                            "X10 calibration failed: {}",
-                            e
-                        )))
-                        .await
-                        .expect("Failed to send calibration result"),
+                    Err(e) => self.notify(Notification::Error(format!(
+                        "X10 calibration failed: {}",
+                        e
+                    ))),
                 },
             },
             ControlCommand::StoreCalibration() => {
                 match Ok(())
-                    .and(self.x1.write_calibration_to_flash(fleascope))
-                    .and(self.x10.write_calibration_to_flash(fleascope))
+                    .and(fleascope.write_calibration(&self.x1))
+                    .and(fleascope.write_calibration(&self.x10))
                 {
-                    Ok(_) => self
-                        .notification_tx
-                        .blocking_send(Notification::Success(
-                            "Calibration saved successfully".to_string(),
-                        ))
-                        .expect("Failed to send calibration save success"),
-                    Err(e) => self
-                        .notification_tx
-                        .blocking_send(Notification::Error(format!(
-                            "Failed to save calibration: {}",
-                            e
-                        )))
-                        .expect("Failed to send calibration save error"),
+                    Ok(_) => self.notify(Notification::Success(
+                        "Calibration saved successfully".to_string(),
+                    )),
+                    Err(e) => self.notify(Notification::Error(format!(
+                        "Failed to save calibration: {}",
+                        e
+                    ))),
                 }
             }
             ControlCommand::Exit => {
@@ -135,26 +281,45 @@ impl FleaWorker {
                 return Err(Error::msg("Exiting FleaWorker")); // Handle exit logic if needed
             }
             ControlCommand::Pause => {
+                self.single_shot = false;
                 self.set_as_paused().await;
             }
             ControlCommand::Resume => {
+                self.single_shot = false;
                 self.set_as_running();
             }
             ControlCommand::Step => {
-                tracing::info!("Stepping FleaWorker");
-                // Implement step logic if needed, e.g., trigger a single read
-                // This could be a no-op if stepping is not supported
+                let triggered = matches!(
+                    self.config_change_rx.borrow().mode,
+                    CaptureMode::Triggered { .. }
+                );
+                if triggered {
+                    tracing::info!("Arming FleaWorker for a single capture");
+                    self.single_shot = true;
+                    self.set_as_running();
+                } else {
+                    self.notify(Notification::Error(
+                        "Single capture needs triggered mode".to_string(),
+                    ));
+                }
             }
         };
         Ok(())
     }
 
+    /// Hand a notification to the UI; a full or closed queue only costs the message
+    fn notify(&self, notification: Notification) {
+        if let Err(e) = self.notification_tx.try_send(notification) {
+            tracing::warn!("Dropped notification: {}", e);
+        }
+    }
+
     async fn set_as_paused(&mut self) {
         tracing::info!("Setting FleaWorker as paused");
         self.running = false;
+        self.state_tx.send_replace(AcquisitionState::Stopped);
         sleep(Duration::from_millis(20)).await;
         let data = self.data.load();
-        // TODO Introduce device state channel
         self.data.store(Arc::new(DeviceData {
             x_values: data.x_values.clone(),
             data_points: data.data_points.clone(),
@@ -162,36 +327,123 @@ impl FleaWorker {
             update_rate: 0.0,
             connected: true,
             running: self.running,
+            settings: data.settings.clone(),
         }));
     }
 
     async fn set_lost_connection(&mut self) {
         tracing::info!("Lost connection");
-        self.notification_tx
-            .send(Notification::Error(
-                "Lost connection to the device.".to_string(),
-            ))
-            .await
-            .expect("Failed to send read error notification");
-        self.running = false;
+        self.notify(Notification::Error(format!(
+            "Lost connection to {}, reconnecting…",
+            self.hostname
+        )));
+        // Keep `running` and `single_shot`, acquisition resumes as it was after reconnecting
+        self.state_tx.send_replace(AcquisitionState::Reconnecting);
         sleep(Duration::from_millis(20)).await;
+        self.store_connected(false);
+    }
+
+    /// Publish the connection state and the latest run/pause request, keeping the last capture
+    fn store_connected(&self, connected: bool) {
         let data = self.data.load();
         self.data.store(Arc::new(DeviceData {
             x_values: data.x_values.clone(),
             data_points: data.data_points.clone(),
             last_update: data.last_update,
             update_rate: 0.0,
-            connected: false,
+            connected,
             running: self.running,
+            settings: data.settings.clone(),
         }));
     }
 
+    /// Try to reach the device again every [`RECONNECT_INTERVAL`].
+    ///
+    /// Capture and waveform settings live in the watch channels and calibrations in the worker,
+    /// so they carry over to the new connection. Returns `None` if the device gets removed.
+    async fn reconnect<S: ScopeBackend>(&mut self) -> Option<S> {
+        self.set_lost_connection().await;
+
+        let mut attempts = 0;
+        loop {
+            tokio::select! {
+                _ = sleep(RECONNECT_INTERVAL) => {}
+                Some(command) = self.control_rx.recv() => {
+                    match command {
+                        ControlCommand::Exit => return None,
+                        ControlCommand::Pause => {
+                            self.running = false;
+                            self.single_shot = false;
+                        }
+                        ControlCommand::Resume => {
+                            self.running = true;
+                            self.single_shot = false;
+                        }
+                        command => {
+                            self.notify(Notification::Error(format!(
+                                    "{:?} needs a connected device",
+                                    command
+                                )));
+                        }
+                    }
+                    self.store_connected(false);
+                    continue;
+                }
+            }
+
+            attempts += 1;
+            let hostname = self.hostname.clone();
+            let connected = tokio::task::spawn_blocking(move || S::connect(&hostname)).await;
+            match connected {
+                Ok(Ok((mut scope, _, _))) => {
+                    tracing::info!(
+                        "Reconnected to {} after {} attempts",
+                        self.hostname,
+                        attempts
+                    );
+                    let waveform_config = self.waveform_rx.borrow_and_update().clone();
+                    scope.set_waveform(waveform_config.waveform_type, waveform_config.frequency_hz);
+                    if !self.running {
+                        self.state_tx.send_replace(AcquisitionState::Stopped);
+                    }
+                    self.store_connected(true);
+                    self.notify(Notification::Success(format!(
+                        "Reconnected to {}",
+                        self.hostname
+                    )));
+                    return Some(scope);
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!(
+                        "Reconnect attempt {} to {} failed: {}",
+                        attempts,
+                        self.hostname,
+                        e
+                    );
+                }
+                // A panic while connecting fails this attempt, the next one starts afresh
+                Err(e) => {
+                    tracing::error!(
+                        "Reconnect attempt {} to {} panicked: {}",
+                        attempts,
+                        self.hostname,
+                        e
+                    );
+                    self.notify(Notification::Error(format!(
+                        "Reconnecting to {} panicked, retrying",
+                        self.hostname
+                    )));
+                }
+            }
+        }
+    }
+
     fn set_as_running(&mut self) {
         tracing::info!("Setting FleaWorker as running");
         self.running = true;
     }
 
-    pub async fn run(&mut self, mut fleascope: IdleFleaScope) -> Result<()> {
+    pub async fn run<S: ScopeBackend>(&mut self, mut fleascope: S) -> Result<()> {
         tracing::info!("FleaWorker started");
         let mut update_rate = 0.0;
         let mut last_rate_update = Instant::now();
@@ -208,7 +460,8 @@ impl FleaWorker {
             if self
                 .waveform_rx
                 .has_changed()
-                .expect("Failed to check for waveform config change")
+                // Closed once the device is removed, the queued exit command follows
+                .unwrap_or(false)
             {
                 tracing::info!("Waveform configuration changed, updating waveform");
                 let waveform_config = self.waveform_rx.borrow_and_update().clone();
@@ -237,27 +490,44 @@ impl FleaWorker {
 
             tracing::debug!("Device is running, starting data generation");
             let capture_config = self.config_change_rx.borrow_and_update().clone();
-            match capture_config.mode {
+            // `None` means the connection was lost
+            let next = match capture_config.mode {
                 CaptureMode::Triggered {
                     trigger_config,
                     time_frame,
                 } => {
-                    fleascope = self
-                        .handle_triggered_capture(
-                            update_rate,
-                            capture_config.probe_multiplier,
-                            time_frame,
-                            trigger_config,
-                            fleascope,
-                        )
-                        .await;
+                    self.handle_triggered_capture(
+                        update_rate,
+                        capture_config.probe_multiplier,
+                        time_frame,
+                        trigger_config,
+                        fleascope,
+                    )
+                    .await
+                }
+                CaptureMode::Continuous {} if self.single_shot => {
+                    // Switched to continuous mode while armed, there is no single frame to wait for
+                    self.single_shot = false;
+                    self.set_as_paused().await;
+                    Some(fleascope)
                 }
                 CaptureMode::Continuous {} => {
-                    fleascope = self
-                        .handle_continuous_capture(capture_config.probe_multiplier, fleascope)
-                        .await;
+                    self.state_tx.send_replace(AcquisitionState::Rolling);
+                    self.handle_continuous_capture(capture_config.probe_multiplier, fleascope)
+                        .await
                 }
-            }
+            };
+            fleascope = match next {
+                Some(scope) => scope,
+                None => match self.reconnect().await {
+                    Some(scope) => scope,
+                    None => {
+                        tracing::info!("Device removed while reconnecting");
+                        self.set_exited();
+                        return Ok(());
+                    }
+                },
+            };
 
             if last_rate_update.elapsed() >= Duration::from_secs(1) {
                 update_rate = read_count as f64 / last_rate_update.elapsed().as_secs_f64();
@@ -267,152 +537,216 @@ impl FleaWorker {
             read_count += 1;
         }
         fleascope.teardown();
-        let data = self.data.load();
-        self.data.store(Arc::new(DeviceData {
-            x_values: data.x_values.clone(),
-            data_points: data.data_points.clone(),
-            last_update: data.last_update,
-            update_rate: 0.0,
-            connected: false,
-            running: false,
-        }));
-        Err(Error::msg("FleaWorker exited"))
+        self.set_exited();
+        Ok(())
+    }
+
+    fn set_exited(&mut self) {
+        self.running = false;
+        self.state_tx.send_replace(AcquisitionState::Stopped);
+        self.store_connected(false);
     }
 
-    async fn handle_triggered_capture(
+    async fn handle_triggered_capture<S: ScopeBackend>(
         &mut self,
         update_rate: f64,
-        probe: ProbeType,
+        probe_type: ProbeType,
         time_frame: f64,
         trigger_config: TriggerConfig,
-        idle_scope: IdleFleaScope,
-    ) -> IdleFleaScope {
-        let probe = match probe {
+        idle_scope: S,
+    ) -> Option<S> {
+        let probe = match probe_type {
             ProbeType::X1 => &self.x1,
             ProbeType::X10 => &self.x10,
         };
         let probe_clone = probe.clone(); // Clone early to avoid borrowing issues
-        let trigger_str = {
-            profiling::scope!("trigger_string_conversion");
+        let placement = TriggerPlacement::new(time_frame, &trigger_config);
+        let mut settings = CaptureSettings {
+            probe: probe_type,
+            time_frame,
+            trigger: trigger_config.clone(),
+            waveform: self.waveform_rx.borrow().clone(),
+            captured_at: Utc::now(),
+        };
+        let trigger = {
+            profiling::scope!("trigger_conversion");
 
             match trigger_config.source {
                 TriggerSource::Analog => {
-                    tracing::debug!("Converting analog trigger to string");
-                    trigger_config
-                        .analog
-                        .into_trigger(probe)
-                        .map(|trigger| trigger.into_trigger_fields())
+                    tracing::debug!("Converting analog trigger");
+                    trigger_config.analog.into_trigger(probe).map(Trigger::from)
                 }
                 TriggerSource::Digital => {
-                    tracing::debug!("Converting digital trigger to string");
-                    Ok(trigger_config.digital.into_trigger_fields())
+                    tracing::debug!("Converting digital trigger");
+                    Ok(Trigger::from(trigger_config.digital))
                 }
             }
         };
 
-        let trigger_str = match trigger_str {
-            Ok(str) => str,
+        let trigger = match trigger {
+            Ok(trigger) => trigger,
             Err(e) => {
                 tracing::error!("Failed to convert trigger to string: {}", e);
-                self.notification_tx
-                    .blocking_send(Notification::Error(format!(
-                        "Invalid trigger configuration: {}",
-                        e
-                    )))
-                    .expect("Failed to send error notification");
+                self.notify(Notification::Error(format!(
+                    "Invalid trigger configuration: {}",
+                    e
+                )));
                 self.set_as_paused().await;
-                return idle_scope;
+                return Some(idle_scope);
             }
         };
 
+        let (acquisition, trigger, delay) = match &placement {
+            TriggerPlacement::Hardware { delay } => (time_frame, trigger, *delay),
+            // Free-run and look for the configured trigger in the samples
+            TriggerPlacement::Software(pre_trigger) => (
+                pre_trigger.acquisition,
+                Trigger::from(DigitalTrigger::start_capturing_when().is_matching()),
+                0.0,
+            ),
+        };
+
         let star_res = {
             profiling::scope!("hardware_read_async");
 
-            idle_scope.read_async(Duration::from_secs_f64(time_frame), trigger_str, None)
+            idle_scope.read_async(
+                probe_type,
+                Duration::from_secs_f64(acquisition),
+                trigger,
+                Duration::from_secs_f64(delay),
+            )
         };
         let mut fleascope_for_read = match star_res {
             Ok(fleascope_for_read) => fleascope_for_read,
             Err((s, e)) => {
                 tracing::error!("Failed to start read operation: {}", e);
-                return s;
+                return Some(s);
             }
         };
         tracing::debug!("Successfully started read operation on FleaScope");
 
+        // Keep showing TRIG'D while captures follow each other, ARMED once one takes too long
+        let armed_after = Duration::from_secs_f64(acquisition * 2.0) + Duration::from_millis(100);
+        let started = Instant::now();
+        if *self.state_tx.borrow() != AcquisitionState::Triggered {
+            self.state_tx.send_replace(AcquisitionState::Armed);
+        }
+
         loop {
             match fleascope_for_read.try_get_result() {
                 Ok(Ok((scope, reading))) => {
+                    settings.captured_at = Utc::now();
+                    let software = matches!(placement, TriggerPlacement::Software(_));
+                    if !software && !self.single_shot {
+                        self.state_tx.send_replace(AcquisitionState::Triggered);
+                    }
                     let data_copy = self.data.clone();
-                    let running = self.running;
-                    tokio::spawn(async move {
+                    let error_tx = self.error_tx.clone();
+                    let stream_tx = self.stream_tx.clone();
+                    let state_tx = self.state_tx.clone();
+                    let single_shot = self.single_shot;
+                    // A single capture pauses the worker once it is taken
+                    let running = self.running && !single_shot;
+                    // Resolves to whether the trigger fired, the software trigger only knows after processing
+                    let fired = tokio::spawn(async move {
                         profiling::scope!("data_processing_pipeline");
 
-                        let _parse_csv_scope = {
-                            profiling::scope!("parse_csv");
-                            reading
-                                .parse_csv()
-                                .map(|df| {
-                                    profiling::scope!("apply_calibration");
-                                    probe_clone.apply_calibration(df).collect().unwrap()
-                                })
-                                .map(|df| {
-                                    profiling::scope!("convert_to_data_points");
-                                    FleaWorker::convert_polars_to_data_points(df)
-                                })
-                        };
-
-                        _parse_csv_scope
-                            .map(|data_points| {
+                        let processed = FleaWorker::process_reading(&reading, &probe_clone).map(
+                            |(x_values, data_points)| match &placement {
+                                // Sampling started `delay` after the trigger
+                                TriggerPlacement::Hardware { delay } => Some((
+                                    x_values.into_iter().map(|t| t + delay).collect(),
+                                    data_points,
+                                )),
+                                TriggerPlacement::Software(pre_trigger) => {
+                                    pre_trigger.locate(&x_values, &data_points)
+                                }
+                            },
+                        );
+                        match processed {
+                            Ok(Some((x_values, data_points))) => {
                                 profiling::scope!("update_shared_data");
 
+                                if software && !single_shot {
+                                    state_tx.send_replace(AcquisitionState::Triggered);
+                                }
                                 let new_data = DeviceData {
-                                    x_values: data_points.0,
-                                    data_points: data_points.1,
+                                    x_values,
+                                    data_points,
                                     last_update: Instant::now(),
                                     update_rate,
                                     connected: true,
                                     running,
+                                    settings: Some(settings),
                                 };
-                                data_copy.store(Arc::new(new_data));
-                            })
-                            .ok();
+                                let new_data = Arc::new(new_data);
+                                data_copy.store(new_data.clone());
+                                if stream_tx.receiver_count() > 0 {
+                                    let _ = stream_tx.send(StreamFrame::Capture(new_data));
+                                }
+                                true
+                            }
+                            Ok(None) => {
+                                tracing::debug!("No trigger event in the capture, re-arming");
+                                state_tx.send_replace(AcquisitionState::Armed);
+                                false
+                            }
+                            Err(e) => {
+                                // Drop the frame, the next capture is already on its way
+                                tracing::warn!("Dropping triggered capture: {}", e);
+                                let _ = error_tx.send(e);
+                                true
+                            }
+                        }
                     });
-                    return scope;
+                    // Only a single capture has to know whether it is done before the next read
+                    if single_shot && fired.await.unwrap_or(true) {
+                        tracing::info!("Single capture done, pausing FleaWorker");
+                        self.single_shot = false;
+                        self.running = false;
+                        self.state_tx.send_replace(AcquisitionState::Stopped);
+                    }
+                    return Some(scope);
                 }
                 Ok(Err(scope)) => {
                     fleascope_for_read = scope;
                 }
                 Err(_) => {
                     tracing::error!("Error during hardware read: Connection lost");
-                    self.set_lost_connection().await;
-                    panic!("Connection lost during hardware read");
+                    return None;
                 }
             }
             profiling::scope!("hardware_wait_polling_loop");
 
+            if started.elapsed() > armed_after {
+                self.state_tx.send_if_modified(|state| {
+                    let waiting = *state == AcquisitionState::Triggered;
+                    if waiting {
+                        *state = AcquisitionState::Armed;
+                    }
+                    waiting
+                });
+            }
+
             if self.check_settings_changed() {
                 tracing::info!("Settings changed during hardware wait, calling unblock()");
-                return fleascope_for_read.cancel();
+                return Some(fleascope_for_read.cancel());
             }
+
+            // Let the processing tasks and timers run, even with a single runtime thread
+            tokio::task::yield_now().await;
         }
     }
 
+    /// Whether the pending read has to be cancelled; a removed device counts as a change
     fn check_settings_changed(&self) -> bool {
-        if self
-            .config_change_rx
-            .has_changed()
-            .expect("Failed to check for config change")
-        {
+        if self.config_change_rx.has_changed().unwrap_or(true) {
             profiling::scope!("config_change_detected");
 
             tracing::info!("Configuration changed during hardware read, calling unblock()");
             return true;
         }
-        if self
-            .waveform_rx
-            .has_changed()
-            .expect("Failed to check for waveform change")
-        {
+        if self.waveform_rx.has_changed().unwrap_or(true) {
             profiling::scope!("waveform_change_detected");
 
             tracing::info!("Waveform changed during hardware read, calling unblock()");
@@ -427,59 +761,70 @@ impl FleaWorker {
         false
     }
 
-    async fn handle_continuous_capture(
+    async fn handle_continuous_capture<S: ScopeBackend>(
         &mut self,
-        probe: ProbeType,
-        fleascope: IdleFleaScope,
-    ) -> IdleFleaScope {
+        probe_type: ProbeType,
+        fleascope: S,
+    ) -> Option<S> {
         const BUFF_SIZE: usize = 512;
-        let probe = match probe {
+        let probe = match probe_type {
             ProbeType::X1 => &self.x1,
             ProbeType::X10 => &self.x10,
         };
 
-        let mut streaming_scope = fleascope.stream();
+        let mut streaming_scope = fleascope.stream(probe_type);
         let mut start_time = Instant::now();
+        // The recorder marks a gap before the first batch and after a dropped one
+        let mut contiguous = false;
         // let mut total_samples = 0u32;
         loop {
             let batch_result = {
                 profiling::scope!("read_batch_data");
 
-                streaming_scope.read(BUFF_SIZE).map(|buffer| {
-                    // Process the buffer data here
-                    let ca = UInt16Chunked::from_vec(RAW_COLUMN_NAME.into(), buffer);
-                    let column = ca.into_series().into();
-                    let df = DataFrame::new(vec![column]).unwrap();
-                    probe
-                        .apply_calibration(df.lazy())
-                        .collect()
-                        .unwrap()
-                        .column(CALIBRATED_COLUMN_NAME)
-                        .unwrap()
-                        .f64()
-                        .unwrap()
-                        .into_no_null_iter()
-                        .collect()
-                })
+                streaming_scope
+                    .read(BUFF_SIZE)
+                    .map(|buffer| FleaWorker::calibrate_batch(probe, buffer))
             };
 
             match batch_result {
-                Ok(batch) => {
+                Ok(Err(e)) => {
+                    tracing::warn!("Dropping continuous batch: {}", e);
+                    let _ = self.error_tx.send(e);
+                    contiguous = false;
+                    if self.check_settings_changed() {
+                        return Some(streaming_scope.stop());
+                    }
+                }
+                Ok(Ok(batch)) => {
                     {
                         profiling::scope!("send_batch_channel");
 
+                        if let Some(recording) = self.recording_rx.borrow().as_ref() {
+                            // The recorder stops on its own, a closed channel just means it is done
+                            let _ = recording.send(RecordedBatch {
+                                batch: batch.clone(),
+                                received: Instant::now(),
+                                contiguous,
+                            });
+                        }
+                        contiguous = true;
+                        if self.stream_tx.receiver_count() > 0 {
+                            let _ = self
+                                .stream_tx
+                                .send(StreamFrame::Batch(Arc::new(batch.clone())));
+                        }
                         if self.batch_tx.send(batch).is_err() {
                             tracing::warn!("Failed to send batch - receiver may have been dropped");
                         }
                     }
                     if self.check_settings_changed() {
-                        return streaming_scope.stop();
+                        return Some(streaming_scope.stop());
                     }
                 }
                 Err(e) => {
                     tracing::error!("Error reading from continuous stream: {}", e);
-                    self.set_lost_connection().await;
-                    return streaming_scope.stop();
+                    // The port is gone, there is nobody left to send the stop command to
+                    return None;
                 }
             }
             let now = Instant::now();
@@ -491,145 +836,120 @@ impl FleaWorker {
                 BUFF_SIZE as f64 / (now - start_time).as_secs_f64()
             );
             start_time = now;
+            tokio::task::yield_now().await;
         }
     }
 
-    fn convert_polars_to_data_points(df: DataFrame) -> (Vec<f64>, Vec<DataPoint>) {
-        profiling::scope!("FleaWorker::convert_polars_to_data_points");
-
-        tracing::debug!(
-            "Converting DataFrame with columns: {:?}",
-            df.get_column_names()
-        );
-        tracing::debug!(
-            "DataFrame shape: {} rows, {} columns",
-            df.height(),
-            df.width()
-        );
-
-        // Extract columns from the DataFrame
-        profiling::scope!("extract_dataframe_columns");
-
-        let time_col = match df.column(TIME_COLUMN_NAME) {
-            Ok(col) => col,
-            Err(e) => {
-                tracing::error!("Failed to get time column: {}", e);
-                panic!("Time column not found in DataFrame");
-            }
-        };
-
-        let bnc_col = match df.column(CALIBRATED_COLUMN_NAME) {
-            Ok(col) => col,
-            Err(e) => {
-                tracing::error!("Failed to get bnc column: {}", e);
-                panic!("BNC column not found in DataFrame");
-            }
-        };
-
-        let bitmap_col = match df.column(BITMAP_COLUMN_NAME) {
-            Ok(col) => col,
-            Err(e) => {
-                tracing::error!("Failed to get bitmap column: {}", e);
-                panic!("Bitmap column not found in DataFrame");
-            }
-        };
-
-        let time_values: Vec<f64> = match time_col.f64() {
-            Ok(chunked) => chunked.into_no_null_iter().collect(),
-            Err(e) => {
-                tracing::error!("Failed to convert time column to f64: {}", e);
-                panic!("Time column conversion failed");
-            }
+    /// Parse and calibrate a triggered capture
+    fn process_reading(
+        reading: &ScopeReading,
+        probe: &FleaProbe,
+    ) -> Result<(Vec<f64>, Vec<DataPoint>), CaptureError> {
+        let df = {
+            profiling::scope!("parse_csv");
+            reading.parse_csv().map_err(CaptureError::Parse)?
         };
-
-        let bnc_values: Vec<f64> = match bnc_col.f64() {
-            Ok(chunked) => chunked.into_no_null_iter().collect(),
-            Err(e) => {
-                tracing::error!("Failed to convert bnc column to f64: {}", e);
-                panic!("BNC column conversion failed");
-            }
+        let df = {
+            profiling::scope!("apply_calibration");
+            probe
+                .apply_calibration(df)
+                .collect()
+                .map_err(CaptureError::Calibration)?
         };
+        FleaWorker::convert_polars_to_data_points(df)
+    }
 
-        // Convert bitmap column - handle both string and numeric formats
-        let bitmap_values: Vec<u16> = if bitmap_col.dtype() == &polars::datatypes::DataType::String
-        {
-            profiling::scope!("parse_bitmap_strings");
+    /// Calibrate a batch of raw continuous samples
+    fn calibrate_batch(probe: &FleaProbe, raw: RawBatch) -> Result<ContinuousBatch, CaptureError> {
+        profiling::scope!("FleaWorker::calibrate_batch");
+
+        let column = UInt16Chunked::from_vec(RAW_COLUMN_NAME.into(), raw.adc)
+            .into_series()
+            .into();
+        let df = DataFrame::new(vec![column]).map_err(CaptureError::Calibration)?;
+        let df = probe
+            .apply_calibration(df.lazy())
+            .collect()
+            .map_err(CaptureError::Calibration)?;
+        Ok(ContinuousBatch {
+            analog: float_column(&df, CALIBRATED_COLUMN_NAME)?,
+            bitmap: raw.bitmap,
+        })
+    }
 
-            // Handle string bitmap data (e.g., "0x1ff", "0101010101", or "255")
-            // TODO maybe use the fleascope-rs function
-            match bitmap_col.str() {
-                Ok(chunked) => {
-                    let mut values = Vec::new();
-                    for opt_str in chunked.into_iter() {
-                        match opt_str {
-                            Some(s) => {
-                                // Hexadecimal string like "0x1ff"
-                                match u16::from_str_radix(&s[2..], 16) {
-                                    Ok(val) => values.push(val),
-                                    Err(e) => {
-                                        tracing::error!(
-                                            "Failed to parse hex string '{}': {}",
-                                            s,
-                                            e
-                                        );
-                                        panic!("Invalid bitmap hex string");
-                                    }
-                                }
-                            }
-                            None => {
-                                tracing::error!("Found null bitmap value");
-                                panic!("Null bitmap value encountered");
-                            }
-                        }
-                    }
-                    values
-                }
-                Err(e) => {
-                    tracing::error!("Failed to convert bitmap column to string: {}", e);
-                    panic!("Bitmap column conversion failed");
-                }
-            }
-        } else {
-            panic!("Bitmap column is not a string type, expected string or numeric format");
-        };
+    fn convert_polars_to_data_points(
+        df: DataFrame,
+    ) -> Result<(Vec<f64>, Vec<DataPoint>), CaptureError> {
+        profiling::scope!("FleaWorker::convert_polars_to_data_points");
 
         tracing::debug!(
-            "Extracted {} time values, {} BNC values, {} bitmap values",
-            time_values.len(),
-            bnc_values.len(),
-            bitmap_values.len()
+            "Converting DataFrame with columns: {:?}, {} rows",
+            df.get_column_names(),
+            df.height()
         );
 
-        tracing::debug!(
-            "Successfully converted DataFrame to vectors, processing {} data points",
-            time_values.len()
-        );
+        let x_values = float_column(&df, TIME_COLUMN_NAME)?;
+        let bnc_values = float_column(&df, CALIBRATED_COLUMN_NAME)?;
+        let bitmap_values = {
+            profiling::scope!("parse_bitmap_strings");
+            bitmap_column(&df)?
+        };
 
         profiling::scope!("create_data_points_from_vectors");
 
-        let mut x_values = Vec::new();
-        let mut data_points = Vec::new();
-
-        for ((time, bnc), bitmap) in time_values
+        let data_points = bnc_values
             .iter()
-            .zip(bnc_values.iter())
-            .zip(bitmap_values.iter())
-        {
-            x_values.push(*time);
-
-            // Extract digital channels from bitmap
-            let mut digital_channels = [false; 9];
-            for (i, ch) in digital_channels.iter_mut().enumerate() {
-                *ch = (bitmap & (1 << i)) != 0;
-            }
-
-            data_points.push(DataPoint {
-                analog_channel: *bnc,
-                digital_channels,
-            });
-        }
+            .zip(&bitmap_values)
+            .map(|(&bnc, &bitmap)| {
+                let mut digital_channels = [false; 9];
+                for (i, ch) in digital_channels.iter_mut().enumerate() {
+                    *ch = (bitmap & (1 << i)) != 0;
+                }
+                DataPoint {
+                    analog_channel: bnc,
+                    digital_channels,
+                }
+            })
+            .collect::<Vec<_>>();
 
         tracing::debug!("Converted to {} data points", data_points.len());
-        (x_values, data_points)
+        Ok((x_values, data_points))
+    }
+}
+
+fn float_column(df: &DataFrame, name: &'static str) -> Result<Vec<f64>, CaptureError> {
+    let column = df
+        .column(name)
+        .map_err(|_| CaptureError::MissingColumn(name))?;
+    let values = column.f64().map_err(|_| CaptureError::ColumnType {
+        column: name,
+        found: column.dtype().to_string(),
+        expected: "f64",
+    })?;
+    if values.null_count() > 0 {
+        return Err(CaptureError::NullValue(name));
     }
+    Ok(values.into_no_null_iter().collect())
+}
+
+/// Digital channel states, sent by the firmware as hex strings like "0x1ff"
+fn bitmap_column(df: &DataFrame) -> Result<Vec<u16>, CaptureError> {
+    let column = df
+        .column(BITMAP_COLUMN_NAME)
+        .map_err(|_| CaptureError::MissingColumn(BITMAP_COLUMN_NAME))?;
+    let values = column.str().map_err(|_| CaptureError::ColumnType {
+        column: BITMAP_COLUMN_NAME,
+        found: column.dtype().to_string(),
+        expected: "string",
+    })?;
+    values
+        .into_iter()
+        .map(|value| {
+            let value = value.ok_or(CaptureError::NullValue(BITMAP_COLUMN_NAME))?;
+            value
+                .strip_prefix("0x")
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| CaptureError::InvalidBitmap(value.to_string()))
+        })
+        .collect()
 }
@@ -0,0 +1,280 @@
+use crate::decoder::DecodedFrame;
+use crate::device::DataPoint;
+
+#[derive(Debug, Clone)]
+pub struct I2cConfig {
+    pub scl: usize,
+    pub sda: usize,
+}
+
+impl Default for I2cConfig {
+    fn default() -> Self {
+        Self { scl: 0, sda: 1 }
+    }
+}
+
+/// Byte currently being clocked in, eight data bits and the acknowledge bit
+struct Byte {
+    /// SCL rising edge of the first bit
+    start: f64,
+    bits: Vec<bool>,
+}
+
+/// Decode start, repeated start and stop conditions and the address and data bytes in between
+pub fn decode(config: &I2cConfig, time: &[f64], points: &[DataPoint]) -> Vec<DecodedFrame> {
+    profiling::scope!("i2c_decoder::decode");
+
+    let n = time.len().min(points.len());
+    if n < 2 {
+        return Vec::new();
+    }
+    let scl = |i: usize| points[i].digital_channels[config.scl];
+    let sda = |i: usize| points[i].digital_channels[config.sda];
+    let frame =
+        |start: f64, end: f64, value: &str, info: String, error: Option<&str>| DecodedFrame {
+            start,
+            end,
+            channel: config.sda,
+            value: value.to_string(),
+            info,
+            error: error.map(str::to_string),
+        };
+
+    let mut frames = Vec::new();
+    let mut in_transfer = false;
+    // Start condition waiting for the first bit of the address byte, to span up to it
+    let mut pending_start: Option<(f64, &str)> = None;
+    let mut expect_address = false;
+    let mut byte: Option<Byte> = None;
+    // End of the last byte, where a stop condition frame begins
+    let mut last_end = time[0];
+
+    for i in 1..n {
+        let edge = (time[i - 1] + time[i]) / 2.0;
+
+        // SDA changing while SCL stays high is a start or stop condition
+        if scl(i - 1) && scl(i) && sda(i - 1) != sda(i) {
+            // A lone bit is the SCL rise that precedes every stop and repeated start
+            if let Some(partial) = byte.take().filter(|partial| partial.bits.len() > 1) {
+                frames.push(frame(
+                    partial.start,
+                    edge,
+                    "?",
+                    format!("{} bits", partial.bits.len()),
+                    Some("incomplete"),
+                ));
+            }
+            if sda(i) {
+                if in_transfer {
+                    frames.push(frame(last_end.min(edge), edge, "P", "Stop".into(), None));
+                }
+                in_transfer = false;
+                pending_start = None;
+            } else {
+                let kind = if in_transfer { "Sr" } else { "S" };
+                pending_start = Some((edge, kind));
+                in_transfer = true;
+                expect_address = true;
+            }
+            continue;
+        }
+
+        if !in_transfer || scl(i - 1) || !scl(i) {
+            continue;
+        }
+
+        // SCL rising edge, SDA is stable and holds the next bit
+        if let Some((start, kind)) = pending_start.take() {
+            let info = if kind == "S" {
+                "Start"
+            } else {
+                "Repeated start"
+            };
+            frames.push(frame(start, edge, kind, info.into(), None));
+        }
+        let current = byte.get_or_insert_with(|| Byte {
+            start: edge,
+            bits: Vec::with_capacity(9),
+        });
+        current.bits.push(sda(i));
+        if current.bits.len() < 9 {
+            continue;
+        }
+
+        let done = byte.take().expect("byte was just filled");
+        let value = done.bits[..8]
+            .iter()
+            .fold(0u8, |acc, &bit| acc << 1 | bit as u8);
+        let ack = if done.bits[8] { "NACK" } else { "ACK" };
+        if expect_address {
+            expect_address = false;
+            let direction = if value & 1 == 1 { "Read" } else { "Write" };
+            frames.push(frame(
+                done.start,
+                edge,
+                &format!("0x{:02X}", value >> 1),
+                format!("{} {}", direction, ack),
+                None,
+            ));
+        } else {
+            frames.push(frame(
+                done.start,
+                edge,
+                &format!("0x{:02X}", value),
+                ack.to_string(),
+                None,
+            ));
+        }
+        last_end = edge;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SCL and SDA levels, one sample per bus state change
+    #[derive(Default)]
+    struct Bus {
+        levels: Vec<(bool, bool)>,
+    }
+
+    impl Bus {
+        fn set(&mut self, scl: bool, sda: bool) {
+            self.levels.push((scl, sda));
+        }
+
+        fn start(&mut self) {
+            self.set(true, true);
+            self.set(true, false);
+            self.set(false, false);
+        }
+
+        fn repeated_start(&mut self) {
+            self.set(false, true);
+            self.start();
+        }
+
+        fn stop(&mut self) {
+            self.set(false, false);
+            self.set(true, false);
+            self.set(true, true);
+        }
+
+        fn bit(&mut self, sda: bool) {
+            self.set(false, sda);
+            self.set(true, sda);
+            self.set(false, sda);
+        }
+
+        /// Eight bits MSB first and the acknowledge bit, high for NACK
+        fn byte(&mut self, value: u8, nack: bool) {
+            for position in (0..8).rev() {
+                self.bit(value >> position & 1 == 1);
+            }
+            self.bit(nack);
+        }
+
+        fn decode(&self) -> Vec<DecodedFrame> {
+            let config = I2cConfig::default();
+            let mut levels = vec![(true, true); 2];
+            levels.extend(&self.levels);
+            levels.extend([(true, true); 2]);
+            let points: Vec<DataPoint> = levels
+                .iter()
+                .map(|&(scl, sda)| {
+                    let mut digital_channels = [false; 9];
+                    digital_channels[config.scl] = scl;
+                    digital_channels[config.sda] = sda;
+                    DataPoint {
+                        analog_channel: 0.0,
+                        digital_channels,
+                    }
+                })
+                .collect();
+            let time: Vec<f64> = (0..points.len()).map(|i| i as f64 * 1e-6).collect();
+            decode(&config, &time, &points)
+        }
+    }
+
+    fn summary(frames: &[DecodedFrame]) -> Vec<(String, String)> {
+        frames
+            .iter()
+            .map(|f| (f.value.clone(), f.info.clone()))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|(value, info)| (value.to_string(), info.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_register_read_with_repeated_start() {
+        let mut bus = Bus::default();
+        bus.start();
+        bus.byte(0x50 << 1, false);
+        bus.byte(0x12, false);
+        bus.repeated_start();
+        bus.byte(0x50 << 1 | 1, false);
+        bus.byte(0x34, true);
+        bus.stop();
+        let frames = bus.decode();
+
+        assert_eq!(
+            summary(&frames),
+            pairs(&[
+                ("S", "Start"),
+                ("0x50", "Write ACK"),
+                ("0x12", "ACK"),
+                ("Sr", "Repeated start"),
+                ("0x50", "Read ACK"),
+                ("0x34", "NACK"),
+                ("P", "Stop"),
+            ])
+        );
+        assert!(frames.iter().all(|f| f.error.is_none()));
+    }
+
+    #[test]
+    fn reports_unacknowledged_address() {
+        let mut bus = Bus::default();
+        bus.start();
+        bus.byte(0x3C << 1, true);
+        bus.stop();
+
+        assert_eq!(
+            summary(&bus.decode()),
+            pairs(&[("S", "Start"), ("0x3C", "Write NACK"), ("P", "Stop")])
+        );
+    }
+
+    #[test]
+    fn flags_bytes_cut_by_stop() {
+        let mut bus = Bus::default();
+        bus.start();
+        bus.byte(0x50 << 1, false);
+        for _ in 0..4 {
+            bus.bit(true);
+        }
+        bus.stop();
+        let frames = bus.decode();
+
+        let cut = frames.iter().find(|f| f.value == "?").unwrap();
+        assert_eq!(cut.error.as_deref(), Some("incomplete"));
+        assert_eq!(frames.last().unwrap().value, "P");
+    }
+
+    #[test]
+    fn ignores_traffic_before_the_first_start() {
+        let mut bus = Bus::default();
+        bus.set(false, true);
+        bus.byte(0xFF, true);
+        bus.set(true, true);
+
+        assert!(bus.decode().is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::continuous_buffer::ContinuousBatch;
+use crate::device::{DeviceData, DeviceManager, CONTINUOUS_SAMPLE_RATE_HZ};
+use crate::websocket::{self, Message};
+use crate::worker_interface::FleaScopeDevice;
+
+/// Frames a device buffers for its subscribers, a subscriber further behind skips ahead
+pub const STREAM_CAPACITY: usize = 16;
+
+/// Encoded frames waiting for a client's socket, newer ones are dropped while it is full
+const CLIENT_QUEUE_LENGTH: usize = 32;
+
+const DIGITAL_CHANNELS: usize = 9;
+
+/// What a device publishes to its subscribers
+#[derive(Clone)]
+pub enum StreamFrame {
+    /// A complete triggered capture
+    Capture(Arc<DeviceData>),
+    /// Samples from continuous mode, sampled at `CONTINUOUS_SAMPLE_RATE_HZ`
+    Batch(Arc<ContinuousBatch>),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    List,
+    Subscribe {
+        device: String,
+        #[serde(default)]
+        channels: Option<Vec<String>>,
+        #[serde(default)]
+        format: Format,
+    },
+    Unsubscribe {
+        device: String,
+    },
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Json,
+    Binary,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Channel {
+    Analog,
+    Digital(usize),
+}
+
+impl Channel {
+    fn parse(name: &str) -> Result<Self> {
+        let name = name.to_ascii_lowercase();
+        if name == "analog" {
+            return Ok(Channel::Analog);
+        }
+        name.strip_prefix('d')
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n < DIGITAL_CHANNELS)
+            .map(Channel::Digital)
+            .with_context(|| format!("Unknown channel '{}', expected analog or d0..d8", name))
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Channel::Analog => "analog".to_string(),
+            Channel::Digital(n) => format!("d{}", n),
+        }
+    }
+
+    /// Samples of this channel in a continuous batch, `None` if the stream does not carry it
+    fn batch_values(&self, batch: &ContinuousBatch) -> Option<Vec<f64>> {
+        match self {
+            Channel::Analog => Some(batch.analog.clone()),
+            Channel::Digital(n) => batch
+                .bitmap
+                .as_ref()
+                .map(|bitmap| bitmap.iter().map(|bits| ((bits >> n) & 1) as f64).collect()),
+        }
+    }
+
+    fn values(&self, data: &DeviceData) -> Vec<f64> {
+        data.data_points
+            .iter()
+            .map(|p| match self {
+                Channel::Analog => p.analog_channel,
+                Channel::Digital(n) => p.digital_channels[*n] as u8 as f64,
+            })
+            .collect()
+    }
+}
+
+struct Subscription {
+    device: String,
+    channels: Vec<Channel>,
+    format: Format,
+}
+
+/// Bind the streaming server, the GUI shares `device_manager` with it
+pub fn start(addr: SocketAddr, device_manager: Arc<Mutex<DeviceManager>>) -> Result<()> {
+    let listener = std::net::TcpListener::bind(addr)
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    listener.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(listener)?;
+    tracing::info!("Live stream listening on {}", addr);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let device_manager = device_manager.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_client(stream, device_manager).await {
+                            tracing::info!("Live stream client {} disconnected: {:#}", peer, e);
+                        } else {
+                            tracing::info!("Live stream client {} disconnected", peer);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Failed to accept live stream client: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn handle_client(stream: TcpStream, device_manager: Arc<Mutex<DeviceManager>>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    websocket::accept(&mut reader, &mut writer).await?;
+    let mut messages = websocket::MessageReader::new(reader);
+
+    // A single writer task owns the socket, subscriptions only ever try_send to it
+    let (out_tx, mut out_rx) = mpsc::channel::<Vec<u8>>(CLIENT_QUEUE_LENGTH);
+    let writer_task = tokio::spawn(async move {
+        while let Some(frame) = out_rx.recv().await {
+            if writer.write_all(&frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut subscriptions: HashMap<String, JoinHandle<()>> = HashMap::new();
+    let result = async {
+        loop {
+            let reply = match messages.read().await? {
+                Message::Text(text) => {
+                    handle_request(&text, &device_manager, &mut subscriptions, &out_tx)
+                        .await
+                        .unwrap_or_else(|e| json!({"type": "error", "message": format!("{:#}", e)}))
+                }
+                Message::Binary => {
+                    json!({"type": "error", "message": "Requests are JSON text messages"})
+                }
+                Message::Ping(payload) => {
+                    out_tx.send(websocket::pong_frame(&payload)).await?;
+                    continue;
+                }
+                Message::Close => {
+                    let _ = out_tx.send(websocket::close_frame()).await;
+                    return Ok(());
+                }
+            };
+            // Replies wait for room in the queue, only stream frames are dropped
+            out_tx
+                .send(websocket::text_frame(&reply.to_string()))
+                .await?;
+        }
+    }
+    .await;
+
+    for (_, task) in subscriptions {
+        task.abort();
+    }
+    drop(out_tx);
+    let _ = writer_task.await;
+    result
+}
+
+async fn handle_request(
+    text: &str,
+    device_manager: &Arc<Mutex<DeviceManager>>,
+    subscriptions: &mut HashMap<String, JoinHandle<()>>,
+    out_tx: &mpsc::Sender<Vec<u8>>,
+) -> Result<Value> {
+    let request: Request = serde_json::from_str(text).context("Invalid request")?;
+    match request {
+        Request::List => {
+            let manager = device_manager.lock().await;
+            let devices: Vec<Value> = manager
+                .get_devices()
+                .iter()
+                .enumerate()
+                .map(|(index, device)| json!({"index": index, "name": device.name}))
+                .collect();
+            Ok(json!({"type": "devices", "devices": devices}))
+        }
+        Request::Subscribe {
+            device,
+            channels,
+            format,
+        } => {
+            let channels = match channels {
+                Some(names) => names
+                    .iter()
+                    .map(|name| Channel::parse(name))
+                    .collect::<Result<Vec<_>>>()?,
+                None => std::iter::once(Channel::Analog)
+                    .chain((0..DIGITAL_CHANNELS).map(Channel::Digital))
+                    .collect(),
+            };
+            let (name, rx) = {
+                let manager = device_manager.lock().await;
+                let device = find_device(&manager, &device)?;
+                (device.name.clone(), device.subscribe_stream())
+            };
+            let reply = json!({
+                "type": "subscribed",
+                "device": name,
+                "channels": channels.iter().map(Channel::name).collect::<Vec<_>>(),
+            });
+            let subscription = Subscription {
+                device: name.clone(),
+                channels,
+                format,
+            };
+            let task = tokio::spawn(forward(subscription, rx, out_tx.clone()));
+            if let Some(previous) = subscriptions.insert(name, task) {
+                previous.abort();
+            }
+            Ok(reply)
+        }
+        Request::Unsubscribe { device } => {
+            let name = {
+                let manager = device_manager.lock().await;
+                find_device(&manager, &device)
+                    .map(|device| device.name.clone())
+                    .unwrap_or(device)
+            };
+            let task = subscriptions
+                .remove(&name)
+                .with_context(|| format!("Not subscribed to '{}'", name))?;
+            task.abort();
+            Ok(json!({"type": "unsubscribed", "device": name}))
+        }
+    }
+}
+
+/// A device by its name or its index in the device list
+fn find_device<'a>(manager: &'a DeviceManager, device: &str) -> Result<&'a FleaScopeDevice> {
+    let devices = manager.get_devices();
+    devices
+        .iter()
+        .find(|d| d.name == device)
+        .or_else(|| device.parse::<usize>().ok().and_then(|i| devices.get(i)))
+        .with_context(|| format!("No device '{}'", device))
+}
+
+/// Pass a device's frames on to one client until it unsubscribes or the device is removed
+async fn forward(
+    subscription: Subscription,
+    mut rx: broadcast::Receiver<StreamFrame>,
+    out_tx: mpsc::Sender<Vec<u8>>,
+) {
+    let mut dropped = 0u64;
+    loop {
+        match rx.recv().await {
+            Ok(frame) => {
+                let Some(message) = encode(&subscription, &frame) else {
+                    continue;
+                };
+                match out_tx.try_send(message) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => dropped += 1,
+                    Err(TrySendError::Closed(_)) => return,
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => dropped += skipped,
+            Err(RecvError::Closed) => {
+                let notice = json!({"type": "closed", "device": subscription.device});
+                let _ = out_tx.try_send(websocket::text_frame(&notice.to_string()));
+                return;
+            }
+        }
+
+        if dropped > 0 {
+            let notice =
+                json!({"type": "dropped", "device": subscription.device, "frames": dropped});
+            if out_tx
+                .try_send(websocket::text_frame(&notice.to_string()))
+                .is_ok()
+            {
+                dropped = 0;
+            }
+        }
+    }
+}
+
+/// Encode a frame with the subscribed channels, `None` if it carries none of them
+fn encode(subscription: &Subscription, frame: &StreamFrame) -> Option<Vec<u8>> {
+    profiling::scope!("live_stream::encode");
+
+    let (kind, columns): (&str, Vec<(String, Vec<f64>)>) = match frame {
+        StreamFrame::Capture(data) => (
+            "capture",
+            std::iter::once(("time".to_string(), data.x_values.clone()))
+                .chain(
+                    subscription
+                        .channels
+                        .iter()
+                        .map(|channel| (channel.name(), channel.values(data))),
+                )
+                .collect(),
+        ),
+        // Digital channels are only there if the device streams them
+        StreamFrame::Batch(batch) => {
+            let columns: Vec<_> = subscription
+                .channels
+                .iter()
+                .filter_map(|channel| Some((channel.name(), channel.batch_values(batch)?)))
+                .collect();
+            if columns.is_empty() {
+                return None;
+            }
+            ("batch", columns)
+        }
+    };
+    let samples = columns.first().map_or(0, |(_, values)| values.len());
+
+    let mut header = Map::new();
+    header.insert("type".into(), kind.into());
+    header.insert("device".into(), subscription.device.clone().into());
+    header.insert("samples".into(), samples.into());
+    if matches!(frame, StreamFrame::Batch(_)) {
+        header.insert("sample_rate_hz".into(), CONTINUOUS_SAMPLE_RATE_HZ.into());
+    }
+
+    match subscription.format {
+        Format::Json => {
+            for (name, values) in columns {
+                header.insert(name, values.into());
+            }
+            Some(websocket::text_frame(&Value::Object(header).to_string()))
+        }
+        Format::Binary => {
+            header.insert(
+                "channels".into(),
+                columns.iter().map(|(name, _)| name.clone()).collect(),
+            );
+            let header = Value::Object(header).to_string();
+            let mut data = Vec::with_capacity(4 + header.len() + columns.len() * samples * 8);
+            data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+            data.extend_from_slice(header.as_bytes());
+            for (_, values) in columns {
+                for value in values {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Some(websocket::binary_frame(&data))
+        }
+    }
+}
@@ -2,17 +2,43 @@ use eframe::egui;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+mod acquisition_mode;
+mod capture_error;
+mod capture_export;
+mod cli;
+mod combined_view;
+mod continuous_buffer;
 mod control_panel;
+mod cursors;
+mod decoder;
 mod device;
 mod device_worker;
+mod i2c_decoder;
+mod live_stream;
+mod math_channel;
+mod measurements;
 mod notifications;
+mod persistence;
 mod plot_area;
+mod pre_trigger;
+mod recorder;
+mod reference;
+mod remote;
+mod replay;
+mod session;
+mod simulator;
+mod spectrum;
+mod spi_decoder;
+mod uart_decoder;
+mod websocket;
 mod worker_interface;
 
 use control_panel::ControlPanel;
 use device::DeviceManager;
 use notifications::NotificationManager;
 use plot_area::PlotArea;
+use replay::{OpenCaptureDialog, Replay};
+use session::{DeviceSession, Session, SESSION_KEY};
 
 #[derive(Default)]
 pub struct FleaScopeApp {
@@ -20,18 +46,116 @@ pub struct FleaScopeApp {
     plot_area: PlotArea,
     control_panel: ControlPanel,
     notification_manager: NotificationManager,
+    open_capture_dialog: OpenCaptureDialog,
+    /// Where the remote control server listens, if it runs
+    remote_address: Option<std::net::SocketAddr>,
+    /// Where the live stream server listens, if it runs
+    stream_address: Option<std::net::SocketAddr>,
 }
 
 impl FleaScopeApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-        // Restore app state using cc.storage (requires the "persistence" feature).
-        Self::default()
+        let mut app = Self::default();
+        app.plot_area.load_references();
+        if let Some(session) = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<Session>(storage, SESSION_KEY))
+        {
+            app.restore_session(session);
+        }
+        app
+    }
+
+    /// Reconnect the devices of the last session and reapply their settings
+    fn restore_session(&mut self, session: Session) {
+        self.plot_area.apply_settings(&session.plot);
+
+        let Ok(mut manager) = self.device_manager.try_lock() else {
+            tracing::error!("Device manager busy, not restoring session");
+            return;
+        };
+        for saved in session.devices {
+            // Start the worker with the saved capture settings instead of the defaults
+            match manager.add_device_with_config(saved.hostname.clone(), saved.capture_config()) {
+                Ok(()) => {
+                    if let Some(device) = manager.get_devices_mut().last_mut() {
+                        saved.apply_to(device);
+                    }
+                    self.notification_manager
+                        .add_success(format!("Reconnected to device: {}", saved.hostname));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Device {} from last session unavailable: {}",
+                        saved.hostname,
+                        e
+                    );
+                    self.notification_manager.add_error(format!(
+                        "Device {} from last session is not available: {}",
+                        saved.hostname, e
+                    ));
+                }
+            }
+        }
+    }
+
+    fn start_remote(&mut self, addr: std::net::SocketAddr) {
+        match remote::start(addr, self.device_manager.clone()) {
+            Ok(()) => {
+                self.remote_address = Some(addr);
+                self.notification_manager
+                    .add_info(format!("Remote control listening on {}", addr));
+            }
+            Err(e) => {
+                tracing::error!("Failed to start remote control: {:#}", e);
+                self.notification_manager
+                    .add_error(format!("Remote control unavailable: {:#}", e));
+            }
+        }
+    }
+
+    fn start_stream(&mut self, addr: std::net::SocketAddr) {
+        match live_stream::start(addr, self.device_manager.clone()) {
+            Ok(()) => {
+                self.stream_address = Some(addr);
+                self.notification_manager
+                    .add_info(format!("Live stream listening on ws://{}", addr));
+            }
+            Err(e) => {
+                tracing::error!("Failed to start live stream: {:#}", e);
+                self.notification_manager
+                    .add_error(format!("Live stream unavailable: {:#}", e));
+            }
+        }
+    }
+
+    fn session(&self) -> Option<Session> {
+        let manager = self.device_manager.try_lock().ok()?;
+        Some(Session {
+            devices: manager
+                .get_devices()
+                .iter()
+                .map(DeviceSession::capture)
+                .collect(),
+            plot: self.plot_area.settings(),
+        })
     }
 }
 
 impl eframe::App for FleaScopeApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        match self.session() {
+            Some(session) => eframe::set_value(storage, SESSION_KEY, &session),
+            None => tracing::debug!("Device manager busy, skipping session save"),
+        }
+    }
+
+    /// Storage only touches the disk when the session actually changed, so save often
+    fn auto_save_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(1)
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         profiling::finish_frame!();
 
@@ -47,6 +171,20 @@ impl eframe::App for FleaScopeApp {
             profiling::scope!("top_menu_bar");
             egui::MenuBar::new().ui(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    if ui.button("Open capture...").clicked() {
+                        self.open_capture_dialog.show();
+                    }
+                    if ui.button("Save captures").clicked() {
+                        if let Ok(manager) = self.device_manager.try_lock() {
+                            if manager.get_devices().is_empty() {
+                                self.notification_manager.add_error("No devices connected");
+                            }
+                            for device in manager.get_devices() {
+                                ControlPanel::save_capture(device, &mut self.notification_manager);
+                            }
+                        }
+                    }
+                    ui.separator();
                     if ui.button("Exit").clicked() {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
@@ -99,6 +237,14 @@ impl eframe::App for FleaScopeApp {
 
                 ui.label(format!("Devices: {}", device_count));
                 ui.separator();
+                if let Some(addr) = self.remote_address {
+                    ui.label(format!("Remote: {}", addr));
+                    ui.separator();
+                }
+                if let Some(addr) = self.stream_address {
+                    ui.label(format!("Stream: ws://{}", addr));
+                    ui.separator();
+                }
                 ui.label(format!("FPS: {:.1}", ctx.input(|i| i.stable_dt).recip()));
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -157,6 +303,24 @@ impl eframe::App for FleaScopeApp {
             });
         });
 
+        if let Some(path) = self.open_capture_dialog.ui(ctx) {
+            match Replay::open(&path) {
+                Ok(replay) => {
+                    self.notification_manager
+                        .add_success(format!("Opened {}", path.display()));
+                    self.plot_area.open_replay(replay);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to open {}: {:#}", path.display(), e);
+                    self.notification_manager.add_error(format!(
+                        "Failed to open {}: {:#}",
+                        path.display(),
+                        e
+                    ));
+                }
+            }
+        }
+
         // Render notifications (always last, so they appear on top)
         profiling::scope!("notifications_render");
         self.notification_manager.ui(ctx);
@@ -168,6 +332,21 @@ async fn main() -> eframe::Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
+    let (remote, stream) = match cli::parse() {
+        Ok(cli::Command::Gui { remote, stream }) => (remote, stream),
+        Ok(command) => {
+            if let Err(e) = cli::run(command).await {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
     // Register main thread for profiling
     profiling::register_thread!("Main Thread");
 
@@ -186,7 +365,14 @@ async fn main() -> eframe::Result<()> {
             // This gives us image support:
             egui_extras::install_image_loaders(&cc.egui_ctx);
 
-            Ok(Box::new(FleaScopeApp::new(cc)))
+            let mut app = FleaScopeApp::new(cc);
+            if let Some(addr) = remote {
+                app.start_remote(addr);
+            }
+            if let Some(addr) = stream {
+                app.start_stream(addr);
+            }
+            Ok(Box::new(app))
         }),
     )
 }
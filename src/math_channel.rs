@@ -0,0 +1,735 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::device::{AnalogSamples, DeviceData};
+
+/// Time and values of an evaluated math channel
+pub type MathTrace = Arc<(Vec<f64>, Vec<f64>)>;
+
+/// Derived trace defined by an expression over the channels of one or more devices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MathChannel {
+    pub name: String,
+    pub expression: String,
+    pub unit: String,
+    /// Device whose time base is used and whose channels unqualified names refer to
+    pub device: String,
+    pub color: [u8; 3],
+    /// `expression` and what it parsed to, parsed again only when the text changes
+    #[serde(skip)]
+    parsed: Option<(String, Result<Expr, String>)>,
+    #[serde(skip)]
+    result: Option<CachedTrace>,
+}
+
+/// Last result with the device and the sources it was computed from
+#[derive(Debug, Clone)]
+struct CachedTrace {
+    device: String,
+    /// Kept alive so a new source can never reuse the allocation of a cached one
+    inputs: Vec<(String, SourceData)>,
+    trace: MathTrace,
+}
+
+/// Samples of one device that expressions can refer to, shared with the plots instead of copied
+#[derive(Debug, Clone)]
+pub enum SourceData {
+    /// A triggered capture
+    Capture(Arc<DeviceData>),
+    /// Time and volts of the continuous window, digital channels are not available
+    Continuous(AnalogSamples),
+}
+
+impl SourceData {
+    fn time(&self) -> &[f64] {
+        match self {
+            SourceData::Capture(data) => &data.x_values,
+            SourceData::Continuous(samples) => &samples.0,
+        }
+    }
+
+    fn analog(&self) -> Vec<f64> {
+        match self {
+            SourceData::Capture(data) => {
+                data.data_points.iter().map(|p| p.analog_channel).collect()
+            }
+            SourceData::Continuous(samples) => samples.1.clone(),
+        }
+    }
+
+    fn digital(&self, channel: usize) -> Option<Vec<f64>> {
+        match self {
+            SourceData::Capture(data) => Some(
+                data.data_points
+                    .iter()
+                    .map(|p| from_bool(p.digital_channels[channel]))
+                    .collect(),
+            ),
+            SourceData::Continuous(_) => None,
+        }
+    }
+
+    /// Whether both are the same samples, a new `Arc` is made whenever the samples change
+    fn same(&self, other: &SourceData) -> bool {
+        match (self, other) {
+            (SourceData::Capture(a), SourceData::Capture(b)) => Arc::ptr_eq(a, b),
+            (SourceData::Continuous(a), SourceData::Continuous(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signal {
+    Time,
+    Analog,
+    Digital(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Integrate,
+    Derivative,
+    Abs,
+    Sqrt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Less,
+    Greater,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Signal {
+        device: Option<String>,
+        signal: Signal,
+    },
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    /// Quoted device name, for hostnames like `sim://sine`
+    Quoted(String),
+    Symbol(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Exponent, e.g. 1e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text
+                .parse()
+                .map_err(|_| anyhow!("Invalid number '{}'", text))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '"' {
+            let start = i + 1;
+            let end = chars[start..]
+                .iter()
+                .position(|&c| c == '"')
+                .map(|len| start + len)
+                .ok_or_else(|| anyhow!("Unterminated device name"))?;
+            tokens.push(Token::Quoted(chars[start..end].iter().collect()));
+            i = end + 1;
+        } else if "+-*/()<>&|!.".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            bail!("Unexpected '{}'", c);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, lowest precedence first: `|`, `&`, `< >`, `+ -`, `* /`, unary `- !`
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<()> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            bail!("Expected '{}'", symbol)
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[(char, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut left = operand(self)?;
+        'outer: loop {
+            for (symbol, op) in operators {
+                if self.eat(*symbol) {
+                    left = Expr::Binary(*op, Box::new(left), Box::new(operand(self)?));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.binary(&[('|', BinaryOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.binary(&[('&', BinaryOp::And)], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        self.binary(
+            &[('<', BinaryOp::Less), ('>', BinaryOp::Greater)],
+            Self::sum,
+        )
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        self.binary(&[('+', BinaryOp::Add), ('-', BinaryOp::Sub)], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        self.binary(&[('*', BinaryOp::Mul), ('/', BinaryOp::Div)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat('-') {
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else if self.eat('!') {
+            Ok(Expr::Not(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Symbol('(')) => {
+                let expr = self.or()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Quoted(device)) => {
+                self.expect('.')?;
+                self.signal(Some(device))
+            }
+            Some(Token::Ident(name)) => {
+                if self.eat('(') {
+                    let function = match name.as_str() {
+                        "integrate" => Function::Integrate,
+                        "derivative" => Function::Derivative,
+                        "abs" => Function::Abs,
+                        "sqrt" => Function::Sqrt,
+                        _ => bail!("Unknown function '{}'", name),
+                    };
+                    let argument = self.or()?;
+                    self.expect(')')?;
+                    Ok(Expr::Call(function, Box::new(argument)))
+                } else if self.eat('.') {
+                    self.signal(Some(name))
+                } else {
+                    self.position -= 1;
+                    self.signal(None)
+                }
+            }
+            Some(Token::Symbol(c)) => bail!("Unexpected '{}'", c),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+
+    fn signal(&mut self, device: Option<String>) -> Result<Expr> {
+        let Some(Token::Ident(name)) = self.next() else {
+            bail!("Expected a channel name");
+        };
+        let signal = match name.as_str() {
+            "A" => Signal::Analog,
+            "t" => Signal::Time,
+            _ => match name
+                .strip_prefix('D')
+                .and_then(|ch| ch.parse::<usize>().ok())
+            {
+                Some(ch) if ch < 9 => Signal::Digital(ch),
+                _ => bail!("Unknown channel '{}', expected A, D0-D8 or t", name),
+            },
+        };
+        Ok(Expr::Signal { device, signal })
+    }
+}
+
+fn parse(expression: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+    };
+    let expr = parser.or()?;
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected {:?} after the expression", token);
+    }
+    Ok(expr)
+}
+
+impl MathChannel {
+    pub fn new(
+        name: String,
+        expression: String,
+        unit: String,
+        device: String,
+        color: [u8; 3],
+    ) -> Self {
+        Self {
+            name,
+            expression,
+            unit,
+            device,
+            color,
+            parsed: None,
+            result: None,
+        }
+    }
+
+    /// Evaluate the expression on the time base of `self.device`
+    ///
+    /// The result is kept until the expression, the device or one of the sources changes.
+    pub fn evaluate(&mut self, sources: &HashMap<String, SourceData>) -> Result<MathTrace> {
+        profiling::scope!("MathChannel::evaluate");
+
+        if self.parsed.as_ref().map(|(text, _)| text) != Some(&self.expression) {
+            let expr = parse(&self.expression).map_err(|e| format!("{:#}", e));
+            self.parsed = Some((self.expression.clone(), expr));
+            self.result = None;
+        }
+        if let Some(cached) = &self.result {
+            let unchanged = cached.inputs.len() == sources.len()
+                && cached.inputs.iter().all(|(name, source)| {
+                    sources
+                        .get(name)
+                        .is_some_and(|current| current.same(source))
+                });
+            if cached.device == self.device && unchanged {
+                return Ok(cached.trace.clone());
+            }
+        }
+
+        let expr = match &self.parsed {
+            Some((_, Ok(expr))) => expr,
+            Some((_, Err(e))) => bail!("{}", e),
+            None => unreachable!("parsed above"),
+        };
+        let base = sources
+            .get(&self.device)
+            .ok_or_else(|| anyhow!("Device '{}' is not connected", self.device))?;
+        let values = Evaluator {
+            base_device: &self.device,
+            time: base.time(),
+            sources,
+        }
+        .evaluate(expr)?;
+        let trace = Arc::new((base.time().to_vec(), values));
+        self.result = Some(CachedTrace {
+            device: self.device.clone(),
+            inputs: sources
+                .iter()
+                .map(|(name, source)| (name.clone(), source.clone()))
+                .collect(),
+            trace: trace.clone(),
+        });
+        Ok(trace)
+    }
+}
+
+struct Evaluator<'a> {
+    base_device: &'a str,
+    time: &'a [f64],
+    sources: &'a HashMap<String, SourceData>,
+}
+
+fn truth(value: f64) -> bool {
+    value != 0.0
+}
+
+fn from_bool(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl Evaluator<'_> {
+    fn evaluate(&self, expr: &Expr) -> Result<Vec<f64>> {
+        Ok(match expr {
+            Expr::Number(value) => vec![*value; self.time.len()],
+            Expr::Signal { device, signal } => {
+                self.signal(device.as_deref().unwrap_or(self.base_device), *signal)?
+            }
+            Expr::Negate(inner) => self.evaluate(inner)?.into_iter().map(|v| -v).collect(),
+            Expr::Not(inner) => self
+                .evaluate(inner)?
+                .into_iter()
+                .map(|v| if v.is_nan() { v } else { from_bool(!truth(v)) })
+                .collect(),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (self.evaluate(left)?, self.evaluate(right)?);
+                left.iter()
+                    .zip(&right)
+                    .map(|(&a, &b)| match op {
+                        BinaryOp::Add => a + b,
+                        BinaryOp::Sub => a - b,
+                        BinaryOp::Mul => a * b,
+                        BinaryOp::Div => a / b,
+                        _ if a.is_nan() || b.is_nan() => f64::NAN,
+                        BinaryOp::Less => from_bool(a < b),
+                        BinaryOp::Greater => from_bool(a > b),
+                        BinaryOp::And => from_bool(truth(a) && truth(b)),
+                        BinaryOp::Or => from_bool(truth(a) || truth(b)),
+                    })
+                    .collect()
+            }
+            Expr::Call(function, argument) => {
+                let values = self.evaluate(argument)?;
+                match function {
+                    Function::Abs => values.into_iter().map(f64::abs).collect(),
+                    Function::Sqrt => values.into_iter().map(f64::sqrt).collect(),
+                    Function::Integrate => integrate(self.time, &values),
+                    Function::Derivative => derivative(self.time, &values),
+                }
+            }
+        })
+    }
+
+    fn signal(&self, device: &str, signal: Signal) -> Result<Vec<f64>> {
+        let source = self
+            .sources
+            .get(device)
+            .ok_or_else(|| anyhow!("Device '{}' is not connected", device))?;
+        let values: Vec<f64> = match signal {
+            Signal::Time => source.time().to_vec(),
+            Signal::Analog => source.analog(),
+            Signal::Digital(ch) => source
+                .digital(ch)
+                .ok_or_else(|| anyhow!("Digital channels of '{}' are not available", device))?,
+        };
+        if device == self.base_device {
+            return Ok(values);
+        }
+        // Other devices have their own time base, resample onto ours
+        let digital = matches!(signal, Signal::Digital(_));
+        Ok(self
+            .time
+            .iter()
+            .map(|&t| resample(source.time(), &values, t, digital))
+            .collect())
+    }
+}
+
+/// Value at time `t`: linear interpolation, or the last sample for digital signals. NaN outside the samples.
+pub fn resample(time: &[f64], values: &[f64], t: f64, hold: bool) -> f64 {
+    let n = time.len().min(values.len());
+    if n == 0 || t < time[0] || t > time[n - 1] {
+        return f64::NAN;
+    }
+    let i = time[..n].partition_point(|&x| x <= t);
+    if i == 0 || i >= n || hold {
+        return values[i.saturating_sub(1)];
+    }
+    let (t0, t1) = (time[i - 1], time[i]);
+    let fraction = if t1 > t0 { (t - t0) / (t1 - t0) } else { 0.0 };
+    values[i - 1] + fraction * (values[i] - values[i - 1])
+}
+
+/// Running integral with the trapezoidal rule, starting at zero
+fn integrate(time: &[f64], values: &[f64]) -> Vec<f64> {
+    let mut sum = 0.0;
+    let mut result = Vec::with_capacity(values.len());
+    for i in 0..values.len() {
+        if i > 0 {
+            sum += (values[i] + values[i - 1]) / 2.0 * (time[i] - time[i - 1]);
+        }
+        result.push(sum);
+    }
+    result
+}
+
+/// Central differences, one-sided at both ends
+fn derivative(time: &[f64], values: &[f64]) -> Vec<f64> {
+    let n = values.len();
+    if n < 2 {
+        return vec![f64::NAN; n];
+    }
+    (0..n)
+        .map(|i| {
+            let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+            (values[b] - values[a]) / (time[b] - time[a])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::DataPoint;
+    use std::time::Instant;
+
+    fn continuous(time: &[f64], volts: &[f64]) -> SourceData {
+        SourceData::Continuous(Arc::new((time.to_vec(), volts.to_vec())))
+    }
+
+    fn capture(time: &[f64], digital: &[[bool; 2]]) -> SourceData {
+        let data_points = digital
+            .iter()
+            .map(|&[d0, d1]| {
+                let mut digital_channels = [false; 9];
+                digital_channels[0] = d0;
+                digital_channels[1] = d1;
+                DataPoint {
+                    analog_channel: 0.0,
+                    digital_channels,
+                }
+            })
+            .collect();
+        SourceData::Capture(Arc::new(DeviceData {
+            x_values: time.to_vec(),
+            data_points,
+            last_update: Instant::now(),
+            update_rate: 0.0,
+            connected: true,
+            running: true,
+            settings: None,
+        }))
+    }
+
+    fn channel(expression: &str) -> MathChannel {
+        MathChannel::new(
+            "M".to_string(),
+            expression.to_string(),
+            "V".to_string(),
+            "a".to_string(),
+            [255, 255, 255],
+        )
+    }
+
+    /// Values of `expression` on device "a", which has three samples at 0, 1 and 2 s
+    fn evaluate(expression: &str) -> Result<Vec<f64>> {
+        let sources = HashMap::from([(
+            "a".to_string(),
+            continuous(&[0.0, 1.0, 2.0], &[1.0, -2.0, 3.0]),
+        )]);
+        Ok(channel(expression).evaluate(&sources)?.1.clone())
+    }
+
+    fn constant(expression: &str) -> f64 {
+        let values = evaluate(expression).unwrap();
+        assert!(values.iter().all(|&v| v == values[0]), "{}", expression);
+        values[0]
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        assert_eq!(constant("1 + 2 * 3"), 7.0);
+        assert_eq!(constant("(1 + 2) * 3"), 9.0);
+        assert_eq!(constant("-2 * 3 + 10 / 4"), -3.5);
+        assert_eq!(constant("8 - 4 - 2"), 2.0);
+        assert_eq!(constant("1 + 1 < 3"), 1.0);
+        assert_eq!(constant("1 < 2 & 3 > 4 | 1"), 1.0);
+        assert_eq!(constant("!0 & !!2"), 1.0);
+        assert_eq!(constant("--1"), 1.0);
+    }
+
+    #[test]
+    fn reads_numbers() {
+        assert_eq!(constant("1e-3 * 2E3"), 2.0);
+        assert_eq!(constant(".5 + 2.25"), 2.75);
+        assert_eq!(constant("1e+2"), 100.0);
+    }
+
+    #[test]
+    fn parses_device_qualified_signals() {
+        let signal = |device: &str, signal| Expr::Signal {
+            device: Some(device.to_string()),
+            signal,
+        };
+        assert_eq!(
+            parse("a.A - \"sim://sine\".D3 * t").unwrap(),
+            Expr::Binary(
+                BinaryOp::Sub,
+                Box::new(signal("a", Signal::Analog)),
+                Box::new(Expr::Binary(
+                    BinaryOp::Mul,
+                    Box::new(signal("sim://sine", Signal::Digital(3))),
+                    Box::new(Expr::Signal {
+                        device: None,
+                        signal: Signal::Time,
+                    }),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let error = |expression: &str| format!("{:#}", parse(expression).unwrap_err());
+        assert_eq!(error("1 +"), "Unexpected end of expression");
+        assert_eq!(error("(1 + 2"), "Expected ')'");
+        assert_eq!(error("1 # 2"), "Unexpected '#'");
+        assert_eq!(error("* 2"), "Unexpected '*'");
+        assert_eq!(error("foo(A)"), "Unknown function 'foo'");
+        assert_eq!(error("\"sim://sine.A"), "Unterminated device name");
+        assert_eq!(error("\"sim://sine\" A"), "Expected '.'");
+        assert!(error("D9").starts_with("Unknown channel 'D9'"));
+        assert!(error("1 2").ends_with("after the expression"));
+    }
+
+    #[test]
+    fn evaluates_signals_and_functions() {
+        assert_eq!(evaluate("A").unwrap(), [1.0, -2.0, 3.0]);
+        assert_eq!(evaluate("abs(A) * t").unwrap(), [0.0, 2.0, 6.0]);
+        assert_eq!(evaluate("sqrt(4 * t * t)").unwrap(), [0.0, 2.0, 4.0]);
+        assert_eq!(evaluate("integrate(2)").unwrap(), [0.0, 2.0, 4.0]);
+        assert_eq!(evaluate("derivative(t * t)").unwrap(), [1.0, 2.0, 3.0]);
+        assert_eq!(evaluate("A > 0").unwrap(), [1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn resamples_other_devices() {
+        let sources = HashMap::from([
+            ("a".to_string(), continuous(&[0.0, 1.0, 2.0], &[0.0; 3])),
+            ("b".to_string(), continuous(&[0.5, 1.5], &[10.0, 20.0])),
+            (
+                "c".to_string(),
+                capture(
+                    &[0.0, 0.9, 2.0],
+                    &[[true, false], [false, true], [true, true]],
+                ),
+            ),
+        ]);
+        let values = channel("b.A").evaluate(&sources).unwrap();
+        assert!(values.1[0].is_nan() && values.1[2].is_nan());
+        assert_eq!(values.1[1], 15.0);
+
+        // Digital channels hold their last level instead of interpolating
+        let values = channel("c.D0 & !c.D1").evaluate(&sources).unwrap();
+        assert_eq!(values.1, [1.0, 0.0, 0.0]);
+        // Comparisons with samples outside the other device's window stay unknown
+        let values = channel("b.A > 12 | 1").evaluate(&sources).unwrap();
+        assert!(values.1[0].is_nan());
+        assert_eq!(values.1[1], 1.0);
+    }
+
+    #[test]
+    fn reports_missing_sources() {
+        let error = |expression: &str| format!("{:#}", evaluate(expression).unwrap_err());
+        assert_eq!(error("b.A"), "Device 'b' is not connected");
+        assert_eq!(error("D0"), "Digital channels of 'a' are not available");
+    }
+
+    #[test]
+    fn reuses_the_result_until_inputs_change() {
+        let mut math = channel("A * 2");
+        let mut sources = HashMap::from([("a".to_string(), continuous(&[0.0], &[1.0]))]);
+        let first = math.evaluate(&sources).unwrap();
+        assert!(Arc::ptr_eq(&first, &math.evaluate(&sources).unwrap()));
+
+        sources.insert("a".to_string(), continuous(&[0.0], &[2.0]));
+        let second = math.evaluate(&sources).unwrap();
+        assert_eq!(second.1, [4.0]);
+
+        math.expression = "A * 3".to_string();
+        assert_eq!(math.evaluate(&sources).unwrap().1, [6.0]);
+    }
+
+    #[test]
+    fn keeps_cached_sources_alive() {
+        let mut math = channel("A * 2");
+        let samples = Arc::new((vec![0.0], vec![1.0]));
+        let mut sources =
+            HashMap::from([("a".to_string(), SourceData::Continuous(samples.clone()))]);
+        math.evaluate(&sources).unwrap();
+        // So a new source cannot be allocated where the cached one was
+        assert_eq!(Arc::strong_count(&samples), 3);
+
+        sources.clear();
+        drop(samples);
+        sources.insert("a".to_string(), continuous(&[0.0], &[5.0]));
+        assert_eq!(math.evaluate(&sources).unwrap().1, [10.0]);
+
+        // A source that is added or removed invalidates the result as well
+        sources.insert("b".to_string(), continuous(&[0.0], &[7.0]));
+        math.expression = "A + b.A".to_string();
+        assert_eq!(math.evaluate(&sources).unwrap().1, [12.0]);
+        math.expression = "A * 2".to_string();
+        let cached = math.evaluate(&sources).unwrap();
+        sources.remove("b");
+        assert!(!Arc::ptr_eq(&cached, &math.evaluate(&sources).unwrap()));
+    }
+}
@@ -0,0 +1,487 @@
+use egui::{Color32, RichText};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::control_panel::pretty_print_number;
+use crate::device::AnalogSamples;
+
+/// Histogram resolution used to find the top and base levels
+const LEVEL_BINS: usize = 100;
+
+/// Hysteresis around the mid level as a fraction of the amplitude, so noise does not count as edges
+const HYSTERESIS: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Measurement {
+    Min,
+    Max,
+    PeakToPeak,
+    Mean,
+    Rms,
+    AcRms,
+    Frequency,
+    Period,
+    DutyCycle,
+    RiseTime,
+    FallTime,
+    Overshoot,
+    PulseWidth,
+}
+
+impl Measurement {
+    pub const ALL: [Measurement; 13] = [
+        Measurement::Min,
+        Measurement::Max,
+        Measurement::PeakToPeak,
+        Measurement::Mean,
+        Measurement::Rms,
+        Measurement::AcRms,
+        Measurement::Frequency,
+        Measurement::Period,
+        Measurement::DutyCycle,
+        Measurement::RiseTime,
+        Measurement::FallTime,
+        Measurement::Overshoot,
+        Measurement::PulseWidth,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Measurement::Min => "Vmin",
+            Measurement::Max => "Vmax",
+            Measurement::PeakToPeak => "Vpp",
+            Measurement::Mean => "Mean",
+            Measurement::Rms => "RMS",
+            Measurement::AcRms => "AC RMS",
+            Measurement::Frequency => "Freq",
+            Measurement::Period => "Period",
+            Measurement::DutyCycle => "Duty",
+            Measurement::RiseTime => "Rise",
+            Measurement::FallTime => "Fall",
+            Measurement::Overshoot => "Overshoot",
+            Measurement::PulseWidth => "+Width",
+        }
+    }
+
+    fn format(&self, value: f64) -> String {
+        match self {
+            Measurement::Min
+            | Measurement::Max
+            | Measurement::PeakToPeak
+            | Measurement::Mean
+            | Measurement::Rms
+            | Measurement::AcRms => pretty_print_number(value, Some("V"), 4),
+            Measurement::Frequency => pretty_print_number(value, Some("Hz"), 4),
+            Measurement::Period
+            | Measurement::RiseTime
+            | Measurement::FallTime
+            | Measurement::PulseWidth => pretty_print_number(value, Some("s"), 4),
+            Measurement::DutyCycle | Measurement::Overshoot => format!("{:.1}%", value),
+        }
+    }
+}
+
+/// All measurements of one waveform; `None` where the signal does not allow one
+#[derive(Debug, Clone, Default)]
+pub struct Measurements {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub rms: Option<f64>,
+    pub ac_rms: Option<f64>,
+    pub period: Option<f64>,
+    pub duty_cycle: Option<f64>,
+    pub rise_time: Option<f64>,
+    pub fall_time: Option<f64>,
+    pub overshoot: Option<f64>,
+    pub pulse_width: Option<f64>,
+}
+
+impl Measurements {
+    pub fn get(&self, measurement: Measurement) -> Option<f64> {
+        match measurement {
+            Measurement::Min => self.min,
+            Measurement::Max => self.max,
+            Measurement::PeakToPeak => Some(self.max? - self.min?),
+            Measurement::Mean => self.mean,
+            Measurement::Rms => self.rms,
+            Measurement::AcRms => self.ac_rms,
+            Measurement::Frequency => self.period.map(f64::recip),
+            Measurement::Period => self.period,
+            Measurement::DutyCycle => self.duty_cycle,
+            Measurement::RiseTime => self.rise_time,
+            Measurement::FallTime => self.fall_time,
+            Measurement::Overshoot => self.overshoot,
+            Measurement::PulseWidth => self.pulse_width,
+        }
+    }
+}
+
+/// Measure a waveform given as sample times and voltages
+pub fn measure(time: &[f64], volts: &[f64]) -> Measurements {
+    profiling::scope!("measurements::measure");
+
+    let n = time.len().min(volts.len());
+    if n == 0 {
+        return Measurements::default();
+    }
+    let (time, volts) = (&time[..n], &volts[..n]);
+
+    let min = volts.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = volts.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let mean = volts.iter().sum::<f64>() / n as f64;
+    let rms = (volts.iter().map(|v| v * v).sum::<f64>() / n as f64).sqrt();
+    let ac_rms = (volts.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n as f64).sqrt();
+    let mut result = Measurements {
+        min: Some(min),
+        max: Some(max),
+        mean: Some(mean),
+        rms: Some(rms),
+        ac_rms: Some(ac_rms),
+        ..Default::default()
+    };
+    if max - min <= f64::EPSILON {
+        return result;
+    }
+
+    let (base, top) = levels(volts, min, max);
+    let amplitude = top - base;
+    if amplitude <= f64::EPSILON {
+        return result;
+    }
+    result.overshoot = Some((max - top) / amplitude * 100.0);
+
+    let mid = base + amplitude / 2.0;
+    let low = base + amplitude * 0.1;
+    let high = base + amplitude * 0.9;
+    let edges = edges(time, volts, mid, amplitude * HYSTERESIS);
+
+    let rising: Vec<f64> = edges.iter().filter(|e| e.rising).map(|e| e.time).collect();
+    if rising.len() >= 2 {
+        result.period = Some((rising[rising.len() - 1] - rising[0]) / (rising.len() - 1) as f64);
+    }
+
+    // Positive pulses: a rising edge followed by a falling one
+    let widths: Vec<f64> = edges
+        .windows(2)
+        .filter(|pair| pair[0].rising && !pair[1].rising)
+        .map(|pair| pair[1].time - pair[0].time)
+        .collect();
+    if !widths.is_empty() {
+        let width = widths.iter().sum::<f64>() / widths.len() as f64;
+        result.pulse_width = Some(width);
+        result.duty_cycle = result.period.map(|period| width / period * 100.0);
+    }
+
+    let transitions = |rising: bool| {
+        let times: Vec<f64> = edges
+            .iter()
+            .filter(|e| e.rising == rising)
+            .filter_map(|e| transition_time(time, volts, e.index, low, high, rising))
+            .collect();
+        (!times.is_empty()).then(|| times.iter().sum::<f64>() / times.len() as f64)
+    };
+    result.rise_time = transitions(true);
+    result.fall_time = transitions(false);
+
+    result
+}
+
+/// Base and top level as the most common value in the lower and upper half of the range
+fn levels(volts: &[f64], min: f64, max: f64) -> (f64, f64) {
+    let bin_width = (max - min) / LEVEL_BINS as f64;
+    let mut counts = [0usize; LEVEL_BINS];
+    let mut sums = [0.0; LEVEL_BINS];
+    for v in volts {
+        let bin = (((v - min) / bin_width) as usize).min(LEVEL_BINS - 1);
+        counts[bin] += 1;
+        sums[bin] += v;
+    }
+    // Mean of the fullest bin rather than its center, so a flat top reads exactly
+    let mode = |bins: std::ops::Range<usize>| {
+        let bin = bins
+            .max_by_key(|&bin| counts[bin])
+            .expect("range is not empty");
+        if counts[bin] == 0 {
+            min + (bin as f64 + 0.5) * bin_width
+        } else {
+            sums[bin] / counts[bin] as f64
+        }
+    };
+    (mode(0..LEVEL_BINS / 2), mode(LEVEL_BINS / 2..LEVEL_BINS))
+}
+
+struct Edge {
+    rising: bool,
+    /// Interpolated time of the mid level crossing
+    time: f64,
+    /// First sample past the mid level
+    index: usize,
+}
+
+/// Mid level crossings, counted only once the signal leaves the hysteresis band
+fn edges(time: &[f64], volts: &[f64], mid: f64, hysteresis: f64) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    let mut high = volts[0] > mid;
+    let mut last_crossing = 0;
+    for i in 1..volts.len() {
+        let (a, b) = (volts[i - 1], volts[i]);
+        if (a <= mid) != (b <= mid) {
+            last_crossing = i;
+        }
+        let rising = !high && b > mid + hysteresis;
+        let falling = high && b < mid - hysteresis;
+        if rising || falling {
+            high = rising;
+            let j = last_crossing.max(1);
+            edges.push(Edge {
+                rising,
+                time: interpolate(time, volts, j, mid),
+                index: j,
+            });
+        }
+    }
+    edges
+}
+
+/// Time between the 10% and 90% crossings around the edge at `index`
+fn transition_time(
+    time: &[f64],
+    volts: &[f64],
+    index: usize,
+    low: f64,
+    high: f64,
+    rising: bool,
+) -> Option<f64> {
+    let (start_level, end_level) = if rising { (low, high) } else { (high, low) };
+    let before_start = |v: f64| {
+        if rising {
+            v <= start_level
+        } else {
+            v >= start_level
+        }
+    };
+    let past_end = |v: f64| {
+        if rising {
+            v >= end_level
+        } else {
+            v <= end_level
+        }
+    };
+
+    let start = (1..=index).rev().find(|&i| before_start(volts[i - 1]))?;
+    let end = (index..volts.len()).find(|&i| past_end(volts[i]))?;
+    let end = end.max(1);
+    Some(interpolate(time, volts, end, end_level) - interpolate(time, volts, start, start_level))
+}
+
+/// Time at which the segment ending at sample `i` crosses `level`
+fn interpolate(time: &[f64], volts: &[f64], i: usize, level: f64) -> f64 {
+    let (v0, v1) = (volts[i - 1], volts[i]);
+    if (v1 - v0).abs() <= f64::EPSILON {
+        return time[i];
+    }
+    let fraction = ((level - v0) / (v1 - v0)).clamp(0.0, 1.0);
+    time[i - 1] + fraction * (time[i] - time[i - 1])
+}
+
+/// Measurement selection of one device and the table shown below its plot
+#[derive(Debug, Clone)]
+pub struct MeasurementPanel {
+    pub selected: Vec<Measurement>,
+    /// Results and the samples they were measured on, every new capture comes in a new `Arc`
+    cache: Option<(AnalogSamples, Measurements)>,
+}
+
+impl Default for MeasurementPanel {
+    fn default() -> Self {
+        Self {
+            selected: vec![
+                Measurement::PeakToPeak,
+                Measurement::Mean,
+                Measurement::Rms,
+                Measurement::Frequency,
+                Measurement::DutyCycle,
+            ],
+            cache: None,
+        }
+    }
+}
+
+impl MeasurementPanel {
+    fn measure_cached(&mut self, samples: &AnalogSamples) -> Measurements {
+        match &self.cache {
+            Some((cached, measurements)) if Arc::ptr_eq(cached, samples) => measurements.clone(),
+            _ => {
+                let (time, volts) = &**samples;
+                let measurements = measure(time, volts);
+                self.cache = Some((samples.clone(), measurements.clone()));
+                measurements
+            }
+        }
+    }
+
+    /// `samples` are time and volts, measured again only when a different `Arc` is passed
+    pub fn ui(&mut self, ui: &mut egui::Ui, id_salt: &str, samples: &AnalogSamples) {
+        egui::CollapsingHeader::new(RichText::new("Measurements").strong())
+            .id_salt(format!("measurements_{}", id_salt))
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    ui.menu_button("＋", |ui| {
+                        for measurement in Measurement::ALL {
+                            let mut shown = self.selected.contains(&measurement);
+                            if ui.checkbox(&mut shown, measurement.label()).changed() {
+                                if shown {
+                                    self.selected.push(measurement);
+                                } else {
+                                    self.selected.retain(|m| *m != measurement);
+                                }
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Choose measurements");
+
+                    if self.selected.is_empty() {
+                        return;
+                    }
+                    let measurements = self.measure_cached(samples);
+                    for measurement in &self.selected {
+                        ui.separator();
+                        ui.label(
+                            RichText::new(measurement.label())
+                                .size(9.0)
+                                .color(Color32::LIGHT_GRAY),
+                        );
+                        let value = measurements
+                            .get(*measurement)
+                            .map_or("—".to_string(), |v| measurement.format(v));
+                        ui.label(
+                            RichText::new(value)
+                                .size(10.0)
+                                .color(Color32::WHITE)
+                                .family(egui::FontFamily::Monospace),
+                        );
+                    }
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{PI, SQRT_2};
+
+    /// One microsecond per sample
+    const DT: f64 = 1e-6;
+
+    fn sampled(samples: usize, wave: impl Fn(f64) -> f64) -> (Vec<f64>, Vec<f64>) {
+        (0..samples)
+            .map(|i| (i as f64 * DT, wave(i as f64 * DT)))
+            .unzip()
+    }
+
+    /// 1 kHz, 0 to 3.3 V with 10 µs ramps and 250 µs between the mid level crossings
+    fn trapezoid(t: f64) -> f64 {
+        let phase = (t / DT).round() as u64 % 1000;
+        match phase {
+            0..10 => 3.3 * phase as f64 / 10.0,
+            10..250 => 3.3,
+            250..260 => 3.3 * (260 - phase) as f64 / 10.0,
+            _ => 0.0,
+        }
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64, tolerance: f64) {
+        let actual = actual.expect("measurement is missing");
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn measures_a_square_wave() {
+        let (time, volts) = sampled(5000, trapezoid);
+        let m = measure(&time, &volts);
+
+        assert_close(m.get(Measurement::PeakToPeak), 3.3, 1e-12);
+        assert_close(m.get(Measurement::Mean), 3.3 * 0.25, 1e-3);
+        assert_close(m.get(Measurement::Frequency), 1000.0, 1e-6);
+        assert_close(m.get(Measurement::DutyCycle), 25.0, 1e-6);
+        assert_close(m.get(Measurement::PulseWidth), 250e-6, 1e-9);
+        // 10% to 90% of a linear 10 µs ramp
+        assert_close(m.get(Measurement::RiseTime), 8e-6, 1e-9);
+        assert_close(m.get(Measurement::FallTime), 8e-6, 1e-9);
+        assert_close(m.get(Measurement::Overshoot), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn measures_overshoot_against_the_top_level() {
+        let (time, volts) = sampled(5000, |t| {
+            let ringing = (t / DT).round() as u64 % 1000 == 12;
+            trapezoid(t) + if ringing { 0.33 } else { 0.0 }
+        });
+        let m = measure(&time, &volts);
+
+        assert_close(m.get(Measurement::Max), 3.63, 1e-12);
+        assert_close(m.get(Measurement::Overshoot), 10.0, 1e-6);
+    }
+
+    #[test]
+    fn measures_a_sine() {
+        let (amplitude, offset) = (1.5, 0.5);
+        let (time, volts) = sampled(10_000, |t| offset + amplitude * (2.0 * PI * 1e3 * t).sin());
+        let m = measure(&time, &volts);
+
+        assert_close(m.get(Measurement::PeakToPeak), 2.0 * amplitude, 1e-9);
+        assert_close(m.get(Measurement::Mean), offset, 1e-9);
+        assert_close(m.get(Measurement::AcRms), amplitude / SQRT_2, 1e-6);
+        assert_close(
+            m.get(Measurement::Rms),
+            (offset * offset + amplitude * amplitude / 2.0).sqrt(),
+            1e-6,
+        );
+        assert_close(m.get(Measurement::Frequency), 1000.0, 1e-3);
+        assert_close(m.get(Measurement::DutyCycle), 50.0, 0.1);
+    }
+
+    #[test]
+    fn leaves_out_timing_of_flat_signals() {
+        let (time, volts) = sampled(100, |_| 1.2);
+        let m = measure(&time, &volts);
+
+        assert_close(m.get(Measurement::PeakToPeak), 0.0, 0.0);
+        assert_close(m.get(Measurement::Rms), 1.2, 1e-12);
+        assert_eq!(m.get(Measurement::Frequency), None);
+        assert_eq!(m.get(Measurement::DutyCycle), None);
+        assert_eq!(m.get(Measurement::RiseTime), None);
+        assert!(measure(&[], &[]).get(Measurement::Mean).is_none());
+    }
+
+    #[test]
+    fn measures_every_new_capture() {
+        let mut panel = MeasurementPanel::default();
+        let square = Arc::new(sampled(5000, trapezoid));
+        // Same length, time axis and last sample as the square wave, but flat
+        let flat = Arc::new(sampled(5000, |_| 0.0));
+        assert_eq!(square.1.last(), flat.1.last());
+
+        let first = panel.measure_cached(&square);
+        assert_close(first.get(Measurement::PeakToPeak), 3.3, 1e-12);
+        let second = panel.measure_cached(&flat);
+        assert_close(second.get(Measurement::PeakToPeak), 0.0, 0.0);
+        // An identical copy is a new capture as well
+        let copy = Arc::new((*square).clone());
+        assert_close(
+            panel.measure_cached(&copy).get(Measurement::PeakToPeak),
+            3.3,
+            1e-12,
+        );
+    }
+}
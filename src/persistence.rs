@@ -0,0 +1,312 @@
+use egui::{Color32, ColorImage, RichText, TextureHandle, TextureOptions, Vec2};
+use egui_plot::PlotPoint;
+use std::time::Instant;
+
+/// Resolution of the density map
+const WIDTH: usize = 512;
+const HEIGHT: usize = 256;
+
+/// Headroom above and below the signal when the voltage range is fitted
+const VOLTAGE_PADDING: f64 = 0.25;
+
+/// Brightness curve, below 1 so rare events stay visible next to the dominant trace
+const GAMMA: f32 = 0.4;
+
+/// Plot area covered by the density map
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    t0: f64,
+    t1: f64,
+    v0: f64,
+    v1: f64,
+}
+
+impl Bounds {
+    fn fit(time: &[f64], volts: &[f64]) -> Option<Self> {
+        let (t0, t1) = (*time.first()?, *time.last()?);
+        let (v0, v1) = min_max(volts)?;
+        let padding = ((v1 - v0) * VOLTAGE_PADDING).max(0.05);
+        (t1 > t0).then_some(Self {
+            t0,
+            t1,
+            v0: v0 - padding,
+            v1: v1 + padding,
+        })
+    }
+
+    /// Whether a capture shares the timebase of the map
+    fn same_timebase(&self, time: &[f64]) -> bool {
+        let tolerance = (self.t1 - self.t0) * 0.01;
+        match (time.first(), time.last()) {
+            (Some(&t0), Some(&t1)) => {
+                (t0 - self.t0).abs() <= tolerance && (t1 - self.t1).abs() <= tolerance
+            }
+            _ => false,
+        }
+    }
+
+    /// Voltage range grown to hold `volts` with padding, `None` if it already does
+    fn grown(&self, volts: &[f64]) -> Option<Self> {
+        let (lo, hi) = min_max(volts)?;
+        if lo >= self.v0 && hi <= self.v1 {
+            return None;
+        }
+        let padding = (hi.max(self.v1) - lo.min(self.v0)) * VOLTAGE_PADDING;
+        Some(Self {
+            v0: if lo < self.v0 { lo - padding } else { self.v0 },
+            v1: if hi > self.v1 { hi + padding } else { self.v1 },
+            ..*self
+        })
+    }
+}
+
+fn min_max(values: &[f64]) -> Option<(f64, f64)> {
+    values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold(None, |range, &v| match range {
+            None => Some((v, v)),
+            Some((lo, hi)) => Some((f64::min(lo, v), f64::max(hi, v))),
+        })
+}
+
+/// Density map image placed in plot coordinates
+pub struct PersistenceImage {
+    pub texture: egui::TextureId,
+    pub center: PlotPoint,
+    pub size: Vec2,
+}
+
+/// Phosphor-like accumulation of successive triggered captures of one device
+pub struct Persistence {
+    pub enabled: bool,
+    /// Seconds for the glow to fade to 1/e; `None` keeps every capture (infinite persistence)
+    pub decay: Option<f64>,
+    density: Vec<f32>,
+    bounds: Option<Bounds>,
+    captures: usize,
+    last_decay: Instant,
+    texture: Option<TextureHandle>,
+}
+
+impl Default for Persistence {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            decay: Some(1.0),
+            density: vec![0.0; WIDTH * HEIGHT],
+            bounds: None,
+            captures: 0,
+            last_decay: Instant::now(),
+            texture: None,
+        }
+    }
+}
+
+impl Persistence {
+    pub fn clear(&mut self) {
+        self.density.fill(0.0);
+        self.bounds = None;
+        self.captures = 0;
+    }
+
+    /// Add a capture to the map
+    pub fn accumulate(&mut self, time: &[f64], volts: &[f64]) {
+        profiling::scope!("Persistence::accumulate");
+
+        let bounds = match self.bounds {
+            Some(bounds) if bounds.same_timebase(time) => match bounds.grown(volts) {
+                // An outlier is what persistence is for, so keep the history and make room
+                Some(grown) => {
+                    self.rescale(bounds, grown);
+                    grown
+                }
+                None => bounds,
+            },
+            _ => {
+                // Timebase changed, the old traces do not line up anymore
+                self.clear();
+                let Some(bounds) = Bounds::fit(time, volts) else {
+                    return;
+                };
+                bounds
+            }
+        };
+        self.bounds = Some(bounds);
+
+        let to_pixel = |t: f64, v: f64| {
+            (
+                (t - bounds.t0) / (bounds.t1 - bounds.t0) * (WIDTH - 1) as f64,
+                (bounds.v1 - v) / (bounds.v1 - bounds.v0) * (HEIGHT - 1) as f64,
+            )
+        };
+        // Like a beam, every sample interval leaves the same energy spread over the pixels it crosses
+        for (t, v) in time.windows(2).zip(volts.windows(2)) {
+            if !v[0].is_finite() || !v[1].is_finite() {
+                continue;
+            }
+            let (x0, y0) = to_pixel(t[0], v[0]);
+            let (x1, y1) = to_pixel(t[1], v[1]);
+            let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0);
+            let energy = 1.0 / steps as f32;
+            for step in 0..steps as usize {
+                let fraction = (step as f64 + 0.5) / steps;
+                let x = (x0 + (x1 - x0) * fraction).round() as usize;
+                let y = (y0 + (y1 - y0) * fraction).round() as usize;
+                if x < WIDTH && y < HEIGHT {
+                    self.density[y * WIDTH + x] += energy;
+                }
+            }
+        }
+        self.captures += 1;
+    }
+
+    /// Move the accumulated rows from the voltage range of `from` into the larger one of `to`
+    fn rescale(&mut self, from: Bounds, to: Bounds) {
+        profiling::scope!("Persistence::rescale");
+
+        let mut density = vec![0.0; WIDTH * HEIGHT];
+        for y in 0..HEIGHT {
+            let v = from.v1 - y as f64 / (HEIGHT - 1) as f64 * (from.v1 - from.v0);
+            let target = ((to.v1 - v) / (to.v1 - to.v0) * (HEIGHT - 1) as f64).round() as usize;
+            let (row, target_row) = (y * WIDTH, target.min(HEIGHT - 1) * WIDTH);
+            for x in 0..WIDTH {
+                density[target_row + x] += self.density[row + x];
+            }
+        }
+        self.density = density;
+    }
+
+    fn fade(&mut self) {
+        let now = Instant::now();
+        let elapsed = (now - self.last_decay).as_secs_f64();
+        self.last_decay = now;
+        if let Some(decay) = self.decay {
+            let factor = (-elapsed / decay).exp() as f32;
+            self.density.iter_mut().for_each(|d| *d *= factor);
+        }
+    }
+
+    /// Fade the map and upload it as a texture for the plot
+    pub fn image(&mut self, ctx: &egui::Context, name: &str) -> Option<PersistenceImage> {
+        profiling::scope!("Persistence::image");
+
+        self.fade();
+        let bounds = self.bounds?;
+        let max = self.density.iter().copied().fold(0.0, f32::max);
+        if max <= f32::EPSILON {
+            return None;
+        }
+
+        let pixels = self
+            .density
+            .iter()
+            .map(|&d| phosphor((d / max).powf(GAMMA)))
+            .collect();
+        let image = ColorImage::new([WIDTH, HEIGHT], pixels);
+        let texture = match &mut self.texture {
+            Some(texture) => {
+                texture.set(image, TextureOptions::LINEAR);
+                texture
+            }
+            None => self
+                .texture
+                .insert(ctx.load_texture(name, image, TextureOptions::LINEAR)),
+        };
+
+        Some(PersistenceImage {
+            texture: texture.id(),
+            center: PlotPoint::new((bounds.t0 + bounds.t1) / 2.0, (bounds.v0 + bounds.v1) / 2.0),
+            size: Vec2::new(
+                (bounds.t1 - bounds.t0) as f32,
+                (bounds.v1 - bounds.v0) as f32,
+            ),
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if !self.enabled {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("Persistence").strong());
+            let mut infinite = self.decay.is_none();
+            if ui.checkbox(&mut infinite, "∞").changed() {
+                self.decay = if infinite { None } else { Some(1.0) };
+            }
+            if let Some(decay) = &mut self.decay {
+                ui.label("Decay:");
+                ui.add(
+                    egui::Slider::new(decay, 0.05..=30.0)
+                        .logarithmic(true)
+                        .suffix(" s"),
+                );
+            }
+            if ui.button("Clear").clicked() {
+                self.clear();
+            }
+            ui.label(
+                RichText::new(format!("{} captures", self.captures))
+                    .size(9.0)
+                    .color(Color32::LIGHT_GRAY),
+            );
+        });
+    }
+}
+
+/// Color of a phosphor glowing at `level` (0..=1): transparent, dark green, green, white-hot
+fn phosphor(level: f32) -> Color32 {
+    if level <= 0.0 {
+        return Color32::TRANSPARENT;
+    }
+    let lerp = |a: u8, b: u8, t: f32| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    let (r, g, b) = if level < 0.5 {
+        let t = level * 2.0;
+        (0, lerp(60, 255, t), lerp(0, 60, t))
+    } else {
+        let t = (level - 0.5) * 2.0;
+        (lerp(0, 255, t), 255, lerp(60, 220, t))
+    };
+    // Even a single hit stays visible
+    let alpha = (0.2 + level * 4.0).min(1.0);
+    Color32::from_rgba_unmultiplied(r, g, b, (alpha * 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(persistence: &Persistence) -> f32 {
+        persistence.density.iter().sum()
+    }
+
+    #[test]
+    fn outlier_grows_range_and_keeps_history() {
+        let time: Vec<f64> = (0..100).map(|i| i as f64 * 1e-3).collect();
+        let flat = vec![1.0; 100];
+        let mut glitch = flat.clone();
+        glitch[50] = 5.0;
+
+        let mut persistence = Persistence::default();
+        persistence.accumulate(&time, &flat);
+        let before = total(&persistence);
+        persistence.accumulate(&time, &glitch);
+
+        assert_eq!(persistence.captures, 2);
+        assert!(persistence.bounds.unwrap().v1 > 5.0);
+        assert!(total(&persistence) > before * 1.5);
+    }
+
+    #[test]
+    fn new_timebase_starts_over() {
+        let volts = vec![1.0; 100];
+        let short: Vec<f64> = (0..100).map(|i| i as f64 * 1e-3).collect();
+        let long: Vec<f64> = (0..100).map(|i| i as f64 * 1e-2).collect();
+
+        let mut persistence = Persistence::default();
+        persistence.accumulate(&short, &volts);
+        persistence.accumulate(&long, &volts);
+
+        assert_eq!(persistence.captures, 1);
+    }
+}
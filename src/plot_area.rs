@@ -1,176 +1,130 @@
 use crate::{
-    device::DeviceManager,
+    combined_view::{CombinedChannel, CombinedView},
+    continuous_buffer::{BinnedWindow, ContinuousBuffer, MAX_BUFFER_TIME},
+    cursors::Cursors,
+    decoder::DecodedFrame,
+    device::{AnalogSamples, DeviceData, DeviceManager, CONTINUOUS_SAMPLE_RATE_HZ},
+    math_channel::{MathChannel, MathTrace, SourceData},
+    persistence::PersistenceImage,
+    reference::{Reference, ReferenceAction},
+    replay::{Replay, PLAYBACK_SPEEDS},
+    session::PlotSettings,
     worker_interface::{CaptureModeFlat, FleaScopeDevice},
 };
 use egui::{Color32, RichText};
-use egui_plot::{Line, Plot, PlotPoints};
-use polars::{
-    frame::DataFrame,
-    prelude::{col, lit, Column, DataType, IntoLazy},
+use egui_plot::{
+    Legend, Line, LineStyle, Plot, PlotBounds, PlotGeometry, PlotImage, PlotItem, PlotItemBase,
+    PlotPoint, PlotPoints, PlotTransform, Polygon, Text, VLine,
 };
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Clone)]
-pub struct ContinuousBuffer {
-    data: DataFrame,
-    sample_rate_hz: u32,
-    last_t: f64,
+/// Vertical distance between the lanes of the digital plot
+const DIGITAL_LANE_SPACING: f64 = 1.2;
+
+/// How often the unresampled continuous window behind measurements and spectrum is refreshed
+const RAW_WINDOW_REFRESH: Duration = Duration::from_millis(250);
+
+/// Snapshot of a continuous buffer, copying the whole window every frame would stall the UI
+struct RawWindow {
+    taken_at: Instant,
+    buffer_time: f64,
+    samples: AnalogSamples,
 }
 
-impl ContinuousBuffer {
-    pub fn new(sample_rate_hz: u32) -> Self {
-        profiling::scope!("ContinuousBuffer::new");
+/// Narrowest decoded frame box in pixels that still gets a label
+const MIN_LABELED_FRAME_WIDTH: f32 = 24.0;
 
-        let bnc_column = Column::new("bnc".into(), [0.0].as_ref());
-        let time_index = Column::new("time".into(), [0.0].as_ref());
+/// Extra item drawn over a plot
+enum Overlay {
+    /// A trace such as a reference waveform
+    Trace {
+        name: String,
+        color: Color32,
+        points: Vec<[f64; 2]>,
+        dashed: bool,
+    },
+    /// Persistence density map, drawn behind the traces
+    Image(PersistenceImage),
+    /// Vertical line at the trigger instant
+    TriggerMarker,
+    /// Min/max band of the continuous view, drawn behind the traces
+    Envelope {
+        color: Color32,
+        /// Time, min and max of every bin
+        bins: Vec<[f64; 3]>,
+    },
+}
 
-        let df = DataFrame::new(vec![time_index, bnc_column]).expect("Failed to create DataFrame");
+/// Filled area between the lower and upper bound of every bin
+///
+/// `Polygon` only fills convex shapes, which a band along a signal hardly ever is.
+struct Band {
+    base: PlotItemBase,
+    color: Color32,
+    /// Time, lower and upper bound, ordered by time
+    bins: Vec<[f64; 3]>,
+}
 
+impl Band {
+    fn new(name: &str, color: Color32, bins: Vec<[f64; 3]>) -> Self {
         Self {
-            data: df,
-            sample_rate_hz,
-            last_t: 0.0,
+            base: PlotItemBase::new(name.to_string()),
+            color,
+            bins,
         }
     }
+}
 
-    pub fn add_batch(&mut self, batch: Vec<f64>) {
-        profiling::scope!("ContinuousBuffer::add_batch");
-
-        let time_step = 1.0 / self.sample_rate_hz as f64;
-
-        // Create time and BNC value vectors
-        let mut time_values = Vec::with_capacity(batch.len());
-        let mut bnc_values = Vec::with_capacity(batch.len());
-
-        for &bnc_value in batch.iter() {
-            time_values.push(self.last_t);
-            bnc_values.push(bnc_value);
-            self.last_t += time_step;
+impl PlotItem for Band {
+    fn shapes(&self, _ui: &egui::Ui, transform: &PlotTransform, shapes: &mut Vec<egui::Shape>) {
+        let mut mesh = egui::Mesh::default();
+        for &[t, low, high] in &self.bins {
+            let i = mesh.vertices.len() as u32;
+            mesh.colored_vertex(
+                transform.position_from_point(&PlotPoint::new(t, low)),
+                self.color,
+            );
+            mesh.colored_vertex(
+                transform.position_from_point(&PlotPoint::new(t, high)),
+                self.color,
+            );
+            // Two triangles join this bin with the previous one
+            if i > 0 {
+                mesh.add_triangle(i - 2, i - 1, i);
+                mesh.add_triangle(i - 1, i, i + 1);
+            }
         }
-
-        // Create new DataFrame from the batch with time as index
-        let time_column = Column::new("time".into(), time_values);
-        let bnc_column = Column::new("bnc".into(), bnc_values);
-
-        let batch_df = DataFrame::new(vec![time_column, bnc_column])
-            .expect("Failed to create batch DataFrame");
-
-        // Concatenate with existing data
-        self.data = self
-            .data
-            .vstack(&batch_df)
-            .expect("Failed to concatenate DataFrames");
+        shapes.push(egui::Shape::mesh(mesh));
     }
 
-    fn cleanup_old_batches(&mut self, keep_time: f64) {
-        profiling::scope!("ContinuousBuffer::cleanup_old_batches");
+    fn initialize(&mut self, _x_range: RangeInclusive<f64>) {}
 
-        let keep_samples = (keep_time * self.sample_rate_hz as f64) as usize;
-        let current_height = self.data.height();
+    fn color(&self) -> Color32 {
+        self.color
+    }
 
-        if current_height > keep_samples {
-            let rows_to_remove = current_height - keep_samples;
-            // Keep only the last keep_samples rows
-            self.data = self.data.slice(rows_to_remove as i64, keep_samples);
-        }
+    fn geometry(&self) -> PlotGeometry<'_> {
+        PlotGeometry::None
     }
 
-    pub fn get_data_in_window(
-        &self,
-        window_duration: f64,
-        wrap: bool,
-        plot_width: u32,
-    ) -> (Vec<f64>, Vec<f64>) {
-        profiling::scope!("ContinuousBuffer::get_data_in_window");
-
-        if self.data.height() == 0 {
-            return (Vec::new(), Vec::new());
+    fn bounds(&self) -> PlotBounds {
+        let mut bounds = PlotBounds::NOTHING;
+        for &[t, low, high] in &self.bins {
+            bounds.extend_with(&PlotPoint::new(t, low));
+            bounds.extend_with(&PlotPoint::new(t, high));
         }
+        bounds
+    }
 
-        let latest_time = self.last_t;
-
-        let window_start = latest_time - window_duration;
-        let time_bin_size = window_duration / plot_width as f64;
-
-        // Use Polars lazy evaluation for efficient filtering and resampling
-        let filtered_df = {
-            profiling::scope!("polars_filter_and_resample");
-            let rmse = self
-                .data
-                .clone()
-                .lazy()
-                .filter(col("time").gt_eq(lit(window_start)))
-                .select([
-                    polars::prelude::col("bnc").std(1),
-                ])
-                .collect()
-                .expect("Failed to filter and resample DataFrame")
-                .column("bnc")
-                .expect("time column not found")
-                .f64()
-                .expect("time should be f64")
-                .into_no_null_iter()
-                .collect::<Vec<_>>();
-            tracing::debug!("RMS Error in window: {:?}", rmse);
-            let mut df = self
-                .data
-                .clone()
-                .lazy()
-                .filter(col("time").gt_eq(lit(window_start)))
-                .with_column(
-                    (col("time") / lit(time_bin_size))
-                        .cast(DataType::Int32)
-                        .alias("time_bin"),
-                )
-                .group_by([col("time_bin")])
-                .agg([
-                    col("time").min().alias("time_min"),
-                    col("time").max().alias("time_max"),
-                    col("bnc").min().alias("bnc_min"),
-                    col("bnc").median().alias("bnc_median"),
-                    col("bnc").mean().alias("bnc_mean"),
-                    col("bnc").max().alias("bnc_max"),
-                ])
-                .sort(
-                    ["time_min"],
-                    polars::prelude::SortMultipleOptions::default(),
-                )
-                .with_row_index("idx", None)
-                .filter(col("idx").gt(lit(0)).and(col("idx").lt(col("idx").max())));
-            if wrap {
-                df = df.with_column(col("time_min") % lit(window_duration))
-            }
-            df.sort(
-                ["time_min"],
-                polars::prelude::SortMultipleOptions::default(),
-            )
-            .select([
-                polars::prelude::col("time_min").alias("time"),
-                polars::prelude::col("bnc_mean").alias("bnc"),
-            ])
-            .collect()
-            .expect("Failed to filter and resample DataFrame")
-        };
+    fn base(&self) -> &PlotItemBase {
+        &self.base
+    }
 
-        // Extract vectors efficiently - handle both resampled and non-resampled data
-        // Resampled data - interleave min/max points
-        profiling::scope!("extract_resampled_data");
-
-        let time = filtered_df
-            .column("time")
-            .expect("time column not found")
-            .f64()
-            .expect("time should be f64")
-            .into_no_null_iter()
-            .collect::<Vec<_>>();
-        let bnc = filtered_df
-            .column("bnc")
-            .expect("bnc column not found")
-            .f64()
-            .expect("bnc should be f64")
-            .into_no_null_iter()
-            .collect::<Vec<_>>();
-
-        (time, bnc)
+    fn base_mut(&mut self) -> &mut PlotItemBase {
+        &mut self.base
     }
 }
 
@@ -179,6 +133,13 @@ pub struct PlotArea {
     colors: Vec<Color32>,
     show_grid: bool,
     continuous_buffers: std::collections::HashMap<String, ContinuousBuffer>, // Per-device buffers
+    raw_windows: HashMap<String, RawWindow>,
+    /// Analog samples of the latest triggered capture, kept with the capture they came from
+    raw_captures: HashMap<String, (Arc<DeviceData>, AnalogSamples)>,
+    replays: Vec<Replay>, // Files opened for review, shown below the live devices
+    math_channels: Vec<MathChannel>,
+    references: Vec<Reference>,
+    combined: CombinedView,
     width: u32,
 }
 
@@ -200,12 +161,42 @@ impl Default for PlotArea {
             ],
             show_grid: true,
             continuous_buffers: std::collections::HashMap::new(),
+            raw_windows: HashMap::new(),
+            raw_captures: HashMap::new(),
+            replays: Vec::new(),
+            math_channels: Vec::new(),
+            references: Vec::new(),
+            combined: CombinedView::default(),
             width: 1500,
         }
     }
 }
 
 impl PlotArea {
+    pub fn settings(&self) -> PlotSettings {
+        PlotSettings {
+            plot_height: self.plot_height,
+            show_grid: self.show_grid,
+            math_channels: self.math_channels.clone(),
+            combined: self.combined.clone(),
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &PlotSettings) {
+        self.plot_height = settings.plot_height;
+        self.show_grid = settings.show_grid;
+        self.math_channels = settings.math_channels.clone();
+        self.combined = settings.combined.clone();
+    }
+
+    pub fn load_references(&mut self) {
+        self.references = Reference::load_all(&self.colors[4..]);
+    }
+
+    pub fn open_replay(&mut self, replay: Replay) {
+        self.replays.push(replay);
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui, device_manager: &mut DeviceManager) {
         profiling::scope!("PlotArea::ui");
 
@@ -216,6 +207,12 @@ impl PlotArea {
             ui.separator();
             ui.label("Plot Height:");
             ui.add(egui::Slider::new(&mut self.plot_height, 100.0..=400.0).suffix("px"));
+            ui.separator();
+            if ui.button("🧮 Add math channel").clicked() {
+                self.add_math_channel(device_manager);
+            }
+            ui.toggle_value(&mut self.combined.enabled, "🔀 Combined view")
+                .on_hover_text("Channels of several devices on one time axis");
         });
 
         ui.separator();
@@ -230,14 +227,26 @@ impl PlotArea {
                 // Set minimum width to prevent horizontal clipping
                 ui.set_min_width(ui.available_width());
 
+                if self.combined.enabled {
+                    self.render_combined_view(ui, device_manager);
+                }
+
+                let math_results = self.evaluate_math_channels(device_manager);
+
                 for (device_idx, device) in device_manager.get_devices_mut().iter_mut().enumerate()
                 {
+                    device.combine_captures(self.width);
                     ui.group(|ui| {
                         ui.horizontal(|ui| {
                             ui.label(RichText::new(&device.name).heading().strong());
                             ui.with_layout(
                                 egui::Layout::right_to_left(egui::Align::Center),
                                 |ui| {
+                                    ui.toggle_value(&mut device.cursors.enabled, "⌖ Cursors");
+                                    ui.toggle_value(&mut device.spectrum.enabled, "〰 FFT");
+                                    ui.toggle_value(&mut device.decoder.enabled, "🔣 Decode");
+                                    ui.toggle_value(&mut device.reference.enabled, "📌 Ref");
+                                    ui.toggle_value(&mut device.persistence.enabled, "✨ Persist");
                                     ui.label(format!("📡 {}", device.name));
                                     let status_color = Color32::GREEN; // Default to green
                                                                        /*
@@ -253,9 +262,28 @@ impl PlotArea {
                         });
 
                         // Analog Channel Plot
+                        let mut analog_samples = None;
                         if device.enabled_channels[0] {
                             ui.label(RichText::new("Analog Channel (12-bit)").strong());
-                            self.render_analog_plot(ui, device, device_idx);
+                            let math = self.math_overlays(&device.name, &math_results);
+                            self.render_analog_plot(ui, device, device_idx, math);
+                            if device.persistence.enabled
+                                && matches!(device.get_capture_mode(), CaptureModeFlat::Continuous)
+                            {
+                                ui.label("Persistence needs triggered captures");
+                            }
+                            device.persistence.ui(ui);
+                            device.acquisition.ui(ui);
+
+                            let samples = self.get_raw_analog_data(device);
+                            device.spectrum.ui(
+                                ui,
+                                &device.name,
+                                &samples,
+                                self.plot_height,
+                                self.show_grid,
+                            );
+                            analog_samples = Some(samples);
                         }
 
                         // Digital Channels Plot
@@ -264,13 +292,37 @@ impl PlotArea {
                         if enabled_digital {
                             ui.label(RichText::new("Digital Channels").strong());
                             self.render_digital_plot(ui, device, device_idx);
+                            device.decoder.ui(ui, &device.name);
+                        }
+
+                        let analog = analog_samples
+                            .as_deref()
+                            .map(|(time, volts)| (time.as_slice(), volts.as_slice()));
+                        if let Some(action) = device.reference.ui(
+                            ui,
+                            &device.name,
+                            &mut self.references,
+                            analog,
+                            matches!(device.get_capture_mode(), CaptureModeFlat::Triggered),
+                        ) {
+                            self.handle_reference_action(device, action);
+                        }
+
+                        device.cursors.readout(ui);
+
+                        if let Some(samples) = analog_samples {
+                            device.measurements.ui(ui, &device.name, &samples);
                         }
                     });
 
                     ui.add_space(10.0);
                 }
 
-                if device_manager.get_devices().is_empty() {
+                self.render_math_channels(ui, device_manager, &math_results);
+
+                self.render_replays(ui);
+
+                if device_manager.get_devices().is_empty() && self.replays.is_empty() {
                     ui.vertical_centered(|ui| {
                         ui.add_space(50.0);
                         ui.label(RichText::new("No devices connected").size(16.0).weak());
@@ -280,52 +332,81 @@ impl PlotArea {
             });
     }
 
-    fn get_analog_data(&mut self, device: &mut FleaScopeDevice) -> (Vec<f64>, Vec<f64>) {
+    /// Buffer of a device in continuous mode, fed with the batches received since the last frame
+    fn continuous_buffer(&mut self, device: &mut FleaScopeDevice) -> &ContinuousBuffer {
+        profiling::scope!("process_channel_batches");
+
+        let buffer = self
+            .continuous_buffers
+            .entry(device.name.clone())
+            .or_insert_with(|| {
+                profiling::scope!("create_new_buffer");
+                ContinuousBuffer::new(CONTINUOUS_SAMPLE_RATE_HZ, MAX_BUFFER_TIME)
+            });
+
+        while let Ok(batch) = device.batch_rx.try_recv() {
+            profiling::scope!("add_single_batch");
+            tracing::debug!("Received batch with {} points", batch.analog.len());
+            buffer.add_batch(batch);
+        }
+        buffer
+    }
+
+    fn get_continuous_window(&mut self, device: &mut FleaScopeDevice) -> BinnedWindow {
         profiling::scope!("get_plot_data");
 
-        match &device.get_capture_mode() {
-            CaptureModeFlat::Continuous => {
-                // Process any new batches from the channel
-                {
-                    profiling::scope!("process_channel_batches");
-                    let device_name = &device.name;
-                    let buffer = self
-                        .continuous_buffers
-                        .entry(device_name.clone())
-                        .or_insert_with(|| {
-                            profiling::scope!("create_new_buffer");
-                            ContinuousBuffer::new(51_436)
-                        }); // 1 second max buffer
-
-                    while let Ok(batch) = device.batch_rx.try_recv() {
-                        profiling::scope!("add_single_batch");
-                        tracing::debug!("Received batch with {} points", batch.len());
-                        // Get or create buffer for this device
-                        buffer.add_batch(batch);
-                    }
-                    tracing::debug!("Cleaning up old batches");
-                    buffer.cleanup_old_batches(device.get_continuous_config().buffer_time);
-                }
-                profiling::scope!("continuous_mode_data");
+        let width = self.width;
+        self.continuous_buffer(device).get_data_in_window(
+            device.get_continuous_config().buffer_time,
+            device.wrap,
+            width,
+        )
+    }
 
-                let device_name = &device.name;
+    /// Unresampled analog samples for the measurements and the spectrum
+    ///
+    /// In continuous mode this is a snapshot refreshed every `RAW_WINDOW_REFRESH`. The same `Arc`
+    /// is returned until the samples change, so the measurements and the spectrum can tell.
+    fn get_raw_analog_data(&mut self, device: &FleaScopeDevice) -> AnalogSamples {
+        profiling::scope!("get_measurement_data");
 
-                // Get windowed data from our channel-fed buffer
-                if let Some(buffer) = self.continuous_buffers.get(device_name) {
-                    profiling::scope!("buffer_windowed_data");
-                    buffer.get_data_in_window(
-                        device.get_continuous_config().buffer_time,
-                        device.wrap,
-                        self.width,
-                    )
-                } else {
-                    (vec![], vec![])
+        match device.get_capture_mode() {
+            CaptureModeFlat::Continuous => {
+                let buffer_time = device.get_continuous_config().buffer_time;
+                if let Some(window) = self.raw_windows.get(&device.name) {
+                    if window.buffer_time == buffer_time
+                        && window.taken_at.elapsed() < RAW_WINDOW_REFRESH
+                    {
+                        return window.samples.clone();
+                    }
                 }
+                let samples = Arc::new(
+                    self.continuous_buffers
+                        .get(&device.name)
+                        .map(|buffer| buffer.get_samples_in_window(buffer_time))
+                        .unwrap_or_default(),
+                );
+                self.raw_windows.insert(
+                    device.name.clone(),
+                    RawWindow {
+                        taken_at: Instant::now(),
+                        buffer_time,
+                        samples: samples.clone(),
+                    },
+                );
+                samples
             }
             CaptureModeFlat::Triggered => {
-                let data = device.data.load();
-                profiling::scope!("triggered_mode_data");
-                data.get_analog_data()
+                let capture = device.data.load_full();
+                if let Some((cached, samples)) = self.raw_captures.get(&device.name) {
+                    if Arc::ptr_eq(cached, &capture) {
+                        return samples.clone();
+                    }
+                }
+                let samples = Arc::new(capture.get_analog_data());
+                self.raw_captures
+                    .insert(device.name.clone(), (capture, samples.clone()));
+                samples
             }
         }
     }
@@ -335,25 +416,118 @@ impl PlotArea {
         ui: &mut egui::Ui,
         device: &mut FleaScopeDevice,
         device_idx: usize,
+        math: Vec<Overlay>,
     ) {
         profiling::scope!("PlotArea::render_analog_plot");
 
-        let (x_data, y_data) = self.get_analog_data(device);
+        let mut overlays = math;
+        let mut show_live = true;
+        let (x_data, y_data) = match device.get_capture_mode() {
+            CaptureModeFlat::Continuous => {
+                let window = self.get_continuous_window(device);
+                if device.envelope {
+                    overlays.push(Overlay::Envelope {
+                        color: self.colors[0],
+                        bins: window.envelope(),
+                    });
+                }
+                (window.time, window.mean)
+            }
+            CaptureModeFlat::Triggered => {
+                profiling::scope!("triggered_mode_data");
+                device.data.load().get_analog_data()
+            }
+        };
+        if matches!(device.get_capture_mode(), CaptureModeFlat::Triggered) {
+            for trace in device.acquisition.traces() {
+                overlays.push(Overlay::Trace {
+                    name: trace.name.clone(),
+                    color: Color32::GOLD,
+                    points: trace.points.clone(),
+                    dashed: false,
+                });
+            }
+        }
+        if device.persistence.enabled
+            && matches!(device.get_capture_mode(), CaptureModeFlat::Triggered)
+        {
+            let image = device
+                .persistence
+                .image(ui.ctx(), &format!("persistence_{}", device_idx));
+            if let Some(image) = image {
+                overlays.push(Overlay::Image(image));
+                // The density map already holds the latest capture at full brightness
+                show_live = false;
+            }
+        }
+        if matches!(device.get_capture_mode(), CaptureModeFlat::Triggered)
+            && shows_trigger(&device.data.load().x_values)
+        {
+            overlays.push(Overlay::TriggerMarker);
+        }
+        if let Some(reference) = device.reference.reference(&self.references) {
+            overlays.push(Overlay::Trace {
+                name: reference.name.clone(),
+                color: reference.color32(),
+                points: reference.analog_points(&device.reference),
+                dashed: false,
+            });
+            if device.reference.diff {
+                let difference = reference.difference(&device.reference, &x_data, &y_data);
+                overlays.push(Overlay::Trace {
+                    name: format!("Live − {}", reference.name),
+                    color: reference.color32(),
+                    points: x_data
+                        .iter()
+                        .zip(difference)
+                        .filter(|(_, d)| d.is_finite())
+                        .map(|(t, d)| [*t, d])
+                        .collect(),
+                    dashed: true,
+                });
+            }
+        }
+        let (live_x, live_y): (&[f64], &[f64]) = if show_live {
+            (&x_data, &y_data)
+        } else {
+            (&[], &[])
+        };
+        self.show_analog_plot(
+            ui,
+            format!("analog_plot_{}", device_idx),
+            live_x,
+            live_y,
+            &overlays,
+            &mut device.cursors,
+        );
+    }
 
-        if x_data.is_empty() {
+    fn show_analog_plot(
+        &mut self,
+        ui: &mut egui::Ui,
+        id: String,
+        x_data: &[f64],
+        y_data: &[f64],
+        overlays: &[Overlay],
+        cursors: &mut Cursors,
+    ) {
+        if x_data.is_empty() && overlays.is_empty() {
             ui.label("No data available");
             return;
         }
 
-        let plot = Plot::new(format!("analog_plot_{}", device_idx))
+        let plot = Plot::new(&id)
             .height(self.plot_height)
             .show_grid(self.show_grid)
             .auto_bounds([true, true])
             .allow_zoom(true)
-            .allow_drag(true)
-            .allow_scroll(false);
+            .allow_drag(!cursors.blocks_drag(&id))
+            .allow_scroll(false)
+            .legend(Legend::default());
 
         let plot_response = plot.show(ui, |plot_ui| {
+            Self::show_envelopes(plot_ui, overlays);
+
             let filtered_data: Vec<[f64; 2]> = x_data
                 .iter()
                 .zip(y_data.iter())
@@ -367,6 +541,10 @@ impl PlotArea {
                     .width(2.0);
                 plot_ui.line(line);
             }
+
+            Self::show_overlays(plot_ui, overlays);
+
+            cursors.show(plot_ui, &id, true);
         });
         self.width = plot_response.response.rect.width() as u32;
     }
@@ -374,56 +552,653 @@ impl PlotArea {
     fn render_digital_plot(
         &mut self,
         ui: &mut egui::Ui,
-        device: &FleaScopeDevice,
+        device: &mut FleaScopeDevice,
         device_idx: usize,
     ) {
         match device.get_capture_mode() {
             CaptureModeFlat::Continuous => {
-                ui.label("Digital plotting not supported in Continuous mode");
+                if !self.continuous_buffer(device).has_digital() {
+                    ui.label("This device streams no digital channels in Continuous mode");
+                    return;
+                }
+                let window = self.get_continuous_window(device);
+                self.show_digital_plot(
+                    ui,
+                    format!("digital_plot_{}", device_idx),
+                    &device.enabled_channels,
+                    |ch| {
+                        window
+                            .digital_lane(ch)
+                            .into_iter()
+                            .map(|[t, level]| (t, level))
+                            .unzip()
+                    },
+                    &[],
+                    &[],
+                    &mut device.cursors,
+                );
             }
             CaptureModeFlat::Triggered => {
                 let data = device.data.load();
-                let x_data = &data.x_values;
-
-                if x_data.is_empty() {
+                if data.x_values.is_empty() {
                     ui.label("No data available");
                     return;
                 }
+                let mut overlays = Vec::new();
+                if shows_trigger(&data.x_values) {
+                    overlays.push(Overlay::TriggerMarker);
+                }
+                if let Some(reference) = device.reference.reference(&self.references) {
+                    for ch in (0..9).filter(|ch| device.enabled_channels[ch + 1]) {
+                        overlays.push(Overlay::Trace {
+                            name: format!("{} D{}", reference.name, ch),
+                            color: reference.color32(),
+                            points: reference
+                                .digital_points(&device.reference, ch)
+                                .into_iter()
+                                .map(|[t, level]| [t, level + ch as f64 * DIGITAL_LANE_SPACING])
+                                .collect(),
+                            dashed: true,
+                        });
+                    }
+                }
+                let frames = device.decoder.decode(&data.x_values, &data.data_points);
+                self.show_digital_plot(
+                    ui,
+                    format!("digital_plot_{}", device_idx),
+                    &device.enabled_channels,
+                    |ch| data.get_digital_channel_data(ch),
+                    frames,
+                    &overlays,
+                    &mut device.cursors,
+                );
+            }
+        }
+    }
 
-                let plot = Plot::new(format!("digital_plot_{}", device_idx))
-                    .height(self.plot_height * 1.5) // Taller for multiple digital channels
-                    .show_grid(self.show_grid)
-                    .auto_bounds([true, true])
-                    .allow_zoom(true)
-                    .allow_drag(true)
-                    .allow_scroll(false)
-                    .y_axis_min_width(40.0);
-
-                plot.show(ui, |plot_ui| {
-                    for ch in 0..9 {
-                        if !device.enabled_channels[ch + 1] {
-                            continue;
-                        }
+    #[allow(clippy::too_many_arguments)]
+    fn show_digital_plot(
+        &self,
+        ui: &mut egui::Ui,
+        id: String,
+        enabled_channels: &[bool; 10],
+        channel_data: impl Fn(usize) -> (Vec<f64>, Vec<f64>),
+        frames: &[DecodedFrame],
+        overlays: &[Overlay],
+        cursors: &mut Cursors,
+    ) {
+        let plot = Plot::new(&id)
+            .height(self.plot_height * 1.5) // Taller for multiple digital channels
+            .show_grid(self.show_grid)
+            .auto_bounds([true, true])
+            .allow_zoom(true)
+            .allow_drag(!cursors.blocks_drag(&id))
+            .allow_scroll(false)
+            .y_axis_min_width(40.0);
 
-                        let (x_data, y_data) = data.get_digital_channel_data(ch);
-
-                        let filtered_data: Vec<[f64; 2]> = x_data
-                            .iter()
-                            .zip(y_data.iter())
-                            .map(|(x, y)| [*x, *y + ch as f64 * 1.2]) // Offset each channel vertically
-                            .collect();
-
-                        if !filtered_data.is_empty() {
-                            let filtered_points = PlotPoints::from(filtered_data);
-                            let color_idx = (ch + 1) % self.colors.len();
-                            let line = Line::new(format!("D{}", ch), filtered_points)
-                                .color(self.colors[color_idx])
-                                .width(1.5);
-                            plot_ui.line(line);
-                        }
+        plot.show(ui, |plot_ui| {
+            for ch in 0..9 {
+                if !enabled_channels[ch + 1] {
+                    continue;
+                }
+
+                let (x_data, y_data) = channel_data(ch);
+
+                let filtered_data: Vec<[f64; 2]> = x_data
+                    .iter()
+                    .zip(y_data.iter())
+                    .map(|(x, y)| [*x, *y + ch as f64 * DIGITAL_LANE_SPACING]) // Offset each channel vertically
+                    .collect();
+
+                if !filtered_data.is_empty() {
+                    let filtered_points = PlotPoints::from(filtered_data);
+                    let color_idx = (ch + 1) % self.colors.len();
+                    let line = Line::new(format!("D{}", ch), filtered_points)
+                        .color(self.colors[color_idx])
+                        .width(1.5);
+                    plot_ui.line(line);
+                }
+            }
+
+            Self::show_overlays(plot_ui, overlays);
+            Self::show_decoded_frames(plot_ui, enabled_channels, frames);
+
+            // Time cursors only, the y axis of this plot is not a voltage
+            cursors.show(plot_ui, &id, false);
+        });
+    }
+
+    /// Points of one channel of a device, with time zero at the trigger or the latest sample
+    fn combined_points(
+        &mut self,
+        device: &mut FleaScopeDevice,
+        channel: CombinedChannel,
+    ) -> Vec<[f64; 2]> {
+        match device.get_capture_mode() {
+            CaptureModeFlat::Continuous => {
+                let width = self.width;
+                let buffer = self.continuous_buffer(device);
+                let latest = buffer.latest_time();
+                let window = buffer.get_data_in_window(
+                    device.get_continuous_config().buffer_time,
+                    false,
+                    width,
+                );
+                let points = match channel {
+                    CombinedChannel::Analog => window
+                        .time
+                        .iter()
+                        .zip(&window.mean)
+                        .map(|(&t, &v)| [t, v])
+                        .collect(),
+                    CombinedChannel::Digital(ch) => window.digital_lane(ch),
+                };
+                points.into_iter().map(|[t, v]| [t - latest, v]).collect()
+            }
+            CaptureModeFlat::Triggered => {
+                let data = device.data.load();
+                let (time, values) = match channel {
+                    CombinedChannel::Analog => data.get_analog_data(),
+                    CombinedChannel::Digital(ch) => data.get_digital_channel_data(ch),
+                };
+                time.into_iter().zip(values).map(|(t, v)| [t, v]).collect()
+            }
+        }
+    }
+
+    /// Selected channels of all devices on one plot
+    fn render_combined_view(&mut self, ui: &mut egui::Ui, device_manager: &mut DeviceManager) {
+        profiling::scope!("PlotArea::render_combined_view");
+
+        let device_names: Vec<String> = device_manager
+            .get_devices()
+            .iter()
+            .map(|device| device.name.clone())
+            .collect();
+
+        ui.group(|ui| {
+            ui.label(RichText::new("🔀 Combined View").heading().strong());
+            self.combined.ui(ui, &device_names, &self.colors);
+
+            let mut lines = Vec::with_capacity(self.combined.traces.len());
+            for trace in self.combined.traces.clone() {
+                let Some(device) = device_manager
+                    .get_devices_mut()
+                    .iter_mut()
+                    .find(|device| device.name == trace.device)
+                else {
+                    continue;
+                };
+                let points = self.combined_points(device, trace.channel);
+                let skew = self.combined.skew(&trace.device);
+                lines.push((trace.name(), trace.color32(), trace.place(skew, points)));
+            }
+
+            if lines.is_empty() {
+                ui.label("Add traces to compare channels across devices");
+                return;
+            }
+            Plot::new("combined_plot")
+                .height(self.plot_height * 1.5)
+                .show_grid(self.show_grid)
+                .auto_bounds([true, true])
+                .allow_zoom(true)
+                .allow_drag(true)
+                .allow_scroll(false)
+                .legend(Legend::default())
+                .show(ui, |plot_ui| {
+                    for (name, color, points) in lines {
+                        plot_ui.line(
+                            Line::new(name, PlotPoints::from(points))
+                                .color(color)
+                                .width(1.5),
+                        );
                     }
                 });
+        });
+        ui.add_space(10.0);
+    }
+
+    fn add_math_channel(&mut self, device_manager: &DeviceManager) {
+        let index = self.math_channels.len();
+        let color = self.colors[(index + 4) % self.colors.len()];
+        self.math_channels.push(MathChannel::new(
+            format!("M{}", index + 1),
+            "A".to_string(),
+            "V".to_string(),
+            device_manager
+                .get_devices()
+                .first()
+                .map(|device| device.name.clone())
+                .unwrap_or_default(),
+            [color.r(), color.g(), color.b()],
+        ));
+    }
+
+    /// Samples of every device for the math channels to draw from
+    fn math_sources(&mut self, device_manager: &DeviceManager) -> HashMap<String, SourceData> {
+        profiling::scope!("PlotArea::math_sources");
+
+        device_manager
+            .get_devices()
+            .iter()
+            .map(|device| {
+                let source = match device.get_capture_mode() {
+                    CaptureModeFlat::Continuous => {
+                        SourceData::Continuous(self.get_raw_analog_data(device))
+                    }
+                    CaptureModeFlat::Triggered => SourceData::Capture(device.data.load_full()),
+                };
+                (device.name.clone(), source)
+            })
+            .collect()
+    }
+
+    /// Evaluate every math channel, the traces are drawn on the analog plot of their device
+    fn evaluate_math_channels(
+        &mut self,
+        device_manager: &DeviceManager,
+    ) -> Vec<Result<MathTrace, String>> {
+        profiling::scope!("PlotArea::evaluate_math_channels");
+
+        if self.math_channels.is_empty() {
+            return Vec::new();
+        }
+        let sources = self.math_sources(device_manager);
+        self.math_channels
+            .iter_mut()
+            .map(|channel| channel.evaluate(&sources).map_err(|e| format!("{:#}", e)))
+            .collect()
+    }
+
+    /// Math traces of `device`, reduced to about two points per pixel
+    fn math_overlays(&self, device: &str, results: &[Result<MathTrace, String>]) -> Vec<Overlay> {
+        self.math_channels
+            .iter()
+            .zip(results)
+            .filter(|(channel, _)| channel.device == device)
+            .filter_map(|(channel, result)| {
+                let (time, values) = &**result.as_ref().ok()?;
+                // Every sample is kept for the expression, only about two per pixel are drawn
+                let step = (time.len() / (2 * self.width.max(1) as usize)).max(1);
+                let [r, g, b] = channel.color;
+                Some(Overlay::Trace {
+                    name: format!("{} [{}]", channel.name, channel.unit),
+                    color: Color32::from_rgb(r, g, b),
+                    points: time
+                        .iter()
+                        .zip(values)
+                        .step_by(step)
+                        .filter(|(_, v)| v.is_finite())
+                        .map(|(t, v)| [*t, *v])
+                        .collect(),
+                    dashed: false,
+                })
+            })
+            .collect()
+    }
+
+    /// Expression editor of the math channels, with the errors of the last evaluation
+    fn render_math_channels(
+        &mut self,
+        ui: &mut egui::Ui,
+        device_manager: &DeviceManager,
+        results: &[Result<MathTrace, String>],
+    ) {
+        profiling::scope!("PlotArea::render_math_channels");
+
+        if self.math_channels.is_empty() {
+            return;
+        }
+        let mut remove = None;
+
+        ui.group(|ui| {
+            ui.label(RichText::new("🧮 Math Channels").heading().strong());
+            for (idx, channel) in self.math_channels.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_srgb(&mut channel.color);
+                    ui.add(egui::TextEdit::singleline(&mut channel.name).desired_width(60.0));
+                    ui.label("=");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut channel.expression)
+                            .font(egui::TextStyle::Monospace)
+                            .hint_text("A*2.5 - 1.2")
+                            .desired_width(240.0),
+                    )
+                    .on_hover_text(
+                        "Channels: A, D0-D8, t or device.A for another device \
+                         (\"sim://sine\".A for names with symbols)\n\
+                         Operators: + - * / < > & | !\n\
+                         Functions: integrate, derivative, abs, sqrt",
+                    );
+                    ui.label("on");
+                    egui::ComboBox::from_id_salt(format!("math_device_{}", idx))
+                        .selected_text(&channel.device)
+                        .show_ui(ui, |ui| {
+                            for device in device_manager.get_devices() {
+                                ui.selectable_value(
+                                    &mut channel.device,
+                                    device.name.clone(),
+                                    &device.name,
+                                );
+                            }
+                        });
+                    ui.label("Unit:");
+                    ui.add(egui::TextEdit::singleline(&mut channel.unit).desired_width(40.0));
+                    if ui.button("✖").on_hover_text("Remove").clicked() {
+                        remove = Some(idx);
+                    }
+                    if let Some(Err(e)) = results.get(idx) {
+                        ui.colored_label(Color32::LIGHT_RED, e);
+                    }
+                });
+            }
+            ui.label(
+                RichText::new("Math traces are drawn on the analog plot of their device")
+                    .size(9.0)
+                    .color(Color32::LIGHT_GRAY),
+            );
+        });
+        ui.add_space(10.0);
+
+        if let Some(idx) = remove {
+            self.math_channels.remove(idx);
+        }
+    }
+
+    /// The band goes first so the mean line stays visible on top of it
+    fn show_envelopes(plot_ui: &mut egui_plot::PlotUi, overlays: &[Overlay]) {
+        for overlay in overlays {
+            if let Overlay::Envelope { color, bins } = overlay {
+                plot_ui.add(Band::new(
+                    "Min/Max",
+                    color.gamma_multiply(0.35),
+                    bins.clone(),
+                ));
+            }
+        }
+    }
+
+    fn show_overlays(plot_ui: &mut egui_plot::PlotUi, overlays: &[Overlay]) {
+        for overlay in overlays {
+            if let Overlay::Image(image) = overlay {
+                plot_ui.image(PlotImage::new(
+                    "Persistence",
+                    image.texture,
+                    image.center,
+                    image.size,
+                ));
+            }
+        }
+        for overlay in overlays {
+            if let Overlay::Trace {
+                name,
+                color,
+                points,
+                dashed,
+            } = overlay
+            {
+                let mut line = Line::new(name.clone(), PlotPoints::from(points.clone()))
+                    .color(*color)
+                    .width(1.5);
+                if *dashed {
+                    line = line.style(LineStyle::dashed_dense());
+                }
+                plot_ui.line(line);
+            }
+        }
+        if overlays
+            .iter()
+            .any(|overlay| matches!(overlay, Overlay::TriggerMarker))
+        {
+            plot_ui.vline(
+                VLine::new("Trigger", 0.0)
+                    .color(Color32::from_rgb(255, 165, 0))
+                    .style(LineStyle::dashed_loose()),
+            );
+        }
+    }
+
+    fn handle_reference_action(&mut self, device: &mut FleaScopeDevice, action: ReferenceAction) {
+        match action {
+            ReferenceAction::Freeze(name) => {
+                let existing = self.references.iter().position(|r| r.name == name);
+                let color = match existing {
+                    Some(idx) => self.references[idx].color,
+                    None => {
+                        let color = self.colors[(self.references.len() + 4) % self.colors.len()];
+                        [color.r(), color.g(), color.b()]
+                    }
+                };
+                match Reference::freeze(&name, device, color) {
+                    Ok(reference) => {
+                        match existing {
+                            Some(idx) => self.references[idx] = reference,
+                            None => self.references.push(reference),
+                        }
+                        device.reference.selected = Some(name.clone());
+                        device
+                            .reference
+                            .set_status(Ok(format!("Saved reference '{}'", name)));
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to freeze reference {}: {:#}", name, e);
+                        device.reference.set_status(Err(format!("{:#}", e)));
+                    }
+                }
+            }
+            ReferenceAction::Delete(name) => {
+                let Some(idx) = self.references.iter().position(|r| r.name == name) else {
+                    return;
+                };
+                match self.references[idx].delete() {
+                    Ok(()) => {
+                        self.references.remove(idx);
+                        device
+                            .reference
+                            .set_status(Ok(format!("Deleted reference '{}'", name)));
+                    }
+                    Err(e) => device.reference.set_status(Err(format!("{:#}", e))),
+                }
             }
         }
     }
+
+    /// Boxes with the decoded values over the lanes of the decoded channels
+    fn show_decoded_frames(
+        plot_ui: &mut egui_plot::PlotUi,
+        enabled_channels: &[bool; 10],
+        frames: &[DecodedFrame],
+    ) {
+        for frame in frames {
+            if !enabled_channels[frame.channel + 1] {
+                continue;
+            }
+            let color = if frame.error.is_some() {
+                Color32::RED
+            } else {
+                Color32::LIGHT_GREEN
+            };
+            let bottom = frame.channel as f64 * DIGITAL_LANE_SPACING + 0.1;
+            let top = bottom + 0.8;
+            plot_ui.polygon(
+                Polygon::new(
+                    "Decoded",
+                    PlotPoints::from(vec![
+                        [frame.start, bottom],
+                        [frame.end, bottom],
+                        [frame.end, top],
+                        [frame.start, top],
+                    ]),
+                )
+                .fill_color(color.gamma_multiply(0.2))
+                .stroke(egui::Stroke::new(1.0, color)),
+            );
+
+            let width = plot_ui
+                .screen_from_plot(PlotPoint::new(frame.end, bottom))
+                .x
+                - plot_ui
+                    .screen_from_plot(PlotPoint::new(frame.start, bottom))
+                    .x;
+            if width >= MIN_LABELED_FRAME_WIDTH {
+                plot_ui.text(
+                    Text::new(
+                        "Decoded label",
+                        PlotPoint::new((frame.start + frame.end) / 2.0, (bottom + top) / 2.0),
+                        frame.label(),
+                    )
+                    .color(color),
+                );
+            }
+        }
+    }
+
+    fn render_replays(&mut self, ui: &mut egui::Ui) {
+        profiling::scope!("PlotArea::render_replays");
+
+        let mut close = None;
+        let mut replays = std::mem::take(&mut self.replays);
+        for (replay_idx, replay) in replays.iter_mut().enumerate() {
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&replay.name).heading().strong())
+                        .on_hover_text(
+                            replay
+                                .metadata
+                                .iter()
+                                .map(|(key, value)| format!("{}: {}", key, value))
+                                .collect::<Vec<_>>()
+                                .join("\n"),
+                        );
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("✖").on_hover_text("Close").clicked() {
+                            close = Some(replay_idx);
+                        }
+                        ui.toggle_value(&mut replay.cursors.enabled, "⌖ Cursors");
+                        ui.toggle_value(&mut replay.spectrum.enabled, "〰 FFT");
+                        if replay.has_digital {
+                            ui.toggle_value(&mut replay.decoder.enabled, "🔣 Decode");
+                        }
+                        ui.label(format!("📁 {}", replay.path.display()));
+                        ui.colored_label(Color32::GRAY, "●");
+                    });
+                });
+
+                if let Some(playback) = &mut replay.playback {
+                    playback.advance();
+                    Self::render_playback_controls(ui, playback, replay_idx);
+                }
+
+                let (x_data, data_points) = replay.view(self.width);
+                let has_digital = replay.has_digital;
+                let analog: Vec<f64> = data_points.iter().map(|p| p.analog_channel).collect();
+
+                ui.label(RichText::new("Analog Channel (12-bit)").strong());
+                self.show_analog_plot(
+                    ui,
+                    format!("replay_analog_plot_{}", replay_idx),
+                    &x_data,
+                    &analog,
+                    &[],
+                    &mut replay.cursors,
+                );
+
+                let samples = replay.analog_window();
+                let time = &samples.0;
+                let points = replay.window().1.to_vec();
+                let id = replay.path.display().to_string();
+                replay
+                    .spectrum
+                    .ui(ui, &id, &samples, self.plot_height, self.show_grid);
+
+                if has_digital && !x_data.is_empty() {
+                    ui.label(RichText::new("Digital Channels").strong());
+                    self.show_digital_plot(
+                        ui,
+                        format!("replay_digital_plot_{}", replay_idx),
+                        &[true; 10],
+                        |ch| {
+                            let y = data_points
+                                .iter()
+                                .map(|p| if p.digital_channels[ch] { 1.0 } else { 0.0 })
+                                .collect();
+                            (x_data.clone(), y)
+                        },
+                        replay.decoder.decode(time, &points),
+                        &[],
+                        &mut replay.cursors,
+                    );
+                    replay.decoder.ui(ui, &id);
+                }
+
+                replay.cursors.readout(ui);
+
+                replay.measurements.ui(ui, &id, &samples);
+            });
+            ui.add_space(10.0);
+        }
+
+        if let Some(idx) = close {
+            replays.remove(idx);
+        }
+        self.replays = replays;
+    }
+
+    fn render_playback_controls(
+        ui: &mut egui::Ui,
+        playback: &mut crate::replay::Playback,
+        replay_idx: usize,
+    ) {
+        ui.horizontal(|ui| {
+            let label = if playback.playing { "⏸" } else { "▶" };
+            if ui.button(label).clicked() {
+                if playback.playing {
+                    playback.pause();
+                } else {
+                    playback.play();
+                }
+            }
+
+            let mut position = playback.position;
+            let seek = ui.add(
+                egui::Slider::new(&mut position, playback.start()..=playback.end())
+                    .suffix(" s")
+                    .fixed_decimals(3),
+            );
+            if seek.changed() {
+                playback.seek(position);
+            }
+
+            egui::ComboBox::from_id_salt(format!("replay_speed_{}", replay_idx))
+                .selected_text(format!("{}×", playback.speed))
+                .width(60.0)
+                .show_ui(ui, |ui| {
+                    for speed in PLAYBACK_SPEEDS {
+                        ui.selectable_value(&mut playback.speed, speed, format!("{}×", speed));
+                    }
+                });
+
+            ui.label("Window:");
+            ui.add(
+                egui::Slider::new(&mut playback.window, 0.001..=10.0)
+                    .logarithmic(true)
+                    .custom_formatter(|n, _| {
+                        if n >= 1.0 {
+                            format!("{:.2}s", n)
+                        } else {
+                            format!("{:.0}ms", n * 1000.0)
+                        }
+                    }),
+            );
+        });
+    }
+}
+
+/// Whether a triggered capture reaches the trigger instant at `t = 0`
+fn shows_trigger(x_values: &[f64]) -> bool {
+    match (x_values.first(), x_values.last()) {
+        (Some(&t0), Some(&t1)) => t0 <= 0.0 && t1 >= 0.0,
+        _ => false,
+    }
 }
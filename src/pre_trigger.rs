@@ -0,0 +1,297 @@
+use fleascope_rs::{AnalogTriggerBehavior, BitState, DigitalTriggerBehavior};
+
+use crate::device::{DataPoint, TriggerConfig, TriggerSource, MAX_TIME_FRAME};
+
+/// How a triggered capture is placed around the trigger instant, which is `t = 0` on the plot
+pub enum TriggerPlacement {
+    /// The firmware starts sampling `delay` seconds after the trigger
+    Hardware { delay: f64 },
+    /// The firmware only starts at the trigger, so seeing before it takes a software trigger
+    Software(PreTrigger),
+}
+
+impl TriggerPlacement {
+    pub fn new(time_frame: f64, trigger: &TriggerConfig) -> Self {
+        let acquisition = acquisition(time_frame);
+        let pre = (trigger.position * time_frame - trigger.offset).min(acquisition - time_frame);
+        if pre <= 0.0 {
+            return TriggerPlacement::Hardware { delay: -pre };
+        }
+        TriggerPlacement::Software(PreTrigger {
+            trigger: trigger.clone(),
+            time_frame,
+            pre,
+            acquisition,
+        })
+    }
+}
+
+/// Free-running captures twice the window, so an event can be found anywhere in the first half
+fn acquisition(time_frame: f64) -> f64 {
+    (time_frame * 2.0).min(MAX_TIME_FRAME)
+}
+
+/// Largest trigger position that fits the free-running capture, larger ones are cut down to it
+pub fn max_position(time_frame: f64, offset: f64) -> f64 {
+    (acquisition(time_frame) - time_frame + offset) / time_frame
+}
+
+/// Software trigger searched in a free-running capture longer than the window
+pub struct PreTrigger {
+    trigger: TriggerConfig,
+    time_frame: f64,
+    /// Seconds of the window before the trigger instant
+    pre: f64,
+    /// Length of the free-running capture
+    pub acquisition: f64,
+}
+
+impl PreTrigger {
+    /// Cut the window around the first trigger event, `None` if there is none and the trigger is not on auto
+    pub fn locate(
+        &self,
+        x_values: &[f64],
+        data_points: &[DataPoint],
+    ) -> Option<(Vec<f64>, Vec<DataPoint>)> {
+        profiling::scope!("PreTrigger::locate");
+
+        let dt = match x_values {
+            [t0, t1, ..] if t1 > t0 => t1 - t0,
+            _ => return None,
+        };
+        let samples = ((self.time_frame / dt).round() as usize).min(x_values.len());
+        if samples < 2 {
+            return None;
+        }
+        let pre = ((self.pre / dt).round() as usize).clamp(1, samples.saturating_sub(1));
+        let last = x_values.len() - (samples - pre);
+
+        let index = (pre..=last)
+            .find(|&i| fires(&self.trigger, &data_points[i - 1], &data_points[i]))
+            // Auto shows the signal anyway, placed as if the trigger had fired on time
+            .or_else(|| is_auto(&self.trigger).then_some(pre))?;
+
+        let start = index - pre;
+        let t0 = x_values[index];
+        Some((
+            x_values[start..start + samples]
+                .iter()
+                .map(|t| t - t0)
+                .collect(),
+            data_points[start..start + samples].to_vec(),
+        ))
+    }
+}
+
+fn is_auto(trigger: &TriggerConfig) -> bool {
+    match trigger.source {
+        TriggerSource::Analog => trigger.analog.behavior == AnalogTriggerBehavior::Auto,
+        TriggerSource::Digital => trigger.digital.behavior == DigitalTriggerBehavior::Auto,
+    }
+}
+
+/// Same conditions the firmware checks, on calibrated samples
+fn fires(trigger: &TriggerConfig, before: &DataPoint, now: &DataPoint) -> bool {
+    match trigger.source {
+        TriggerSource::Analog => {
+            let level = trigger.analog.volts;
+            let (before, now) = (before.analog_channel, now.analog_channel);
+            match trigger.analog.behavior {
+                AnalogTriggerBehavior::Rising => before < level && now >= level,
+                AnalogTriggerBehavior::Falling => before > level && now <= level,
+                AnalogTriggerBehavior::Level | AnalogTriggerBehavior::Auto => {
+                    (before < level) != (now < level)
+                }
+            }
+        }
+        TriggerSource::Digital => {
+            let matches = |point: &DataPoint| {
+                trigger
+                    .digital
+                    .bit_states
+                    .iter()
+                    .zip(point.digital_channels)
+                    .all(|(state, high)| match state {
+                        BitState::DontCare => true,
+                        BitState::High => high,
+                        BitState::Low => !high,
+                    })
+            };
+            match trigger.digital.behavior {
+                DigitalTriggerBehavior::While | DigitalTriggerBehavior::Auto => matches(now),
+                DigitalTriggerBehavior::Start => !matches(before) && matches(now),
+                DigitalTriggerBehavior::Stop => matches(before) && !matches(now),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fleascope_rs::{AnalogTrigger, DigitalTrigger};
+
+    /// A 100ms window at one sample per millisecond, captured free-running for twice as long
+    const TIME_FRAME: f64 = 0.1;
+    const DT: f64 = 1e-3;
+    const WINDOW: usize = 100;
+    const CAPTURED: usize = 2 * WINDOW;
+
+    fn rising(position: f64) -> TriggerConfig {
+        TriggerConfig {
+            source: TriggerSource::Analog,
+            analog: AnalogTrigger::start_capturing_when(0.5).rising_edge(),
+            position,
+            ..TriggerConfig::default()
+        }
+    }
+
+    fn software(trigger: &TriggerConfig) -> PreTrigger {
+        match TriggerPlacement::new(TIME_FRAME, trigger) {
+            TriggerPlacement::Software(pre_trigger) => pre_trigger,
+            TriggerPlacement::Hardware { .. } => panic!("expected a software trigger"),
+        }
+    }
+
+    /// Free-running capture that is high on D0 and at 1V from each index of `edges` for ten samples
+    fn capture(edges: &[usize]) -> (Vec<f64>, Vec<DataPoint>) {
+        (0..CAPTURED)
+            .map(|i| {
+                let high = edges.iter().any(|&edge| (edge..edge + 10).contains(&i));
+                let mut digital_channels = [false; 9];
+                digital_channels[0] = high;
+                (
+                    i as f64 * DT,
+                    DataPoint {
+                        analog_channel: if high { 1.0 } else { 0.0 },
+                        digital_channels,
+                    },
+                )
+            })
+            .unzip()
+    }
+
+    /// Index of the trigger sample in the located window, which is the one at `t = 0`
+    fn trigger_index(x_values: &[f64]) -> usize {
+        x_values.iter().position(|t| t.abs() < DT / 2.0).unwrap()
+    }
+
+    #[test]
+    fn placement_uses_the_hardware_delay_without_pre_trigger() {
+        let delayed = TriggerConfig {
+            offset: 0.02,
+            ..rising(0.0)
+        };
+        for (trigger, expected) in [(rising(0.0), 0.0), (delayed, 0.02)] {
+            match TriggerPlacement::new(TIME_FRAME, &trigger) {
+                TriggerPlacement::Hardware { delay } => assert!((delay - expected).abs() < 1e-12),
+                TriggerPlacement::Software(_) => panic!("expected a hardware trigger"),
+            }
+        }
+    }
+
+    #[test]
+    fn placement_keeps_the_requested_share_before_the_trigger() {
+        for position in [0.1, 0.5, 0.9] {
+            let pre_trigger = software(&rising(position));
+            assert!((pre_trigger.pre - position * TIME_FRAME).abs() < 1e-12);
+            assert_eq!(pre_trigger.acquisition, 2.0 * TIME_FRAME);
+        }
+        // An offset takes back part of the pre-trigger time
+        let offset = TriggerConfig {
+            offset: 0.02,
+            ..rising(0.5)
+        };
+        assert!((software(&offset).pre - 0.03).abs() < 1e-12);
+    }
+
+    #[test]
+    fn placement_is_cut_down_when_the_window_does_not_fit() {
+        // Two 3s windows exceed the longest capture, leaving 0.49s before the trigger
+        let time_frame = 3.0;
+        let TriggerPlacement::Software(pre_trigger) =
+            TriggerPlacement::new(time_frame, &rising(0.5))
+        else {
+            panic!("expected a software trigger");
+        };
+        assert_eq!(pre_trigger.acquisition, MAX_TIME_FRAME);
+        assert!((pre_trigger.pre - (MAX_TIME_FRAME - time_frame)).abs() < 1e-12);
+        assert!((max_position(time_frame, 0.0) * time_frame - pre_trigger.pre).abs() < 1e-12);
+    }
+
+    #[test]
+    fn locate_places_the_trigger_at_the_requested_position() {
+        let (x_values, data_points) = capture(&[100]);
+        for position in [0.1, 0.5, 0.9] {
+            let (x, points) = software(&rising(position))
+                .locate(&x_values, &data_points)
+                .unwrap();
+            let pre = (position * WINDOW as f64).round() as usize;
+            assert_eq!(x.len(), WINDOW);
+            assert_eq!(points.len(), WINDOW);
+            assert_eq!(trigger_index(&x), pre);
+            assert!((x[0] + pre as f64 * DT).abs() < 1e-9);
+            assert_eq!(points[pre].analog_channel, 1.0);
+            assert_eq!(points[pre - 1].analog_channel, 0.0);
+        }
+    }
+
+    #[test]
+    fn locate_skips_events_without_enough_signal_before_them() {
+        // The first edge comes before half a window was captured, so the second one is used
+        let (x_values, data_points) = capture(&[20, 90]);
+        let (x, points) = software(&rising(0.5))
+            .locate(&x_values, &data_points)
+            .unwrap();
+        assert_eq!(trigger_index(&x), 50);
+        assert_eq!(points[50].analog_channel, 1.0);
+        assert!(points[..50].iter().all(|point| point.analog_channel == 0.0));
+    }
+
+    #[test]
+    fn locate_accepts_a_trigger_at_the_end_of_the_buffer() {
+        // Half a window after the trigger at 150 fills the capture up to its last sample
+        let (x_values, data_points) = capture(&[150]);
+        let (x, points) = software(&rising(0.5))
+            .locate(&x_values, &data_points)
+            .unwrap();
+        assert_eq!(trigger_index(&x), 50);
+        assert_eq!(points[50].analog_channel, 1.0);
+        assert_eq!(*x.last().unwrap(), x_values[CAPTURED - 1] - x_values[150]);
+    }
+
+    #[test]
+    fn locate_gives_up_when_the_window_does_not_fit_after_the_trigger() {
+        let (x_values, data_points) = capture(&[151]);
+        assert!(software(&rising(0.5))
+            .locate(&x_values, &data_points)
+            .is_none());
+
+        // Auto shows the start of the capture as if the trigger had fired on time
+        let auto = TriggerConfig {
+            analog: AnalogTrigger::start_capturing_when(0.5).auto(),
+            ..rising(0.5)
+        };
+        let (x, points) = software(&auto).locate(&x_values, &data_points).unwrap();
+        assert_eq!(trigger_index(&x), 50);
+        assert!((x[0] + 50.0 * DT).abs() < 1e-9);
+        assert!(points.iter().all(|point| point.analog_channel == 0.0));
+    }
+
+    #[test]
+    fn locate_checks_digital_patterns() {
+        let (x_values, data_points) = capture(&[60]);
+        let trigger = TriggerConfig {
+            source: TriggerSource::Digital,
+            digital: DigitalTrigger::start_capturing_when()
+                .bit0(BitState::High)
+                .stops_matching(),
+            position: 0.1,
+            ..TriggerConfig::default()
+        };
+        let (x, points) = software(&trigger).locate(&x_values, &data_points).unwrap();
+        assert_eq!(trigger_index(&x), 10);
+        assert!(points[9].digital_channels[0]);
+        assert!(!points[10].digital_channels[0]);
+    }
+}
@@ -0,0 +1,584 @@
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use fleascope_rs::ProbeType;
+use polars::io::parquet::write::BatchedWriter;
+use polars::prelude::{Column, DataFrame, KeyValueMetadata, ParquetWriter};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::capture_export::{file_stem, ANALOG_COLUMN, DIGITAL_COLUMNS, TIME_COLUMN};
+use crate::continuous_buffer::ContinuousBatch;
+
+/// Default directory for continuous recordings, relative to the working directory
+pub const RECORDING_DIR: &str = "recordings";
+
+pub const SAMPLE_COLUMN: &str = "sample";
+
+/// Counts up whenever samples were lost, `time` jumps over the gap between segments
+pub const SEGMENT_COLUMN: &str = "segment";
+
+/// Samples collected before they are written out as one row group (~1.3s at full rate)
+const CHUNK_SAMPLES: usize = 65_536;
+
+/// Channel end the worker pushes calibrated continuous batches into
+pub type BatchSender = mpsc::Sender<RecordedBatch>;
+
+/// Continuous batch on its way to the recorder
+pub struct RecordedBatch {
+    pub batch: ContinuousBatch,
+    /// When the worker received the batch, shortly after its last sample
+    pub received: Instant,
+    /// `false` if samples were lost right before this batch, e.g. after the stream restarted
+    pub contiguous: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordingConfig {
+    pub dir: PathBuf,
+    /// Start a new file once the current one exceeds this size
+    pub max_file_bytes: u64,
+    /// Start a new file once the current one holds this many seconds of signal
+    pub max_file_duration: f64,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(RECORDING_DIR),
+            max_file_bytes: 256 * 1024 * 1024,
+            max_file_duration: 600.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordingStatus {
+    pub started: Instant,
+    pub samples: u64,
+    /// Bytes on disk over all files of this recording
+    pub bytes: u64,
+    pub files: usize,
+    pub current_file: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// Streams continuous batches into rotating Parquet files on a dedicated thread
+pub struct Recorder {
+    stop: Arc<AtomicBool>,
+    status: Arc<ArcSwap<RecordingStatus>>,
+    thread: JoinHandle<()>,
+}
+
+impl Recorder {
+    pub fn start(
+        device: &str,
+        probe: ProbeType,
+        sample_rate_hz: u32,
+        config: RecordingConfig,
+    ) -> Result<(Self, BatchSender)> {
+        std::fs::create_dir_all(&config.dir)
+            .with_context(|| format!("Failed to create {}", config.dir.display()))?;
+
+        let (tx, rx) = mpsc::channel::<RecordedBatch>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let started = Instant::now();
+        let status = Arc::new(ArcSwap::new(Arc::new(RecordingStatus {
+            started,
+            samples: 0,
+            bytes: 0,
+            files: 0,
+            current_file: None,
+            error: None,
+        })));
+
+        let mut writer =
+            RecordingWriter::new(device, probe, sample_rate_hz, config, status.clone());
+        let thread_stop = stop.clone();
+        let thread = std::thread::Builder::new()
+            .name(format!("recorder {}", device))
+            .spawn(move || {
+                profiling::register_thread!("Recorder");
+                if let Err(e) = writer.run(rx, &thread_stop) {
+                    tracing::error!("Recording failed: {:#}", e);
+                    writer.update_status(Some(format!("{:#}", e)));
+                }
+            })
+            .context("Failed to spawn recorder thread")?;
+
+        Ok((
+            Self {
+                stop,
+                status,
+                thread,
+            },
+            tx,
+        ))
+    }
+
+    pub fn status(&self) -> Arc<RecordingStatus> {
+        self.status.load_full()
+    }
+
+    /// Flush outstanding samples, close the current file and return the final status
+    pub fn stop(self) -> RecordingStatus {
+        self.stop.store(true, Ordering::Relaxed);
+        if self.thread.join().is_err() {
+            tracing::error!("Recorder thread panicked");
+        }
+        self.status.load().as_ref().clone()
+    }
+}
+
+struct OpenFile {
+    path: PathBuf,
+    writer: BatchedWriter<File>,
+    first_sample: u64,
+}
+
+/// Columns of the samples not written yet
+#[derive(Default)]
+struct Pending {
+    segment: Vec<u32>,
+    time: Vec<f64>,
+    analog: Vec<f64>,
+    bitmap: Vec<u16>,
+}
+
+struct RecordingWriter {
+    config: RecordingConfig,
+    stem: String,
+    device: String,
+    probe: ProbeType,
+    sample_rate_hz: u32,
+    started_at: DateTime<Utc>,
+    /// Time zero of the `time` column
+    started: Instant,
+    status: Arc<ArcSwap<RecordingStatus>>,
+    file: Option<OpenFile>,
+    pending: Pending,
+    /// Whether the pending samples and the open file carry the digital channels
+    has_digital: bool,
+    /// Time of the sample following the last one received, `None` before the first batch
+    next_time: Option<f64>,
+    segment: u32,
+    /// Samples received so far, which is also the index of the next sample
+    samples: u64,
+    /// Size of all closed files
+    finished_bytes: u64,
+}
+
+impl RecordingWriter {
+    fn new(
+        device: &str,
+        probe: ProbeType,
+        sample_rate_hz: u32,
+        config: RecordingConfig,
+        status: Arc<ArcSwap<RecordingStatus>>,
+    ) -> Self {
+        Self {
+            config,
+            stem: file_stem(device, Utc::now()),
+            device: device.to_string(),
+            probe,
+            sample_rate_hz,
+            started_at: Utc::now(),
+            started: status.load().started,
+            status,
+            file: None,
+            pending: Pending::default(),
+            has_digital: false,
+            next_time: None,
+            segment: 0,
+            samples: 0,
+            finished_bytes: 0,
+        }
+    }
+
+    fn run(&mut self, rx: mpsc::Receiver<RecordedBatch>, stop: &AtomicBool) -> Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(batch) => {
+                    self.push(batch)?;
+                    if self.pending.analog.len() >= CHUNK_SAMPLES {
+                        self.write_pending()?;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        // Keep whatever already made it into the channel
+        while let Ok(batch) = rx.try_recv() {
+            self.push(batch)?;
+        }
+        self.write_pending()?;
+        self.close_file()?;
+        self.update_status(None);
+        Ok(())
+    }
+
+    /// Stamp a batch with its time and queue it for writing
+    fn push(&mut self, recorded: RecordedBatch) -> Result<()> {
+        let RecordedBatch {
+            batch,
+            received,
+            contiguous,
+        } = recorded;
+        if batch.analog.is_empty() {
+            return Ok(());
+        }
+        // A file keeps the columns it was opened with
+        if batch.bitmap.is_some() != self.has_digital {
+            self.write_pending()?;
+            self.close_file()?;
+            self.has_digital = batch.bitmap.is_some();
+        }
+
+        let dt = 1.0 / self.sample_rate_hz as f64;
+        let duration = batch.analog.len() as f64 * dt;
+        // The last sample was taken about when the batch arrived
+        let arrived = received
+            .saturating_duration_since(self.started)
+            .as_secs_f64()
+            - duration;
+        let start = match self.next_time {
+            Some(next) if contiguous => next,
+            Some(next) => {
+                self.segment += 1;
+                arrived.max(next)
+            }
+            None => arrived.max(0.0),
+        };
+        self.next_time = Some(start + duration);
+
+        let count = batch.analog.len();
+        self.pending
+            .segment
+            .extend(std::iter::repeat_n(self.segment, count));
+        self.pending
+            .time
+            .extend((0..count).map(|i| start + i as f64 * dt));
+        self.pending.analog.extend_from_slice(&batch.analog);
+        if let Some(bitmap) = &batch.bitmap {
+            self.pending.bitmap.extend_from_slice(bitmap);
+        }
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> Result<()> {
+        profiling::scope!("RecordingWriter::write_pending");
+        if self.pending.analog.is_empty() {
+            return Ok(());
+        }
+
+        let first = self.samples;
+        let pending = std::mem::take(&mut self.pending);
+        let count = pending.analog.len() as u64;
+        let dt = 1.0 / self.sample_rate_hz as f64;
+        let mut columns = vec![
+            Column::new(
+                SAMPLE_COLUMN.into(),
+                (first..first + count).collect::<Vec<u64>>(),
+            ),
+            Column::new(SEGMENT_COLUMN.into(), pending.segment),
+            Column::new(TIME_COLUMN.into(), pending.time),
+            Column::new(ANALOG_COLUMN.into(), pending.analog),
+        ];
+        if self.has_digital {
+            for (ch, name) in DIGITAL_COLUMNS.iter().enumerate() {
+                columns.push(Column::new(
+                    (*name).into(),
+                    pending
+                        .bitmap
+                        .iter()
+                        .map(|bits| bits & (1 << ch) != 0)
+                        .collect::<Vec<_>>(),
+                ));
+            }
+        }
+        let df = DataFrame::new(columns)?;
+
+        if self.file.is_none() {
+            self.open_file(&df)?;
+        }
+        let file = self.file.as_mut().expect("opened above");
+        file.writer.write_batch(&df)?;
+        self.samples += count;
+
+        let file_bytes = file_size(&file.path);
+        let file_duration = (self.samples - file.first_sample) as f64 * dt;
+        if file_bytes >= self.config.max_file_bytes
+            || file_duration >= self.config.max_file_duration
+        {
+            self.close_file()?;
+        }
+        self.update_status(None);
+        Ok(())
+    }
+
+    fn open_file(&mut self, df: &DataFrame) -> Result<()> {
+        let index = self.status.load().files;
+        let path = self
+            .config
+            .dir
+            .join(format!("{}_{:03}.parquet", self.stem, index));
+        let file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let metadata = vec![
+            ("device".to_string(), self.device.clone()),
+            (
+                "probe".to_string(),
+                format!("x{}", self.probe.to_multiplier()),
+            ),
+            (
+                "sample_rate_hz".to_string(),
+                self.sample_rate_hz.to_string(),
+            ),
+            ("started_at".to_string(), self.started_at.to_rfc3339()),
+            ("file_index".to_string(), index.to_string()),
+        ];
+        let writer = ParquetWriter::new(file)
+            .with_key_value_metadata(Some(KeyValueMetadata::from_static(metadata)))
+            .batched(df.schema())?;
+
+        tracing::info!("Recording {} to {}", self.device, path.display());
+        self.file = Some(OpenFile {
+            path,
+            writer,
+            first_sample: self.samples,
+        });
+        let status = self.status.load();
+        self.status.store(Arc::new(RecordingStatus {
+            files: status.files + 1,
+            ..status.as_ref().clone()
+        }));
+        Ok(())
+    }
+
+    fn close_file(&mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.writer.finish()?;
+            self.finished_bytes += file_size(&file.path);
+        }
+        Ok(())
+    }
+
+    fn update_status(&self, error: Option<String>) {
+        let status = self.status.load();
+        let current_bytes = self.file.as_ref().map_or(0, |f| file_size(&f.path));
+        self.status.store(Arc::new(RecordingStatus {
+            samples: self.samples,
+            bytes: self.finished_bytes + current_bytes,
+            current_file: self.file.as_ref().map(|f| f.path.clone()),
+            error: error.or_else(|| status.error.clone()),
+            ..status.as_ref().clone()
+        }));
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{read_capture, CaptureFile};
+    use polars::prelude::{ParquetReader, SerReader};
+
+    /// One chunk per second, so a file of one chunk holds a second of signal
+    const RATE: u32 = CHUNK_SAMPLES as u32;
+    const BATCH: usize = CHUNK_SAMPLES / 4;
+
+    /// Empty directory for one test, removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "fleascope-recorder-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn batch(first: usize, digital: bool, contiguous: bool) -> RecordedBatch {
+        let samples = first..first + BATCH;
+        RecordedBatch {
+            batch: ContinuousBatch {
+                analog: samples
+                    .clone()
+                    .map(|i| (i % 1000) as f64 / 1000.0)
+                    .collect(),
+                bitmap: digital.then(|| samples.map(|i| (i % 512) as u16).collect()),
+            },
+            received: Instant::now(),
+            contiguous,
+        }
+    }
+
+    /// Run a writer over `batches` until the channel is empty, as when the worker stops recording
+    fn record(dir: &Path, config: RecordingConfig, batches: Vec<RecordedBatch>) -> RecordingStatus {
+        let status = Arc::new(ArcSwap::new(Arc::new(RecordingStatus {
+            started: Instant::now(),
+            samples: 0,
+            bytes: 0,
+            files: 0,
+            current_file: None,
+            error: None,
+        })));
+        let config = RecordingConfig {
+            dir: dir.to_path_buf(),
+            ..config
+        };
+        let mut writer =
+            RecordingWriter::new("sim://test", ProbeType::X1, RATE, config, status.clone());
+        let (tx, rx) = mpsc::channel();
+        for batch in batches {
+            tx.send(batch).unwrap();
+        }
+        drop(tx);
+        writer.run(rx, &AtomicBool::new(false)).unwrap();
+        status.load().as_ref().clone()
+    }
+
+    /// Every file of the recording in order, with its sample and segment columns
+    fn read_back(dir: &Path) -> Vec<(CaptureFile, Vec<u64>, Vec<u32>)> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| {
+                let capture = read_capture(path).unwrap();
+                let df = ParquetReader::new(File::open(path).unwrap())
+                    .finish()
+                    .unwrap();
+                let samples = df.column(SAMPLE_COLUMN).unwrap().u64().unwrap();
+                let segments = df.column(SEGMENT_COLUMN).unwrap().u32().unwrap();
+                (
+                    capture,
+                    samples.into_no_null_iter().collect(),
+                    segments.into_no_null_iter().collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// Sample indices of all files, checked to run on without a gap or repeat
+    fn assert_continuous(files: &[(CaptureFile, Vec<u64>, Vec<u32>)], total: u64) {
+        let samples: Vec<u64> = files.iter().flat_map(|(_, s, _)| s.clone()).collect();
+        assert_eq!(samples, (0..total).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rotates_files_by_duration() {
+        let dir = TempDir::new("duration");
+        let batches = (0..12).map(|i| batch(i * BATCH, false, true)).collect();
+        let config = RecordingConfig {
+            max_file_duration: 2.0,
+            ..RecordingConfig::default()
+        };
+        let status = record(&dir.0, config, batches);
+
+        let files = read_back(&dir.0);
+        assert_eq!(files.len(), 2);
+        assert_eq!(status.files, 2);
+        assert_eq!(status.samples, 12 * BATCH as u64);
+        assert_continuous(&files, 12 * BATCH as u64);
+        assert_eq!(files[0].0.x_values.len(), 2 * CHUNK_SAMPLES);
+        assert!(files.iter().all(|(capture, _, _)| capture.is_recording));
+        // Time runs on from one file into the next
+        let last = *files[0].0.x_values.last().unwrap();
+        assert!((files[1].0.x_values[0] - last - 1.0 / RATE as f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rotates_files_by_size() {
+        let dir = TempDir::new("size");
+        let batches = (0..12).map(|i| batch(i * BATCH, true, true)).collect();
+        let config = RecordingConfig {
+            max_file_bytes: 1,
+            ..RecordingConfig::default()
+        };
+        record(&dir.0, config, batches);
+
+        // Every chunk exceeds a byte
+        let files = read_back(&dir.0);
+        assert_eq!(files.len(), 3);
+        assert_continuous(&files, 12 * BATCH as u64);
+        let indices: Vec<&str> = files
+            .iter()
+            .map(|(capture, _, _)| {
+                capture
+                    .metadata
+                    .iter()
+                    .find(|(key, _)| key == "file_index")
+                    .map(|(_, value)| value.as_str())
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(indices, ["0", "1", "2"]);
+    }
+
+    #[test]
+    fn numbers_segments_across_gaps() {
+        let dir = TempDir::new("segments");
+        let batches = vec![
+            batch(0, false, true),
+            batch(BATCH, false, true),
+            batch(2 * BATCH, false, false),
+            batch(3 * BATCH, false, true),
+            batch(4 * BATCH, false, false),
+        ];
+        record(&dir.0, RecordingConfig::default(), batches);
+
+        let files = read_back(&dir.0);
+        assert_eq!(files.len(), 1);
+        let (capture, _, segments) = &files[0];
+        assert_continuous(&files, 5 * BATCH as u64);
+        let segment_of = |batch: usize| segments[batch * BATCH];
+        assert_eq!((0..5).map(segment_of).collect::<Vec<_>>(), [0, 0, 1, 1, 2]);
+        // Time never runs backwards, not even across a gap
+        assert!(capture.x_values.windows(2).all(|t| t[1] > t[0]));
+    }
+
+    #[test]
+    fn starts_a_new_file_when_digital_channels_appear() {
+        let dir = TempDir::new("digital");
+        let batches = vec![
+            batch(0, false, true),
+            batch(BATCH, false, true),
+            batch(2 * BATCH, true, true),
+            batch(3 * BATCH, true, true),
+        ];
+        record(&dir.0, RecordingConfig::default(), batches);
+
+        let files = read_back(&dir.0);
+        assert_eq!(files.len(), 2);
+        assert_continuous(&files, 4 * BATCH as u64);
+        assert!(!files[0].0.has_digital);
+        assert!(files[1].0.has_digital);
+        // Sample 2 * BATCH + 3 has bitmap (2 * BATCH + 3) % 512 = 3: D0 and D1 high
+        let point = &files[1].0.data_points[3];
+        assert_eq!(&point.digital_channels[..3], [true, true, false]);
+    }
+}
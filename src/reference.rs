@@ -0,0 +1,359 @@
+use anyhow::{Context, Result};
+use egui::{Color32, RichText};
+use std::path::{Path, PathBuf};
+
+use crate::capture_export::{self, CaptureMetadata};
+use crate::control_panel::pretty_print_number;
+use crate::device::DataPoint;
+use crate::math_channel::resample;
+use crate::replay;
+use crate::worker_interface::{CaptureModeFlat, FleaScopeDevice};
+
+/// Directory the references are kept in, relative to the working directory
+pub const REFERENCE_DIR: &str = "references";
+
+/// Metadata key of the trace color, as `#rrggbb`
+const COLOR_KEY: &str = "color";
+
+/// A frozen capture to compare live captures against
+pub struct Reference {
+    pub name: String,
+    pub color: [u8; 3],
+    /// Color stored in the file, differs from `color` while the user is still picking
+    saved_color: [u8; 3],
+    path: PathBuf,
+    metadata: Vec<(String, String)>,
+    time: Vec<f64>,
+    data_points: Vec<DataPoint>,
+}
+
+impl Reference {
+    /// Freeze the latest triggered capture of `device` and store it in [`REFERENCE_DIR`]
+    pub fn freeze(name: &str, device: &FleaScopeDevice, color: [u8; 3]) -> Result<Self> {
+        // The continuous stream never updates the triggered capture, which would be stale
+        if matches!(device.get_capture_mode(), CaptureModeFlat::Continuous) {
+            anyhow::bail!(
+                "Switch {} to triggered mode to freeze a capture",
+                device.name
+            );
+        }
+        let data = device.data.load();
+        if data.data_points.is_empty() {
+            anyhow::bail!("No capture available for {}", device.name);
+        }
+
+        let dir = Path::new(REFERENCE_DIR);
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let mut reference = Self {
+            name: name.to_string(),
+            color,
+            saved_color: color,
+            path: dir.join(format!("{}.parquet", capture_export::safe_file_name(name))),
+            metadata: CaptureMetadata::from_capture(&device.name, &data)?.entries(),
+            time: data.x_values.clone(),
+            data_points: data.data_points.clone(),
+        };
+        reference.save()?;
+        tracing::info!("Saved reference {} to {}", name, reference.path.display());
+        Ok(reference)
+    }
+
+    /// Write the samples and the metadata, including the current color, to the reference's file
+    fn save(&mut self) -> Result<()> {
+        let [r, g, b] = self.color;
+        self.metadata.retain(|(key, _)| key != COLOR_KEY);
+        self.metadata.push((
+            COLOR_KEY.to_string(),
+            format!("#{:02x}{:02x}{:02x}", r, g, b),
+        ));
+        let mut df = capture_export::samples_to_dataframe(&self.time, &self.data_points)?;
+        capture_export::write_parquet_entries(&self.path, &mut df, self.metadata.clone())?;
+        self.saved_color = self.color;
+        Ok(())
+    }
+
+    /// Load every reference in [`REFERENCE_DIR`], skipping unreadable files
+    pub fn load_all(colors: &[Color32]) -> Vec<Self> {
+        let Ok(entries) = std::fs::read_dir(REFERENCE_DIR) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("parquet"))
+            })
+            .collect();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .filter_map(|path| match replay::read_capture(&path) {
+                Ok(capture) => Some((path, capture)),
+                Err(e) => {
+                    tracing::warn!("Skipping reference {}: {:#}", path.display(), e);
+                    None
+                }
+            })
+            .enumerate()
+            .map(|(idx, (path, capture))| {
+                // References frozen before colors were stored get one from the palette
+                let color = capture
+                    .metadata
+                    .iter()
+                    .find(|(key, _)| key == COLOR_KEY)
+                    .and_then(|(_, value)| parse_color(value))
+                    .unwrap_or_else(|| {
+                        let color = colors[idx % colors.len()];
+                        [color.r(), color.g(), color.b()]
+                    });
+                Self {
+                    name: path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    color,
+                    saved_color: color,
+                    path,
+                    metadata: capture.metadata,
+                    time: capture.x_values,
+                    data_points: capture.data_points,
+                }
+            })
+            .collect()
+    }
+
+    /// Remove the reference from disk
+    pub fn delete(&self) -> Result<()> {
+        std::fs::remove_file(&self.path)
+            .with_context(|| format!("Failed to delete {}", self.path.display()))
+    }
+
+    pub fn color32(&self) -> Color32 {
+        let [r, g, b] = self.color;
+        Color32::from_rgb(r, g, b)
+    }
+
+    pub fn analog_points(&self, overlay: &ReferenceOverlay) -> Vec<[f64; 2]> {
+        self.time
+            .iter()
+            .zip(&self.data_points)
+            .map(|(t, p)| {
+                [
+                    t + overlay.time_offset,
+                    p.analog_channel + overlay.voltage_offset,
+                ]
+            })
+            .collect()
+    }
+
+    pub fn digital_points(&self, overlay: &ReferenceOverlay, channel: usize) -> Vec<[f64; 2]> {
+        self.time
+            .iter()
+            .zip(&self.data_points)
+            .map(|(t, p)| {
+                let level = if p.digital_channels[channel] {
+                    1.0
+                } else {
+                    0.0
+                };
+                [t + overlay.time_offset, level]
+            })
+            .collect()
+    }
+
+    /// Live minus reference on the live time base; NaN where the reference has no samples
+    pub fn difference(&self, overlay: &ReferenceOverlay, time: &[f64], volts: &[f64]) -> Vec<f64> {
+        let reference_time: Vec<f64> = self.time.iter().map(|t| t + overlay.time_offset).collect();
+        let reference_volts: Vec<f64> = self
+            .data_points
+            .iter()
+            .map(|p| p.analog_channel + overlay.voltage_offset)
+            .collect();
+        time.iter()
+            .zip(volts)
+            .map(|(&t, &v)| v - resample(&reference_time, &reference_volts, t, false))
+            .collect()
+    }
+}
+
+/// `#rrggbb` as stored in the metadata
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Largest absolute difference and the time it occurs at
+pub fn max_deviation(time: &[f64], difference: &[f64]) -> Option<(f64, f64)> {
+    time.iter()
+        .zip(difference)
+        .filter(|(_, d)| d.is_finite())
+        .map(|(&t, &d)| (d.abs(), t))
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+/// What the reference panel asks the plot area to do
+pub enum ReferenceAction {
+    Freeze(String),
+    Delete(String),
+}
+
+/// Reference selection and placement of one device
+#[derive(Default)]
+pub struct ReferenceOverlay {
+    pub enabled: bool,
+    pub selected: Option<String>,
+    pub time_offset: f64,
+    pub voltage_offset: f64,
+    /// Show live minus reference and report the maximum deviation
+    pub diff: bool,
+    new_name: String,
+    /// Outcome of the last freeze or delete, shown in the panel
+    status: Option<Result<String, String>>,
+}
+
+impl ReferenceOverlay {
+    pub fn set_status(&mut self, status: Result<String, String>) {
+        self.status = Some(status);
+    }
+
+    /// The selected reference, if it still exists
+    pub fn reference<'a>(&self, references: &'a [Reference]) -> Option<&'a Reference> {
+        if !self.enabled {
+            return None;
+        }
+        let selected = self.selected.as_ref()?;
+        references.iter().find(|r| &r.name == selected)
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        id_salt: &str,
+        references: &mut [Reference],
+        analog: Option<(&[f64], &[f64])>,
+        can_freeze: bool,
+    ) -> Option<ReferenceAction> {
+        if !self.enabled {
+            return None;
+        }
+        let mut action = None;
+
+        egui::CollapsingHeader::new(RichText::new("Reference").strong())
+            .id_salt(format!("reference_{}", id_salt))
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    egui::ComboBox::from_id_salt(format!("reference_select_{}", id_salt))
+                        .selected_text(self.selected.as_deref().unwrap_or("None"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.selected, None, "None");
+                            for reference in references.iter() {
+                                ui.selectable_value(
+                                    &mut self.selected,
+                                    Some(reference.name.clone()),
+                                    &reference.name,
+                                );
+                            }
+                        });
+
+                    let selected = self.selected.clone();
+                    if let Some(reference) = references
+                        .iter_mut()
+                        .find(|r| Some(&r.name) == selected.as_ref())
+                    {
+                        ui.color_edit_button_srgb(&mut reference.color);
+                        // Written once the picker closes, not on every step of a drag
+                        let picking = egui::Popup::is_any_open(ui.ctx());
+                        if reference.color != reference.saved_color && !picking {
+                            if let Err(e) = reference.save() {
+                                tracing::error!("Failed to save reference color: {:#}", e);
+                                self.status = Some(Err(format!("{:#}", e)));
+                            }
+                        }
+                        if ui.button("🗑").on_hover_text("Delete reference").clicked() {
+                            action = Some(ReferenceAction::Delete(reference.name.clone()));
+                        }
+                    }
+
+                    ui.separator();
+                    ui.label("Δt:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.time_offset)
+                            .speed(1e-5)
+                            .custom_formatter(|n, _| pretty_print_number(n, Some("s"), 4)),
+                    );
+                    ui.label("ΔV:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.voltage_offset)
+                            .speed(0.01)
+                            .custom_formatter(|n, _| pretty_print_number(n, Some("V"), 4)),
+                    );
+                    ui.checkbox(&mut self.diff, "Diff");
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Freeze as:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.new_name)
+                            .hint_text("known-good")
+                            .desired_width(120.0),
+                    );
+                    if ui
+                        .add_enabled(
+                            can_freeze && !self.new_name.trim().is_empty(),
+                            egui::Button::new("📌 Freeze"),
+                        )
+                        .on_hover_text(format!("Save the latest capture to {}/", REFERENCE_DIR))
+                        .on_disabled_hover_text(if can_freeze {
+                            "Name the reference first"
+                        } else {
+                            "Only triggered captures can be frozen"
+                        })
+                        .clicked()
+                    {
+                        // Named like its file, so it reads the same after a restart
+                        action = Some(ReferenceAction::Freeze(capture_export::safe_file_name(
+                            self.new_name.trim(),
+                        )));
+                    }
+                    match &self.status {
+                        Some(Ok(message)) => {
+                            ui.colored_label(Color32::LIGHT_GREEN, message);
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(Color32::LIGHT_RED, e);
+                        }
+                        None => {}
+                    }
+                });
+
+                if !self.diff {
+                    return;
+                }
+                let (Some(reference), Some((time, volts))) = (self.reference(references), analog)
+                else {
+                    return;
+                };
+                let difference = reference.difference(self, time, volts);
+                let text = match max_deviation(time, &difference) {
+                    Some((deviation, at)) => format!(
+                        "max |Δ| {} at {}",
+                        pretty_print_number(deviation, Some("V"), 4),
+                        pretty_print_number(at, Some("s"), 4)
+                    ),
+                    None => "No overlap with the reference".to_string(),
+                };
+                ui.label(
+                    RichText::new(text)
+                        .size(10.0)
+                        .color(reference.color32())
+                        .family(egui::FontFamily::Monospace),
+                );
+            });
+
+        action
+    }
+}
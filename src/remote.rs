@@ -0,0 +1,657 @@
+use anyhow::{Context, Result};
+use fleascope_rs::{AnalogTriggerBehavior, ProbeType, Waveform};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+use crate::capture_export::describe_trigger;
+use crate::cli;
+use crate::device::{
+    DeviceData, DeviceManager, TriggerSource, MAX_TIME_FRAME, MAX_TRIGGER_OFFSET,
+    MAX_TRIGGER_POSITION, MIN_TIME_FRAME,
+};
+use crate::measurements::{self, Measurement};
+use crate::worker_interface::{CaptureModeFlat, FleaScopeDevice};
+
+/// SCPI's "not a number", returned for measurements the signal does not allow
+const NOT_A_NUMBER: &str = "9.91E+37";
+
+/// Errors kept per connection until read with `:SYST:ERR?`
+const ERROR_QUEUE_LENGTH: usize = 32;
+
+/// Longest command line, commands are short and a client that never ends its line is refused
+const MAX_LINE: usize = 4096;
+
+// SCPI error codes reported by `:SYST:ERR?`
+const UNDEFINED_HEADER: i32 = -113;
+const HEADER_SUFFIX_OUT_OF_RANGE: i32 = -114;
+const EXECUTION_ERROR: i32 = -200;
+const DATA_OUT_OF_RANGE: i32 = -222;
+const ILLEGAL_PARAMETER_VALUE: i32 = -224;
+const QUEUE_OVERFLOW: i32 = -350;
+const INPUT_BUFFER_OVERRUN: i32 = -363;
+
+/// A failed command as it is reported by `:SYST:ERR?`
+#[derive(Debug, Clone, PartialEq)]
+struct ScpiError {
+    code: i32,
+    message: String,
+}
+
+impl ScpiError {
+    fn new(code: i32, message: impl Display) -> Self {
+        Self {
+            code,
+            // Quotes end the string of the error reply
+            message: message.to_string().replace('"', "'"),
+        }
+    }
+
+    fn undefined_header(header: impl Display) -> Self {
+        Self::new(UNDEFINED_HEADER, format!("Undefined header '{}'", header))
+    }
+
+    /// An argument that did not parse or names no known value
+    fn illegal_parameter(error: anyhow::Error) -> Self {
+        Self::new(ILLEGAL_PARAMETER_VALUE, format!("{:#}", error))
+    }
+
+    fn execution(error: anyhow::Error) -> Self {
+        Self::new(EXECUTION_ERROR, format!("{:#}", error))
+    }
+}
+
+/// Errors of one connection, oldest first
+///
+/// Like any SCPI instrument it keeps the oldest errors when it fills up, the newest entry is
+/// then replaced by a queue overflow error.
+#[derive(Default)]
+struct ErrorQueue {
+    errors: VecDeque<ScpiError>,
+}
+
+impl ErrorQueue {
+    fn push(&mut self, error: ScpiError) {
+        if self.errors.len() < ERROR_QUEUE_LENGTH {
+            self.errors.push_back(error);
+        } else if let Some(last) = self.errors.back_mut() {
+            *last = ScpiError::new(QUEUE_OVERFLOW, "Queue overflow");
+        }
+    }
+
+    /// Reply to `:SYST:ERR?`
+    fn pop(&mut self) -> String {
+        match self.errors.pop_front() {
+            Some(error) => format!("{},\"{}\"", error.code, error.message),
+            None => "0,\"No error\"".to_string(),
+        }
+    }
+}
+
+/// `5025` listens on localhost, `0.0.0.0:5025` on every interface
+pub fn parse_address(value: &str) -> Result<SocketAddr> {
+    if let Ok(port) = value.parse::<u16>() {
+        return Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port));
+    }
+    value
+        .parse()
+        .with_context(|| format!("Invalid address '{}', expected PORT or ADDR:PORT", value))
+}
+
+/// Bind the remote control server, the GUI shares `device_manager` with it
+pub fn start(addr: SocketAddr, device_manager: Arc<Mutex<DeviceManager>>) -> Result<()> {
+    let listener = std::net::TcpListener::bind(addr)
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    listener.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(listener)?;
+    tracing::info!("Remote control listening on {}", addr);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    tracing::info!("Remote control client {} connected", peer);
+                    tokio::spawn(handle_client(stream, peer, device_manager.clone()));
+                }
+                Err(e) => tracing::warn!("Failed to accept remote control client: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    device_manager: Arc<Mutex<DeviceManager>>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut errors = ErrorQueue::default();
+
+    while let Ok(Some(line)) = read_line(&mut reader).await {
+        let Line::Command(line) = line else {
+            tracing::warn!("Remote command line from {} is too long", peer);
+            errors.push(ScpiError::new(
+                INPUT_BUFFER_OVERRUN,
+                format!("Line longer than {} bytes", MAX_LINE),
+            ));
+            continue;
+        };
+        for command in line.split(';').map(str::trim).filter(|c| !c.is_empty()) {
+            let reply = if is_header(command, "SYST:ERR?") {
+                Ok(Some(errors.pop()))
+            } else {
+                execute(command, &device_manager).await
+            };
+
+            match reply {
+                Ok(Some(response)) => {
+                    if let Err(e) = writer.write_all(format!("{}\n", response).as_bytes()).await {
+                        tracing::info!("Remote control client {} is gone: {}", peer, e);
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(
+                        "Remote command '{}' from {} failed: {}",
+                        command,
+                        peer,
+                        e.message
+                    );
+                    errors.push(e);
+                }
+            }
+        }
+    }
+    tracing::info!("Remote control client {} disconnected", peer);
+}
+
+enum Line {
+    Command(String),
+    /// Longer than [`MAX_LINE`], the rest of it was skipped without keeping it
+    TooLong,
+}
+
+/// One command line without its line ending, `None` once the client is done
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Line>> {
+    let mut line = Vec::new();
+    let read = (&mut *reader)
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') && line.len() > MAX_LINE {
+        loop {
+            line.clear();
+            let read = (&mut *reader)
+                .take(MAX_LINE as u64)
+                .read_until(b'\n', &mut line)
+                .await?;
+            if read == 0 || line.last() == Some(&b'\n') {
+                return Ok(Some(Line::TooLong));
+            }
+        }
+    }
+    let line = String::from_utf8(line).context("Command is not UTF-8")?;
+    Ok(Some(Line::Command(line.trim_end().to_string())))
+}
+
+fn is_header(command: &str, header: &str) -> bool {
+    command.trim_start_matches(':').eq_ignore_ascii_case(header)
+}
+
+/// What a capture query asks for, answered after the device manager is unlocked again
+enum CaptureQuery {
+    Volts,
+    Time,
+    Digital,
+    Measure(Measurement),
+}
+
+enum Reply {
+    Text(String),
+    Capture(Arc<DeviceData>, CaptureQuery),
+}
+
+/// Run one command; queries answer with a line, settings with nothing
+async fn execute(
+    command: &str,
+    device_manager: &Arc<Mutex<DeviceManager>>,
+) -> Result<Option<String>, ScpiError> {
+    let (header, argument) = match command.split_once(char::is_whitespace) {
+        Some((header, argument)) => (header, argument.trim()),
+        None => (command, ""),
+    };
+    let query = header.ends_with('?');
+    let header = header
+        .trim_start_matches(':')
+        .trim_end_matches('?')
+        .to_ascii_uppercase();
+
+    let reply = {
+        // Only held briefly, the GUI skips a frame rather than waiting for it
+        let mut manager = device_manager.lock().await;
+        match header.split_once(':') {
+            _ if header == "*IDN" && query => Some(Reply::Text(format!(
+                "FleaScope,fleascope-monitor,{},{}",
+                manager.get_devices().len(),
+                env!("CARGO_PKG_VERSION")
+            ))),
+            Some(("DEV", "COUNT")) if query => {
+                Some(Reply::Text(manager.get_devices().len().to_string()))
+            }
+            Some((node, path)) if node.starts_with("DEV") => {
+                let index: usize = node[3..]
+                    .parse()
+                    .map_err(|_| ScpiError::undefined_header(node))?;
+                let device = manager.get_devices_mut().get_mut(index).ok_or_else(|| {
+                    ScpiError::new(HEADER_SUFFIX_OUT_OF_RANGE, format!("No device {}", index))
+                })?;
+                device_command(device, path, query, argument)?
+            }
+            _ => return Err(ScpiError::undefined_header(header)),
+        }
+    };
+
+    Ok(reply.map(|reply| match reply {
+        Reply::Text(text) => text,
+        Reply::Capture(data, query) => capture_reply(&data, query),
+    }))
+}
+
+/// `value` if it lies in `min..=max`, which the device would otherwise silently clamp it to
+fn in_range(value: f64, min: f64, max: f64, what: &str) -> Result<f64, ScpiError> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(ScpiError::new(
+            DATA_OUT_OF_RANGE,
+            format!("{} {} is outside {} to {}", what, value, min, max),
+        ))
+    }
+}
+
+fn device_command(
+    device: &mut FleaScopeDevice,
+    path: &str,
+    query: bool,
+    argument: &str,
+) -> Result<Option<Reply>, ScpiError> {
+    let text = |text: String| Ok(Some(Reply::Text(text)));
+    let illegal = ScpiError::illegal_parameter;
+    let trigger = device.get_triggered_config().trigger_config;
+
+    match (path, query) {
+        ("NAME", true) => text(device.name.clone()),
+        ("STATE", true) => text(device.acquisition_state().label().to_string()),
+        ("RUN", false) => {
+            device.resume().map_err(ScpiError::execution)?;
+            Ok(None)
+        }
+        ("STOP", false) => {
+            device.pause().map_err(ScpiError::execution)?;
+            Ok(None)
+        }
+        ("SINGLE", false) => {
+            device.single().map_err(ScpiError::execution)?;
+            Ok(None)
+        }
+        ("TIMEBASE", true) => text(device.get_triggered_config().time_frame.to_string()),
+        ("TIMEBASE", false) => {
+            let time_frame = cli::parse_seconds(argument).map_err(illegal)?;
+            device.set_time_frame(in_range(
+                time_frame,
+                MIN_TIME_FRAME,
+                MAX_TIME_FRAME,
+                "Timebase",
+            )?);
+            Ok(None)
+        }
+        ("MODE", true) => text(
+            match device.get_capture_mode() {
+                CaptureModeFlat::Triggered => "TRIGGERED",
+                CaptureModeFlat::Continuous => "CONTINUOUS",
+            }
+            .to_string(),
+        ),
+        ("MODE", false) => {
+            let mode = match argument.to_ascii_uppercase().as_str() {
+                "TRIG" | "TRIGGERED" => CaptureModeFlat::Triggered,
+                "CONT" | "CONTINUOUS" => CaptureModeFlat::Continuous,
+                _ => return Err(illegal(anyhow::anyhow!("Unknown mode '{}'", argument))),
+            };
+            device.set_capture_mode(mode);
+            Ok(None)
+        }
+        ("PROBE", true) => text(match device.get_probe_multiplier() {
+            ProbeType::X1 => "X1".to_string(),
+            ProbeType::X10 => "X10".to_string(),
+        }),
+        ("PROBE", false) => {
+            device.set_probe_multiplier(cli::parse_probe(argument).map_err(illegal)?);
+            Ok(None)
+        }
+        ("TRIG", true) => text(describe_trigger(&trigger)),
+        ("TRIG", false) => {
+            let mut new_trigger = cli::parse_trigger(argument).map_err(illegal)?;
+            new_trigger.position = trigger.position;
+            new_trigger.offset = trigger.offset;
+            device.set_trigger_config(new_trigger);
+            Ok(None)
+        }
+        ("TRIG:SOURCE", true) => text(
+            match trigger.source {
+                TriggerSource::Analog => "ANALOG",
+                TriggerSource::Digital => "DIGITAL",
+            }
+            .to_string(),
+        ),
+        ("TRIG:SOURCE", false) => {
+            let mut trigger = trigger;
+            trigger.source = match argument.to_ascii_uppercase().as_str() {
+                "ANALOG" => TriggerSource::Analog,
+                "DIGITAL" => TriggerSource::Digital,
+                _ => {
+                    return Err(illegal(anyhow::anyhow!(
+                        "Unknown trigger source '{}'",
+                        argument
+                    )))
+                }
+            };
+            device.set_trigger_config(trigger);
+            Ok(None)
+        }
+        ("TRIG:LEVEL", true) => text(trigger.analog.volts.to_string()),
+        ("TRIG:LEVEL", false) => {
+            let mut trigger = trigger;
+            trigger.analog.volts = cli::parse_volts(argument).map_err(illegal)?;
+            device.set_trigger_config(trigger);
+            Ok(None)
+        }
+        ("TRIG:SLOPE", true) => text(
+            match trigger.analog.behavior {
+                AnalogTriggerBehavior::Auto => "AUTO",
+                AnalogTriggerBehavior::Level => "LEVEL",
+                AnalogTriggerBehavior::Rising => "RISING",
+                AnalogTriggerBehavior::Falling => "FALLING",
+            }
+            .to_string(),
+        ),
+        ("TRIG:SLOPE", false) => {
+            let mut trigger = trigger;
+            trigger.analog.behavior = cli::parse_analog_behavior(argument).map_err(illegal)?;
+            device.set_trigger_config(trigger);
+            Ok(None)
+        }
+        ("TRIG:POSITION", true) => text((trigger.position * 100.0).to_string()),
+        ("TRIG:POSITION", false) => {
+            let mut trigger = trigger;
+            let percent = argument
+                .trim_end_matches('%')
+                .parse::<f64>()
+                .with_context(|| format!("Invalid trigger position '{}'", argument))
+                .map_err(illegal)?;
+            trigger.position = in_range(
+                percent,
+                0.0,
+                MAX_TRIGGER_POSITION * 100.0,
+                "Trigger position",
+            )? / 100.0;
+            device.set_trigger_config(trigger);
+            Ok(None)
+        }
+        ("TRIG:OFFSET", true) => text(trigger.offset.to_string()),
+        ("TRIG:OFFSET", false) => {
+            let mut trigger = trigger;
+            // Plain seconds may be zero, unlike time frames
+            let offset = match argument.parse::<f64>() {
+                Ok(seconds) => seconds,
+                Err(_) => cli::parse_seconds(argument).map_err(illegal)?,
+            };
+            trigger.offset = in_range(offset, 0.0, MAX_TRIGGER_OFFSET, "Trigger offset")?;
+            device.set_trigger_config(trigger);
+            Ok(None)
+        }
+        ("TRIG:PATTERN", true) => text(cli::format_pattern(&trigger.digital.bit_states)),
+        ("TRIG:PATTERN", false) => {
+            let mut trigger = trigger;
+            trigger.digital.bit_states = cli::parse_pattern(argument).map_err(illegal)?;
+            device.set_trigger_config(trigger);
+            Ok(None)
+        }
+        ("WAVEFORM", true) => {
+            let waveform = device.get_waveform_config();
+            text(if waveform.enabled {
+                format!(
+                    "{},{}",
+                    waveform.waveform_type.as_str().to_ascii_uppercase(),
+                    waveform.frequency_hz
+                )
+            } else {
+                "OFF".to_string()
+            })
+        }
+        ("WAVEFORM", false) => {
+            let (shape, frequency) = match argument.split_once(',') {
+                Some((shape, frequency)) => (
+                    shape.trim(),
+                    frequency
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("Invalid frequency '{}'", frequency))
+                        .map_err(illegal)?,
+                ),
+                None => (argument, device.get_waveform_config().frequency_hz),
+            };
+            let waveform = match shape.to_ascii_uppercase().as_str() {
+                "SINE" => Waveform::Sine,
+                "SQUARE" => Waveform::Square,
+                "TRIANGLE" => Waveform::Triangle,
+                "EKG" => Waveform::Ekg,
+                _ => return Err(illegal(anyhow::anyhow!("Unknown waveform '{}'", shape))),
+            };
+            device.set_waveform(waveform, frequency);
+            Ok(None)
+        }
+        ("DATA", true) => capture(device, CaptureQuery::Volts),
+        ("DATA:TIME", true) => capture(device, CaptureQuery::Time),
+        ("DATA:DIGITAL", true) => capture(device, CaptureQuery::Digital),
+        (path, true) if path.starts_with("MEAS:") => {
+            let measurement = measurement(&path[5..])
+                .ok_or_else(|| ScpiError::undefined_header(format!("{}?", path)))?;
+            capture(device, CaptureQuery::Measure(measurement))
+        }
+        _ => Err(ScpiError::undefined_header(format!(
+            "{}{}",
+            path,
+            if query { "?" } else { "" }
+        ))),
+    }
+}
+
+fn capture(device: &FleaScopeDevice, query: CaptureQuery) -> Result<Option<Reply>, ScpiError> {
+    Ok(Some(Reply::Capture(device.data.load_full(), query)))
+}
+
+fn measurement(name: &str) -> Option<Measurement> {
+    Some(match name {
+        "MIN" => Measurement::Min,
+        "MAX" => Measurement::Max,
+        "VPP" => Measurement::PeakToPeak,
+        "MEAN" => Measurement::Mean,
+        "RMS" => Measurement::Rms,
+        "ACRMS" => Measurement::AcRms,
+        "FREQ" => Measurement::Frequency,
+        "PERIOD" => Measurement::Period,
+        "DUTY" => Measurement::DutyCycle,
+        "RISE" => Measurement::RiseTime,
+        "FALL" => Measurement::FallTime,
+        "OVERSHOOT" => Measurement::Overshoot,
+        "PWIDTH" => Measurement::PulseWidth,
+        _ => return None,
+    })
+}
+
+/// Comma separated values of the latest triggered capture
+fn capture_reply(data: &DeviceData, query: CaptureQuery) -> String {
+    let join = |values: &mut dyn Iterator<Item = String>| values.collect::<Vec<_>>().join(",");
+    match query {
+        CaptureQuery::Volts => join(
+            &mut data
+                .data_points
+                .iter()
+                .map(|p| p.analog_channel.to_string()),
+        ),
+        CaptureQuery::Time => join(&mut data.x_values.iter().map(f64::to_string)),
+        CaptureQuery::Digital => join(&mut data.data_points.iter().map(|p| {
+            p.digital_channels
+                .iter()
+                .enumerate()
+                .fold(0u16, |bits, (ch, &high)| bits | (high as u16) << ch)
+                .to_string()
+        })),
+        CaptureQuery::Measure(measurement) => {
+            let (time, volts) = data.get_analog_data();
+            measurements::measure(&time, &volts)
+                .get(measurement)
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| NOT_A_NUMBER.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> Arc<Mutex<DeviceManager>> {
+        let mut manager = DeviceManager::default();
+        manager.add_device("sim://square-1k".to_string()).unwrap();
+        Arc::new(Mutex::new(manager))
+    }
+
+    async fn query(manager: &Arc<Mutex<DeviceManager>>, command: &str) -> String {
+        execute(command, manager).await.unwrap().unwrap()
+    }
+
+    async fn error(manager: &Arc<Mutex<DeviceManager>>, command: &str) -> i32 {
+        execute(command, manager).await.unwrap_err().code
+    }
+
+    #[test]
+    fn matches_headers() {
+        assert!(is_header("SYST:ERR?", "SYST:ERR?"));
+        assert!(is_header(":syst:err?", "SYST:ERR?"));
+        assert!(!is_header("SYST:ERR", "SYST:ERR?"));
+        assert!(!is_header("SYST:ERR? 1", "SYST:ERR?"));
+    }
+
+    #[tokio::test]
+    async fn answers_queries() {
+        let manager = manager();
+        assert!(query(&manager, "*IDN?").await.starts_with("FleaScope,"));
+        assert_eq!(query(&manager, ":DEV:COUNT?").await, "1");
+        assert_eq!(query(&manager, "dev0:name?").await, "sim://square-1k");
+        assert_eq!(query(&manager, "DEV0:MODE?").await, "TRIGGERED");
+    }
+
+    #[tokio::test]
+    async fn applies_settings() {
+        let manager = manager();
+        assert_eq!(execute("DEV0:TIMEBASE 20ms", &manager).await, Ok(None));
+        assert_eq!(query(&manager, "DEV0:TIMEBASE?").await, "0.02");
+
+        execute(":DEV0:TRIG digital:start:1X0", &manager)
+            .await
+            .unwrap();
+        assert_eq!(query(&manager, "DEV0:TRIG:SOURCE?").await, "DIGITAL");
+        assert_eq!(query(&manager, "DEV0:TRIG:PATTERN?").await, "1X0XXXXXX");
+        execute("DEV0:TRIG:PATTERN 01", &manager).await.unwrap();
+        assert_eq!(query(&manager, "DEV0:TRIG:PATTERN?").await, "01XXXXXXX");
+
+        execute("DEV0:TRIG:POSITION 25%", &manager).await.unwrap();
+        assert_eq!(query(&manager, "DEV0:TRIG:POSITION?").await, "25");
+        execute("DEV0:WAVEFORM triangle,250", &manager)
+            .await
+            .unwrap();
+        assert_eq!(query(&manager, "DEV0:WAVEFORM?").await, "TRIANGLE,250");
+    }
+
+    #[tokio::test]
+    async fn reports_scpi_error_codes() {
+        let manager = manager();
+        assert_eq!(error(&manager, "FOO?").await, UNDEFINED_HEADER);
+        assert_eq!(error(&manager, "DEV0:NAME").await, UNDEFINED_HEADER);
+        assert_eq!(error(&manager, "DEV0:MEAS:FOO?").await, UNDEFINED_HEADER);
+        assert_eq!(error(&manager, "DEVX:NAME?").await, UNDEFINED_HEADER);
+        assert_eq!(
+            error(&manager, "DEV3:NAME?").await,
+            HEADER_SUFFIX_OUT_OF_RANGE
+        );
+        assert_eq!(
+            error(&manager, "DEV0:MODE SIDEWAYS").await,
+            ILLEGAL_PARAMETER_VALUE
+        );
+        assert_eq!(
+            error(&manager, "DEV0:TIMEBASE fast").await,
+            ILLEGAL_PARAMETER_VALUE
+        );
+        assert_eq!(
+            error(&manager, "DEV0:TRIG:PATTERN 2").await,
+            ILLEGAL_PARAMETER_VALUE
+        );
+        assert_eq!(
+            error(&manager, "DEV0:TIMEBASE 10s").await,
+            DATA_OUT_OF_RANGE
+        );
+        assert_eq!(
+            error(&manager, "DEV0:TRIG:POSITION 95").await,
+            DATA_OUT_OF_RANGE
+        );
+        assert_eq!(
+            error(&manager, "DEV0:TRIG:OFFSET -1").await,
+            DATA_OUT_OF_RANGE
+        );
+        // Rejected values leave the setting alone
+        assert_eq!(query(&manager, "DEV0:TIMEBASE?").await, "0.1");
+    }
+
+    #[test]
+    fn keeps_the_oldest_errors_on_overflow() {
+        let mut queue = ErrorQueue::default();
+        assert_eq!(queue.pop(), "0,\"No error\"");
+        for i in 0..ERROR_QUEUE_LENGTH + 5 {
+            queue.push(ScpiError::undefined_header(format!("\"H{}\"", i)));
+        }
+
+        assert_eq!(queue.pop(), "-113,\"Undefined header ''H0''\"");
+        for _ in 1..ERROR_QUEUE_LENGTH - 1 {
+            assert!(queue.pop().starts_with("-113,"));
+        }
+        assert_eq!(queue.pop(), "-350,\"Queue overflow\"");
+        assert_eq!(queue.pop(), "0,\"No error\"");
+    }
+
+    #[tokio::test]
+    async fn skips_overlong_lines() {
+        let input = format!("A?\n{}\nB?", "X".repeat(3 * MAX_LINE));
+        let mut reader = input.as_bytes();
+
+        let mut lines = Vec::new();
+        while let Some(line) = read_line(&mut reader).await.unwrap() {
+            lines.push(match line {
+                Line::Command(command) => command,
+                Line::TooLong => "too long".to_string(),
+            });
+        }
+        assert_eq!(lines, ["A?", "too long", "B?"]);
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{Context, Result};
+use polars::prelude::{
+    CsvParseOptions, CsvReadOptions, DataFrame, DataType, ParquetReader, SerReader,
+};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::capture_export::{
+    ANALOG_COLUMN, CAPTURE_DIR, CSV_COMMENT_PREFIX, DIGITAL_COLUMNS, TIME_COLUMN,
+};
+use crate::cursors::Cursors;
+use crate::decoder::DecoderPanel;
+use crate::device::{AnalogSamples, DataPoint};
+use crate::measurements::MeasurementPanel;
+use crate::recorder::{RECORDING_DIR, SAMPLE_COLUMN};
+use crate::spectrum::SpectrumView;
+
+/// Playback speeds offered for recordings
+pub const PLAYBACK_SPEEDS: [f64; 7] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0];
+
+/// A capture or recording loaded from disk, shown next to the live devices
+pub struct Replay {
+    pub name: String,
+    pub path: PathBuf,
+    pub metadata: Vec<(String, String)>,
+    pub has_digital: bool,
+    /// Only recordings are played back, captures are shown as a whole
+    pub playback: Option<Playback>,
+    pub cursors: Cursors,
+    pub measurements: MeasurementPanel,
+    pub spectrum: SpectrumView,
+    pub decoder: DecoderPanel,
+    x_values: Vec<f64>,
+    data_points: Vec<DataPoint>,
+    /// Time and volts of the window shown last, with the sample range they cover
+    analog: Option<(Range<usize>, AnalogSamples)>,
+}
+
+impl Replay {
+    /// Load a capture or recording exported by this application (CSV or Parquet)
+    pub fn open(path: &Path) -> Result<Self> {
+        profiling::scope!("Replay::open");
+
+        let capture = read_capture(path)?;
+        let playback = capture.is_recording.then(|| {
+            Playback::new(
+                capture.x_values[0],
+                *capture
+                    .x_values
+                    .last()
+                    .expect("read_capture rejects empty files"),
+            )
+        });
+
+        Ok(Self {
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+            path: path.to_path_buf(),
+            metadata: capture.metadata,
+            has_digital: capture.has_digital,
+            playback,
+            cursors: Cursors::default(),
+            measurements: MeasurementPanel::default(),
+            spectrum: SpectrumView::default(),
+            decoder: DecoderPanel::default(),
+            x_values: capture.x_values,
+            data_points: capture.data_points,
+            analog: None,
+        })
+    }
+
+    /// Samples in the playback window, or all of them for a capture
+    pub fn window(&self) -> (&[f64], &[DataPoint]) {
+        let range = self.window_range();
+        (&self.x_values[range.clone()], &self.data_points[range])
+    }
+
+    fn window_range(&self) -> Range<usize> {
+        let Some(playback) = &self.playback else {
+            return 0..self.x_values.len();
+        };
+
+        let window_start = playback.position - playback.window;
+        let lo = self.x_values.partition_point(|&t| t < window_start);
+        let hi = self.x_values.partition_point(|&t| t <= playback.position);
+        lo..hi
+    }
+
+    /// Time and volts of `window`, the same `Arc` as long as the window does not move
+    pub fn analog_window(&mut self) -> AnalogSamples {
+        let range = self.window_range();
+        if let Some((cached, samples)) = &self.analog {
+            if *cached == range {
+                return samples.clone();
+            }
+        }
+        let samples = Arc::new((
+            self.x_values[range.clone()].to_vec(),
+            self.data_points[range.clone()]
+                .iter()
+                .map(|p| p.analog_channel)
+                .collect(),
+        ));
+        self.analog = Some((range, samples.clone()));
+        samples
+    }
+
+    /// Samples to display, reduced to about `plot_width` points for long recording windows
+    pub fn view(&self, plot_width: u32) -> (Vec<f64>, Vec<DataPoint>) {
+        profiling::scope!("Replay::view");
+
+        let (x_values, data_points) = self.window();
+
+        let bins = plot_width.max(1) as usize;
+        if x_values.len() <= 2 * bins {
+            return (x_values.to_vec(), data_points.to_vec());
+        }
+
+        // Two points per bin like the live continuous view: the analog min/max envelope, and
+        // digital channels that toggled within the bin drawn as an edge so short pulses survive
+        let bin_size = x_values.len().div_ceil(bins);
+        let mut level = data_points[0].digital_channels;
+        let mut view = (Vec::with_capacity(2 * bins), Vec::with_capacity(2 * bins));
+        for (times, points) in x_values.chunks(bin_size).zip(data_points.chunks(bin_size)) {
+            let (min, max) = points.iter().fold((f64::MAX, f64::MIN), |(min, max), p| {
+                (min.min(p.analog_channel), max.max(p.analog_channel))
+            });
+            let (mut high, mut low) = ([false; 9], [false; 9]);
+            for point in points {
+                for (ch, &state) in point.digital_channels.iter().enumerate() {
+                    high[ch] |= state;
+                    low[ch] |= !state;
+                }
+            }
+            let mut before = level;
+            for ch in 0..level.len() {
+                let toggled = high[ch] && low[ch];
+                before[ch] = if toggled { level[ch] } else { high[ch] };
+                level[ch] = if toggled { !level[ch] } else { high[ch] };
+            }
+            for (analog_channel, digital_channels) in [(min, before), (max, level)] {
+                view.0.push(times[0]);
+                view.1.push(DataPoint {
+                    analog_channel,
+                    digital_channels,
+                });
+            }
+        }
+        view
+    }
+}
+
+pub struct Playback {
+    /// Time of the newest displayed sample
+    pub position: f64,
+    pub playing: bool,
+    pub speed: f64,
+    /// Width of the displayed window in seconds
+    pub window: f64,
+    start: f64,
+    end: f64,
+    last_tick: Option<Instant>,
+}
+
+impl Playback {
+    fn new(start: f64, end: f64) -> Self {
+        let window = 1.0_f64.min(end - start).max(0.001);
+        Self {
+            position: start + window,
+            playing: false,
+            speed: 1.0,
+            window,
+            start,
+            end,
+            last_tick: None,
+        }
+    }
+
+    pub fn start(&self) -> f64 {
+        self.start
+    }
+
+    pub fn end(&self) -> f64 {
+        self.end
+    }
+
+    pub fn play(&mut self) {
+        if self.position >= self.end {
+            self.position = self.start + self.window;
+        }
+        self.playing = true;
+        self.last_tick = None;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn seek(&mut self, position: f64) {
+        self.position = position.clamp(self.start, self.end);
+    }
+
+    /// Move the position forward by the wall-clock time since the last call
+    pub fn advance(&mut self) {
+        let now = Instant::now();
+        if self.playing {
+            if let Some(last) = self.last_tick {
+                self.position += (now - last).as_secs_f64() * self.speed;
+            }
+            if self.position >= self.end {
+                self.position = self.end;
+                self.playing = false;
+            }
+        }
+        self.last_tick = Some(now);
+    }
+}
+
+/// Samples and metadata of a capture file
+pub struct CaptureFile {
+    pub x_values: Vec<f64>,
+    pub data_points: Vec<DataPoint>,
+    pub metadata: Vec<(String, String)>,
+    pub has_digital: bool,
+    pub is_recording: bool,
+}
+
+/// Read a capture or recording exported by this application (CSV or Parquet)
+pub fn read_capture(path: &Path) -> Result<CaptureFile> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let (df, metadata) = match extension.as_deref() {
+        Some("csv") => read_csv(path)?,
+        Some("parquet") => read_parquet(path)?,
+        _ => anyhow::bail!("Unsupported file type: {}", path.display()),
+    };
+
+    let x_values = f64_column(&df, TIME_COLUMN)?;
+    let analog = f64_column(&df, ANALOG_COLUMN)?;
+    let mut digital = Vec::new();
+    for name in DIGITAL_COLUMNS {
+        digital.push(match df.column(name) {
+            Ok(column) => Some(
+                column
+                    .cast(&DataType::Boolean)?
+                    .bool()?
+                    .into_iter()
+                    .map(|v| v.unwrap_or(false))
+                    .collect::<Vec<_>>(),
+            ),
+            Err(_) => None,
+        });
+    }
+    if x_values.is_empty() {
+        anyhow::bail!("{} contains no samples", path.display());
+    }
+
+    let data_points = analog
+        .iter()
+        .enumerate()
+        .map(|(i, &analog_channel)| DataPoint {
+            analog_channel,
+            digital_channels: std::array::from_fn(|ch| {
+                digital[ch].as_ref().is_some_and(|values| values[i])
+            }),
+        })
+        .collect();
+
+    let is_recording =
+        df.column(SAMPLE_COLUMN).is_ok() || metadata.iter().any(|(key, _)| key == "sample_rate_hz");
+    Ok(CaptureFile {
+        x_values,
+        data_points,
+        has_digital: digital.iter().any(Option::is_some),
+        metadata,
+        is_recording,
+    })
+}
+
+fn f64_column(df: &DataFrame, name: &str) -> Result<Vec<f64>> {
+    Ok(df
+        .column(name)
+        .with_context(|| format!("Missing column '{}'", name))?
+        .cast(&DataType::Float64)?
+        .f64()?
+        .into_iter()
+        .map(|v| v.unwrap_or(f64::NAN))
+        .collect())
+}
+
+/// Read a CSV file, taking the `# key: value` lines at the top as metadata
+fn read_csv(path: &Path) -> Result<(DataFrame, Vec<(String, String)>)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut metadata = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let Some(entry) = line.strip_prefix(CSV_COMMENT_PREFIX) else {
+            break;
+        };
+        if let Some((key, value)) = entry.split_once(": ") {
+            metadata.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    let df = CsvReadOptions::default()
+        .with_has_header(true)
+        .with_parse_options(
+            CsvParseOptions::default().with_comment_prefix(Some(CSV_COMMENT_PREFIX.trim_end())),
+        )
+        .try_into_reader_with_file_path(Some(path.to_path_buf()))?
+        .finish()
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok((df, metadata))
+}
+
+/// Read a Parquet file together with its key-value metadata
+fn read_parquet(path: &Path) -> Result<(DataFrame, Vec<(String, String)>)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = ParquetReader::new(file);
+    let metadata = reader
+        .get_metadata()?
+        .key_value_metadata
+        .iter()
+        .flatten()
+        // Skip the serialized Arrow schema polars stores next to our entries
+        .filter(|kv| !kv.key.starts_with("ARROW:"))
+        .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+        .collect();
+    let df = reader
+        .finish()
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok((df, metadata))
+}
+
+/// "File → Open capture" window listing exported captures and recordings
+#[derive(Default)]
+pub struct OpenCaptureDialog {
+    open: bool,
+    path: String,
+    files: Vec<PathBuf>,
+}
+
+impl OpenCaptureDialog {
+    pub fn show(&mut self) {
+        self.open = true;
+        self.files = [CAPTURE_DIR, RECORDING_DIR]
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension().is_some_and(|e| {
+                    e.eq_ignore_ascii_case("csv") || e.eq_ignore_ascii_case("parquet")
+                })
+            })
+            .collect();
+        self.files.sort();
+    }
+
+    /// Returns the path the user chose, if any
+    pub fn ui(&mut self, ctx: &egui::Context) -> Option<PathBuf> {
+        let mut chosen = None;
+        let mut open = self.open;
+        egui::Window::new("Open capture")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Path:");
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.path)
+                            .hint_text("captures/device_20250101-120000.parquet")
+                            .desired_width(280.0),
+                    );
+                    let entered =
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if (ui.button("Open").clicked() || entered) && !self.path.is_empty() {
+                        chosen = Some(PathBuf::from(&self.path));
+                    }
+                });
+                ui.separator();
+                if self.files.is_empty() {
+                    ui.label(format!(
+                        "No files in {}/ or {}/",
+                        CAPTURE_DIR, RECORDING_DIR
+                    ));
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for file in &self.files {
+                            if ui
+                                .selectable_label(false, file.display().to_string())
+                                .clicked()
+                            {
+                                chosen = Some(file.clone());
+                            }
+                        }
+                    });
+            });
+        self.open = open && chosen.is_none();
+        chosen
+    }
+}
@@ -0,0 +1,362 @@
+use fleascope_rs::{
+    AnalogTrigger, AnalogTriggerBehavior, BitState, DigitalTrigger, DigitalTriggerBehavior,
+    ProbeType, Waveform,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::combined_view::CombinedView;
+use crate::device::{
+    CaptureConfig, CaptureMode, TriggerConfig, TriggerSource, MAX_TIME_FRAME, MAX_TRIGGER_OFFSET,
+    MAX_TRIGGER_POSITION, MIN_TIME_FRAME,
+};
+use crate::math_channel::MathChannel;
+use crate::measurements::{Measurement, MeasurementPanel};
+use crate::worker_interface::{CaptureModeFlat, FleaScopeDevice};
+
+/// Storage key of the session in eframe's persistence
+pub const SESSION_KEY: &str = "session";
+
+/// Everything needed to bring the bench back after a restart
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub devices: Vec<DeviceSession>,
+    pub plot: PlotSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlotSettings {
+    pub plot_height: f32,
+    pub show_grid: bool,
+    pub math_channels: Vec<MathChannel>,
+    pub combined: CombinedView,
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        Self {
+            plot_height: 200.0,
+            show_grid: true,
+            math_channels: Vec::new(),
+            combined: CombinedView::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceSession {
+    pub hostname: String,
+    pub probe: ProbeSetting,
+    pub capture_mode: CaptureModeFlat,
+    pub time_frame: f64,
+    pub buffer_time: f64,
+    pub trigger: TriggerSettings,
+    pub waveform: WaveformSettings,
+    pub enabled_channels: [bool; 10],
+    pub wrap: bool,
+    #[serde(default = "default_envelope")]
+    pub envelope: bool,
+    #[serde(default = "default_measurements")]
+    pub measurements: Vec<Measurement>,
+}
+
+fn default_envelope() -> bool {
+    true
+}
+
+fn default_measurements() -> Vec<Measurement> {
+    MeasurementPanel::default().selected
+}
+
+impl DeviceSession {
+    pub fn capture(device: &FleaScopeDevice) -> Self {
+        let triggered = device.get_triggered_config();
+        let waveform = device.get_waveform_config();
+        Self {
+            hostname: device.name.clone(),
+            probe: device.get_probe_multiplier().into(),
+            capture_mode: device.get_capture_mode(),
+            time_frame: triggered.time_frame,
+            buffer_time: device.get_continuous_config().buffer_time,
+            trigger: (&triggered.trigger_config).into(),
+            waveform: WaveformSettings {
+                enabled: waveform.enabled,
+                waveform_type: waveform.waveform_type.into(),
+                frequency_hz: waveform.frequency_hz,
+            },
+            enabled_channels: device.enabled_channels,
+            wrap: device.wrap,
+            envelope: device.envelope,
+            measurements: device.measurements.selected.clone(),
+        }
+    }
+
+    /// Capture settings to start the device's worker with, limited like the setters do
+    pub fn capture_config(&self) -> CaptureConfig {
+        let mut trigger_config = TriggerConfig::from(&self.trigger);
+        trigger_config.position = trigger_config.position.clamp(0.0, MAX_TRIGGER_POSITION);
+        trigger_config.offset = trigger_config.offset.clamp(0.0, MAX_TRIGGER_OFFSET);
+        CaptureConfig {
+            probe_multiplier: self.probe.into(),
+            mode: match self.capture_mode {
+                CaptureModeFlat::Triggered => CaptureMode::Triggered {
+                    trigger_config,
+                    time_frame: self.time_frame.clamp(MIN_TIME_FRAME, MAX_TIME_FRAME),
+                },
+                CaptureModeFlat::Continuous => CaptureMode::Continuous {},
+            },
+        }
+    }
+
+    pub fn apply_to(&self, device: &mut FleaScopeDevice) {
+        device.set_probe_multiplier(self.probe.into());
+        device.set_trigger_config((&self.trigger).into());
+        device.set_time_frame(self.time_frame);
+        *device.get_mut_buffer_time_handle() = self.buffer_time;
+        device.set_capture_mode(self.capture_mode);
+        if self.waveform.enabled {
+            device.set_waveform(
+                self.waveform.waveform_type.into(),
+                self.waveform.frequency_hz,
+            );
+        }
+        device.set_enabled_channels(self.enabled_channels);
+        device.wrap = self.wrap;
+        device.envelope = self.envelope;
+        device.measurements.selected = self.measurements.clone();
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerSettings {
+    pub source: TriggerSource,
+    pub analog_volts: f64,
+    pub analog_behavior: AnalogBehaviorSetting,
+    pub digital_bits: [BitSetting; 9],
+    pub digital_behavior: DigitalBehaviorSetting,
+    #[serde(default)]
+    pub position: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl From<&TriggerConfig> for TriggerSettings {
+    fn from(config: &TriggerConfig) -> Self {
+        Self {
+            source: config.source,
+            analog_volts: config.analog.volts,
+            analog_behavior: config.analog.behavior.into(),
+            digital_bits: config.digital.bit_states.map(BitSetting::from),
+            digital_behavior: config.digital.behavior.into(),
+            position: config.position,
+            offset: config.offset,
+        }
+    }
+}
+
+impl From<&TriggerSettings> for TriggerConfig {
+    fn from(settings: &TriggerSettings) -> Self {
+        let mut analog = AnalogTrigger::start_capturing_when(settings.analog_volts);
+        analog.behavior = settings.analog_behavior.into();
+        Self {
+            source: settings.source,
+            analog,
+            digital: DigitalTrigger::new(
+                settings.digital_bits.map(BitState::from),
+                settings.digital_behavior.into(),
+            ),
+            position: settings.position,
+            offset: settings.offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaveformSettings {
+    pub enabled: bool,
+    pub waveform_type: WaveformSetting,
+    pub frequency_hz: i32,
+}
+
+// Mirrors of the fleascope-rs enums, so the stored format does not depend on the library
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ProbeSetting {
+    X1,
+    X10,
+}
+
+impl From<ProbeType> for ProbeSetting {
+    fn from(probe: ProbeType) -> Self {
+        match probe {
+            ProbeType::X1 => Self::X1,
+            ProbeType::X10 => Self::X10,
+        }
+    }
+}
+
+impl From<ProbeSetting> for ProbeType {
+    fn from(probe: ProbeSetting) -> Self {
+        match probe {
+            ProbeSetting::X1 => Self::X1,
+            ProbeSetting::X10 => Self::X10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum AnalogBehaviorSetting {
+    Auto,
+    Level,
+    Rising,
+    Falling,
+}
+
+impl From<AnalogTriggerBehavior> for AnalogBehaviorSetting {
+    fn from(behavior: AnalogTriggerBehavior) -> Self {
+        match behavior {
+            AnalogTriggerBehavior::Auto => Self::Auto,
+            AnalogTriggerBehavior::Level => Self::Level,
+            AnalogTriggerBehavior::Rising => Self::Rising,
+            AnalogTriggerBehavior::Falling => Self::Falling,
+        }
+    }
+}
+
+impl From<AnalogBehaviorSetting> for AnalogTriggerBehavior {
+    fn from(behavior: AnalogBehaviorSetting) -> Self {
+        match behavior {
+            AnalogBehaviorSetting::Auto => Self::Auto,
+            AnalogBehaviorSetting::Level => Self::Level,
+            AnalogBehaviorSetting::Rising => Self::Rising,
+            AnalogBehaviorSetting::Falling => Self::Falling,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum DigitalBehaviorSetting {
+    Auto,
+    While,
+    Start,
+    Stop,
+}
+
+impl From<DigitalTriggerBehavior> for DigitalBehaviorSetting {
+    fn from(behavior: DigitalTriggerBehavior) -> Self {
+        match behavior {
+            DigitalTriggerBehavior::Auto => Self::Auto,
+            DigitalTriggerBehavior::While => Self::While,
+            DigitalTriggerBehavior::Start => Self::Start,
+            DigitalTriggerBehavior::Stop => Self::Stop,
+        }
+    }
+}
+
+impl From<DigitalBehaviorSetting> for DigitalTriggerBehavior {
+    fn from(behavior: DigitalBehaviorSetting) -> Self {
+        match behavior {
+            DigitalBehaviorSetting::Auto => Self::Auto,
+            DigitalBehaviorSetting::While => Self::While,
+            DigitalBehaviorSetting::Start => Self::Start,
+            DigitalBehaviorSetting::Stop => Self::Stop,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum BitSetting {
+    High,
+    Low,
+    DontCare,
+}
+
+impl From<BitState> for BitSetting {
+    fn from(state: BitState) -> Self {
+        match state {
+            BitState::High => Self::High,
+            BitState::Low => Self::Low,
+            BitState::DontCare => Self::DontCare,
+        }
+    }
+}
+
+impl From<BitSetting> for BitState {
+    fn from(state: BitSetting) -> Self {
+        match state {
+            BitSetting::High => Self::High,
+            BitSetting::Low => Self::Low,
+            BitSetting::DontCare => Self::DontCare,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum WaveformSetting {
+    Sine,
+    Square,
+    Triangle,
+    Ekg,
+}
+
+impl From<Waveform> for WaveformSetting {
+    fn from(waveform: Waveform) -> Self {
+        match waveform {
+            Waveform::Sine => Self::Sine,
+            Waveform::Square => Self::Square,
+            Waveform::Triangle => Self::Triangle,
+            Waveform::Ekg => Self::Ekg,
+        }
+    }
+}
+
+impl From<WaveformSetting> for Waveform {
+    fn from(waveform: WaveformSetting) -> Self {
+        match waveform {
+            WaveformSetting::Sine => Self::Sine,
+            WaveformSetting::Square => Self::Square,
+            WaveformSetting::Triangle => Self::Triangle,
+            WaveformSetting::Ekg => Self::Ekg,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::DeviceManager;
+
+    #[tokio::test]
+    async fn restored_devices_start_with_the_saved_capture_settings() {
+        let mut manager = DeviceManager::default();
+        manager.add_device("sim://sine-1k".to_string()).unwrap();
+        let mut saved = DeviceSession::capture(&manager.get_devices_mut()[0]);
+        saved.probe = ProbeSetting::X10;
+        saved.capture_mode = CaptureModeFlat::Triggered;
+        saved.time_frame = 0.02;
+        saved.trigger.source = TriggerSource::Analog;
+        saved.trigger.analog_volts = 1.2;
+        // Out of range, as in a hand-edited session
+        saved.trigger.position = 5.0;
+
+        let config = saved.capture_config();
+        manager
+            .add_device_with_config(saved.hostname.clone(), config)
+            .unwrap();
+        let restored = &manager.get_devices_mut()[1];
+        assert_eq!(restored.get_probe_multiplier(), ProbeType::X10);
+        assert!(matches!(
+            restored.get_capture_mode(),
+            CaptureModeFlat::Triggered
+        ));
+        let triggered = restored.get_triggered_config();
+        assert_eq!(triggered.time_frame, 0.02);
+        assert_eq!(triggered.trigger_config.source, TriggerSource::Analog);
+        assert_eq!(triggered.trigger_config.analog.volts, 1.2);
+        assert_eq!(triggered.trigger_config.position, MAX_TRIGGER_POSITION);
+
+        manager.remove_device(1);
+        manager.remove_device(0);
+    }
+}
@@ -0,0 +1,675 @@
+use fleascope_rs::flea_scope::{CalibrationError, CaptureConfigError, ScopeReading};
+use fleascope_rs::serial_terminal::ConnectionLostError;
+use fleascope_rs::{
+    AnalogTrigger, AnalogTriggerBehavior, BitState, DigitalTrigger, DigitalTriggerBehavior,
+    FleaConnectorError, FleaProbe, ProbeType, Trigger, Waveform,
+};
+use std::io::Write as _;
+use std::time::{Duration, Instant};
+
+use crate::device::{CONTINUOUS_SAMPLE_RATE_HZ, MAX_TIME_FRAME, MIN_TIME_FRAME};
+use crate::device_worker::{PendingReading, RawBatch, ScopeBackend, ScopeStream};
+
+/// Hostname prefix that selects the simulated backend
+pub const SIMULATOR_SCHEME: &str = "sim://";
+
+// Firmware constants, see `IdleFleaScope::prepare_read_command`
+const MSPS: u32 = 18;
+const MCU_MHZ: f64 = 120.0;
+const INTERLEAVE: u32 = 5;
+const TOTAL_SAMPLES: u32 = 2000;
+
+// Raw ADC values of the simulated probes at 0V and the span for 3.3V
+const X1_CALIBRATION: (f64, f64) = (2048.0, 1000.0);
+const X10_CALIBRATION: (f64, f64) = (2048.0, 100.0);
+
+/// Auto triggers fire after this long without a match
+const AUTO_TRIGGER_TIMEOUT: f64 = 0.1;
+/// Upper bound of samples checked for the trigger per poll, so a poll returns as quickly as a
+/// serial read timeout even when polls are far apart
+const MAX_TRIGGER_SEARCH_STEPS: usize = 100_000;
+
+pub fn is_simulated(hostname: &str) -> bool {
+    hostname.starts_with(SIMULATOR_SCHEME)
+}
+
+/// Signal and fault description parsed from a `sim://` hostname.
+///
+/// Format: `sim://<waveform>-<frequency>[?key=value&...]`, e.g. `sim://sine-1k` or
+/// `sim://square-250?noise=0.05&disconnect_after=10`.
+#[derive(Debug, Clone)]
+pub struct SimulatorConfig {
+    pub waveform: Waveform,
+    pub frequency_hz: f64,
+    pub amplitude: f64,
+    pub offset: f64,
+    pub noise: f64,
+    /// Drop the connection this many seconds after connecting
+    pub disconnect_after: Option<f64>,
+}
+
+impl SimulatorConfig {
+    pub fn parse(hostname: &str) -> Option<Self> {
+        let spec = hostname.strip_prefix(SIMULATOR_SCHEME)?;
+        let (signal, options) = spec.split_once('?').unwrap_or((spec, ""));
+        let (waveform, frequency) = signal.split_once('-')?;
+
+        let mut config = Self {
+            waveform: match waveform {
+                "sine" => Waveform::Sine,
+                "square" => Waveform::Square,
+                "triangle" => Waveform::Triangle,
+                "ekg" => Waveform::Ekg,
+                _ => return None,
+            },
+            frequency_hz: parse_si(frequency)?,
+            amplitude: 1.5,
+            offset: 1.65,
+            noise: 0.01,
+            disconnect_after: None,
+        };
+
+        for option in options.split('&').filter(|o| !o.is_empty()) {
+            let (key, value) = option.split_once('=')?;
+            let value = parse_si(value)?;
+            match key {
+                "amplitude" => config.amplitude = value,
+                "offset" => config.offset = value,
+                "noise" => config.noise = value,
+                "disconnect_after" => config.disconnect_after = Some(value),
+                _ => return None,
+            }
+        }
+        Some(config)
+    }
+}
+
+/// Parse numbers like `250`, `1k` or `2.5k`
+fn parse_si(value: &str) -> Option<f64> {
+    let (number, factor) = match value.strip_suffix('k') {
+        Some(number) => (number, 1e3),
+        None => (value, 1.0),
+    };
+    number.parse::<f64>().ok().map(|n| n * factor)
+}
+
+/// Synthetic signal source shared by all typestates of a simulated scope
+struct SignalSource {
+    config: SimulatorConfig,
+    x1: FleaProbe,
+    x10: FleaProbe,
+    connected_at: Instant,
+    rng_state: u64,
+}
+
+impl SignalSource {
+    fn probe(&self, probe: ProbeType) -> &FleaProbe {
+        match probe {
+            ProbeType::X1 => &self.x1,
+            ProbeType::X10 => &self.x10,
+        }
+    }
+
+    /// Seconds since connecting, which is the time base of the signal
+    fn now(&self) -> f64 {
+        self.connected_at.elapsed().as_secs_f64()
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.config
+            .disconnect_after
+            .is_some_and(|after| self.now() >= after)
+    }
+
+    fn shape(&self, t: f64) -> f64 {
+        let phase = (t * self.config.frequency_hz).fract();
+        match self.config.waveform {
+            Waveform::Sine => (phase * std::f64::consts::TAU).sin(),
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Ekg => {
+                // P wave, QRS complex and T wave as gaussian bumps
+                const BUMPS: [(f64, f64, f64); 5] = [
+                    (0.20, 0.15, 0.025),
+                    (0.37, -0.10, 0.010),
+                    (0.40, 1.00, 0.012),
+                    (0.43, -0.25, 0.012),
+                    (0.65, 0.30, 0.040),
+                ];
+                BUMPS
+                    .iter()
+                    .map(|(center, amplitude, width)| {
+                        amplitude * (-((phase - center) / width).powi(2) / 2.0).exp()
+                    })
+                    .sum()
+            }
+        }
+    }
+
+    /// Noise-free input voltage at time `t`
+    fn voltage(&self, t: f64) -> f64 {
+        self.config.offset + self.config.amplitude * self.shape(t)
+    }
+
+    /// A 9-bit counter clocked at twice the signal frequency, so D0 toggles with the signal
+    fn bitmap(&self, t: f64) -> u16 {
+        ((t * self.config.frequency_hz * 2.0).floor() as u64 & 0x1ff) as u16
+    }
+
+    fn gaussian(&mut self) -> f64 {
+        let mut uniform = || {
+            // xorshift64
+            self.rng_state ^= self.rng_state << 13;
+            self.rng_state ^= self.rng_state >> 7;
+            self.rng_state ^= self.rng_state << 17;
+            (self.rng_state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let (u1, u2) = (uniform().max(f64::MIN_POSITIVE), uniform());
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+
+    fn raw_sample(&mut self, probe: ProbeType, t: f64) -> u16 {
+        let voltage = self.voltage(t) + self.config.noise * self.gaussian();
+        self.probe(probe)
+            .voltage_to_raw(voltage)
+            .round()
+            .clamp(0.0, 4095.0) as u16
+    }
+
+    /// Raw trigger comparator value, which the firmware compares at a quarter of the ADC resolution
+    fn trigger_value(&self, probe: ProbeType, t: f64) -> f64 {
+        self.probe(probe).voltage_to_raw(self.voltage(t)) / 4.0
+    }
+
+    fn digital_matches(trigger: &DigitalTrigger, bitmap: u16) -> bool {
+        trigger
+            .bit_states
+            .iter()
+            .enumerate()
+            .all(|(bit, state)| match state {
+                BitState::DontCare => true,
+                BitState::High => bitmap & (1 << bit) != 0,
+                BitState::Low => bitmap & (1 << bit) == 0,
+            })
+    }
+
+    /// Search the signal between `from` and `until` for the first instant `trigger` fires.
+    ///
+    /// Returns how far the search got, which is short of `until` after
+    /// [`MAX_TRIGGER_SEARCH_STEPS`], and the trigger instant if it fired. Auto triggers fire on
+    /// their own [`AUTO_TRIGGER_TIMEOUT`] after `armed_at`.
+    fn find_trigger(
+        &self,
+        probe: ProbeType,
+        trigger: &Trigger,
+        armed_at: f64,
+        from: f64,
+        until: f64,
+        dt: f64,
+    ) -> (f64, Option<f64>) {
+        let timeout = match trigger {
+            Trigger::Analog(AnalogTrigger { behavior, .. })
+                if *behavior == AnalogTriggerBehavior::Auto =>
+            {
+                Some(AUTO_TRIGGER_TIMEOUT)
+            }
+            Trigger::Digital(DigitalTrigger { behavior, .. })
+                if *behavior == DigitalTriggerBehavior::Auto =>
+            {
+                Some(AUTO_TRIGGER_TIMEOUT)
+            }
+            _ => None,
+        };
+
+        let steps = (((until - from) / dt).max(0.0) as usize).min(MAX_TRIGGER_SEARCH_STEPS);
+        let mut previous = from;
+        for step in 1..=steps {
+            let t = from + step as f64 * dt;
+            let fires = timeout.is_some_and(|timeout| t - armed_at >= timeout)
+                || match trigger {
+                    Trigger::Analog(analog) => self.analog_fires(probe, analog, previous, t),
+                    Trigger::Digital(digital) => self.digital_fires(digital, previous, t),
+                };
+            if fires {
+                return (t, Some(t));
+            }
+            previous = t;
+        }
+        (previous, None)
+    }
+
+    fn analog_fires(
+        &self,
+        probe: ProbeType,
+        trigger: &AnalogTrigger,
+        previous: f64,
+        t: f64,
+    ) -> bool {
+        let level = trigger.level as f64;
+        let (before, now) = (
+            self.trigger_value(probe, previous),
+            self.trigger_value(probe, t),
+        );
+        match trigger.behavior {
+            AnalogTriggerBehavior::Rising => before < level && now >= level,
+            AnalogTriggerBehavior::Falling => before > level && now <= level,
+            // Level and auto fire on a crossing in either direction
+            AnalogTriggerBehavior::Level | AnalogTriggerBehavior::Auto => {
+                (before < level) != (now < level)
+            }
+        }
+    }
+
+    fn digital_fires(&self, trigger: &DigitalTrigger, previous: f64, t: f64) -> bool {
+        let was_matching = Self::digital_matches(trigger, self.bitmap(previous));
+        let matching = Self::digital_matches(trigger, self.bitmap(t));
+        match trigger.behavior {
+            DigitalTriggerBehavior::While | DigitalTriggerBehavior::Auto => matching,
+            DigitalTriggerBehavior::Start => !was_matching && matching,
+            DigitalTriggerBehavior::Stop => was_matching && !matching,
+        }
+    }
+}
+
+/// Idle simulated FleaScope, selected by hostnames starting with `sim://`
+pub struct SimulatedScope {
+    source: SignalSource,
+}
+
+impl SimulatedScope {
+    /// Mirror the firmware's prescaler selection to get the same sample rate as real hardware
+    fn effective_msps(time_frame: Duration) -> Result<f64, CaptureConfigError> {
+        if time_frame.as_secs_f64() > MAX_TIME_FRAME {
+            return Err(CaptureConfigError::TimeFrameTooLarge);
+        }
+        if time_frame.as_secs_f64() < MIN_TIME_FRAME {
+            return Err(CaptureConfigError::TimeFrameTooSmall);
+        }
+        let number1 = MSPS * time_frame.as_micros() as u32 / TOTAL_SAMPLES;
+        let ps = if number1 > 1000 { 16 } else { 1 };
+        let t = ((MCU_MHZ * (number1 * INTERLEAVE) as f64 / ps as f64 / MSPS as f64) + 0.5) as u32;
+        if t == 0 {
+            return Err(CaptureConfigError::TimeFrameTooSmall);
+        }
+        Ok(MCU_MHZ * INTERLEAVE as f64 / (ps * t) as f64)
+    }
+}
+
+impl ScopeBackend for SimulatedScope {
+    type Reading = SimulatedReading;
+    type Stream = SimulatedStream;
+
+    fn connect(hostname: &str) -> Result<(Self, FleaProbe, FleaProbe), FleaConnectorError> {
+        let config =
+            SimulatorConfig::parse(hostname).ok_or_else(|| FleaConnectorError::DeviceNotFound {
+                name: hostname.to_string(),
+            })?;
+
+        let mut x1 = FleaProbe::new(ProbeType::X1);
+        x1.set_calibration(X1_CALIBRATION.0, X1_CALIBRATION.1);
+        let mut x10 = FleaProbe::new(ProbeType::X10);
+        x10.set_calibration(X10_CALIBRATION.0, X10_CALIBRATION.1);
+
+        tracing::info!("Connected to simulated FleaScope {:?}", config);
+        let scope = Self {
+            source: SignalSource {
+                config,
+                x1: x1.clone(),
+                x10: x10.clone(),
+                connected_at: Instant::now(),
+                rng_state: 0x2545_f491_4f6c_dd1d,
+            },
+        };
+        Ok((scope, x1, x10))
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform, hz: i32) {
+        // The generator output is looped back into the BNC input
+        self.source.config.waveform = waveform;
+        self.source.config.frequency_hz = hz as f64;
+    }
+
+    fn calibrate_0(
+        &mut self,
+        probe_type: ProbeType,
+        probe: &mut FleaProbe,
+    ) -> Result<f64, CalibrationError> {
+        // The simulated input is ideal, so calibrating restores the true calibration
+        let (zero, span) = match self.source.probe(probe_type).calibration() {
+            (Some(zero), Some(span)) => (zero, span),
+            _ => return Err(CalibrationError::NoCalibrationPresent),
+        };
+        probe.set_calibration(zero, span);
+        Ok(zero)
+    }
+
+    fn calibrate_3v3(
+        &mut self,
+        probe_type: ProbeType,
+        probe: &mut FleaProbe,
+    ) -> Result<f64, CalibrationError> {
+        if probe.calibration().0.is_none() {
+            return Err(CalibrationError::NoZeroCalibrarion);
+        }
+        let (zero, span) = match self.source.probe(probe_type).calibration() {
+            (Some(zero), Some(span)) => (zero, span),
+            _ => return Err(CalibrationError::NoCalibrationPresent),
+        };
+        probe.set_calibration(zero, span);
+        Ok(span)
+    }
+
+    fn write_calibration(&mut self, probe: &FleaProbe) -> Result<(), CalibrationError> {
+        match probe.calibration() {
+            (Some(_), Some(_)) => Ok(()),
+            _ => Err(CalibrationError::NoCalibrationPresent),
+        }
+    }
+
+    fn read_async(
+        self,
+        probe: ProbeType,
+        time_frame: Duration,
+        trigger: Trigger,
+        delay: Duration,
+    ) -> Result<Self::Reading, (Self, CaptureConfigError)> {
+        let effective_msps = match Self::effective_msps(time_frame) {
+            Ok(effective_msps) => effective_msps,
+            Err(e) => return Err((self, e)),
+        };
+        // The signal is searched for the trigger as it arrives, poll by poll
+        let armed_at = self.source.now();
+        Ok(SimulatedReading {
+            scope: self,
+            probe,
+            effective_msps,
+            trigger,
+            delay: delay.as_secs_f64(),
+            armed_at,
+            searched_until: armed_at,
+            trigger_at: None,
+        })
+    }
+
+    fn stream(self, probe: ProbeType) -> Self::Stream {
+        let next_t = self.source.now();
+        SimulatedStream {
+            scope: self,
+            probe,
+            next_t,
+        }
+    }
+
+    fn teardown(self) {
+        tracing::info!("Simulated FleaScope torn down");
+    }
+}
+
+pub struct SimulatedReading {
+    scope: SimulatedScope,
+    probe: ProbeType,
+    effective_msps: f64,
+    trigger: Trigger,
+    /// Seconds sampling starts after the trigger
+    delay: f64,
+    armed_at: f64,
+    /// Signal up to here has been searched for the trigger
+    searched_until: f64,
+    /// When sampling starts; `None` until the trigger fires, like real hardware waiting forever
+    trigger_at: Option<f64>,
+}
+
+impl PendingReading for SimulatedReading {
+    type Scope = SimulatedScope;
+
+    fn try_get_result(
+        mut self,
+    ) -> Result<Result<(Self::Scope, ScopeReading), Self>, ConnectionLostError> {
+        if self.scope.source.is_disconnected() {
+            return Err(ConnectionLostError);
+        }
+
+        let dt = 1.0 / (self.effective_msps * 1_000_000.0);
+        if self.trigger_at.is_none() {
+            let source = &self.scope.source;
+            let (searched_until, fired_at) = source.find_trigger(
+                self.probe,
+                &self.trigger,
+                self.armed_at,
+                self.searched_until,
+                source.now(),
+                dt,
+            );
+            self.searched_until = searched_until;
+            self.trigger_at = fired_at.map(|fired_at| fired_at + self.delay);
+        }
+        let done_at = self
+            .trigger_at
+            .map(|trigger_at| trigger_at + TOTAL_SAMPLES as f64 * dt);
+        match done_at {
+            Some(done_at) if self.scope.source.now() >= done_at => {}
+            _ => {
+                // Behave like a serial read timeout instead of spinning the caller
+                std::thread::sleep(Duration::from_millis(1));
+                return Ok(Err(self));
+            }
+        }
+
+        let trigger_at = self.trigger_at.expect("checked above");
+        let source = &mut self.scope.source;
+        let mut data = Vec::with_capacity(TOTAL_SAMPLES as usize * 12);
+        for i in 0..TOTAL_SAMPLES {
+            let t = trigger_at + i as f64 * dt;
+            let raw = source.raw_sample(self.probe, t);
+            writeln!(data, "{},0x{:x}", raw, source.bitmap(t)).expect("writing to a Vec");
+        }
+
+        Ok(Ok((
+            self.scope,
+            ScopeReading {
+                effective_msps: self.effective_msps,
+                data,
+            },
+        )))
+    }
+
+    fn cancel(self) -> Self::Scope {
+        self.scope
+    }
+}
+
+pub struct SimulatedStream {
+    scope: SimulatedScope,
+    probe: ProbeType,
+    next_t: f64,
+}
+
+impl ScopeStream for SimulatedStream {
+    type Scope = SimulatedScope;
+
+    fn read(&mut self, n: usize) -> Result<RawBatch, std::io::Error> {
+        let source = &mut self.scope.source;
+        if source.is_disconnected() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "simulated disconnect",
+            ));
+        }
+
+        let dt = 1.0 / CONTINUOUS_SAMPLE_RATE_HZ as f64;
+        let times: Vec<f64> = (0..n).map(|i| self.next_t + i as f64 * dt).collect();
+        let batch = RawBatch {
+            adc: times
+                .iter()
+                .map(|&t| source.raw_sample(self.probe, t))
+                .collect(),
+            bitmap: Some(times.iter().map(|&t| source.bitmap(t)).collect()),
+        };
+        self.next_t += n as f64 * dt;
+
+        // Pace the stream like the real device does
+        let ahead = self.next_t - source.now();
+        if ahead > 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(ahead));
+        }
+        Ok(batch)
+    }
+
+    fn stop(self) -> Self::Scope {
+        self.scope
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One microsecond per trigger search step
+    const DT: f64 = 1e-6;
+
+    fn source(hostname: &str) -> SignalSource {
+        SimulatedScope::connect(hostname).unwrap().0.source
+    }
+
+    /// Raw trigger level of `volts` on the X1 probe
+    fn level(source: &SignalSource, volts: f64) -> i16 {
+        (source.x1.voltage_to_raw(volts) / 4.0).round() as i16
+    }
+
+    fn analog(source: &SignalSource, volts: f64, behavior: AnalogTriggerBehavior) -> Trigger {
+        Trigger::Analog(AnalogTrigger::new(level(source, volts), behavior))
+    }
+
+    fn assert_fires_at(found: (f64, Option<f64>), expected: f64) {
+        let fired = found.1.expect("trigger did not fire");
+        assert!(
+            (fired - expected).abs() < 5.0 * DT,
+            "fired at {fired}, expected {expected}"
+        );
+        assert_eq!(found.0, fired);
+    }
+
+    #[test]
+    fn parses_waveform_frequency_and_options() {
+        let config =
+            SimulatorConfig::parse("sim://square-250?noise=0.05&disconnect_after=10").unwrap();
+        assert_eq!(config.waveform, Waveform::Square);
+        assert_eq!(config.frequency_hz, 250.0);
+        assert_eq!(config.noise, 0.05);
+        assert_eq!(config.disconnect_after, Some(10.0));
+        assert_eq!(config.amplitude, 1.5);
+
+        let config = SimulatorConfig::parse("sim://ekg-2.5k?amplitude=1&offset=0.5").unwrap();
+        assert_eq!(config.waveform, Waveform::Ekg);
+        assert_eq!(config.frequency_hz, 2500.0);
+        assert_eq!((config.amplitude, config.offset), (1.0, 0.5));
+        assert_eq!(config.disconnect_after, None);
+    }
+
+    #[test]
+    fn rejects_malformed_hostnames() {
+        for hostname in [
+            "/dev/ttyACM0",
+            "sim://sine",
+            "sim://sawtooth-1k",
+            "sim://sine-fast",
+            "sim://sine-1M",
+            "sim://sine-1k?volume=2",
+            "sim://sine-1k?noise",
+            "sim://sine-1k?noise=lots",
+        ] {
+            assert!(SimulatorConfig::parse(hostname).is_none(), "{hostname}");
+        }
+        assert!(SimulatedScope::connect("sim://sawtooth-1k").is_err());
+    }
+
+    #[test]
+    fn analog_triggers_fire_on_their_slope_and_level() {
+        // 1.65V ± 1.5V at 1kHz crosses 3V upwards at asin(0.9) / 2π of a period and downwards
+        // the same distance before half a period
+        let source = source("sim://sine-1k?noise=0");
+        let up = 0.9f64.asin() / std::f64::consts::TAU * 1e-3;
+        let down = 0.5e-3 - up;
+        let search = |trigger: &Trigger, from: f64| {
+            source.find_trigger(ProbeType::X1, trigger, from, from, 0.01, DT)
+        };
+
+        let rising = analog(&source, 3.0, AnalogTriggerBehavior::Rising);
+        assert_fires_at(search(&rising, 0.0), up);
+        assert_fires_at(search(&rising, up + 10.0 * DT), 1e-3 + up);
+
+        let falling = analog(&source, 3.0, AnalogTriggerBehavior::Falling);
+        assert_fires_at(search(&falling, 0.0), down);
+
+        let level = analog(&source, 3.0, AnalogTriggerBehavior::Level);
+        assert_fires_at(search(&level, 0.0), up);
+        assert_fires_at(search(&level, up + 10.0 * DT), down);
+
+        let falling_at_offset = analog(&source, 1.65, AnalogTriggerBehavior::Falling);
+        assert_fires_at(search(&falling_at_offset, 0.0), 0.5e-3);
+    }
+
+    #[test]
+    fn untriggered_searches_stop_at_until_and_auto_fires_on_timeout() {
+        let source = source("sim://sine-1k?noise=0");
+        // Above the 3.15V peak, so never crossed
+        let rising = analog(&source, 3.2, AnalogTriggerBehavior::Rising);
+        let found = source.find_trigger(ProbeType::X1, &rising, 0.0, 0.0, 0.01, DT);
+        assert!(found.1.is_none());
+        assert!((found.0 - 0.01).abs() < DT);
+
+        let auto = analog(&source, 3.2, AnalogTriggerBehavior::Auto);
+        let armed_at = 0.02;
+        let found = source.find_trigger(ProbeType::X1, &auto, armed_at, 0.05, 0.2, DT);
+        assert_fires_at(found, armed_at + AUTO_TRIGGER_TIMEOUT);
+    }
+
+    #[test]
+    fn long_searches_resume_where_the_last_one_stopped() {
+        // 1Hz rises through the offset again only at t = 1s
+        let source = source("sim://sine-1?noise=0");
+        let trigger = analog(&source, 1.65, AnalogTriggerBehavior::Rising);
+        let step = MAX_TRIGGER_SEARCH_STEPS as f64 * DT;
+
+        let (mut reached, fired) =
+            source.find_trigger(ProbeType::X1, &trigger, 0.25, 0.25, 2.0, DT);
+        assert!(fired.is_none());
+        assert!((reached - (0.25 + step)).abs() < 1e-9);
+
+        let mut searches = 1;
+        let fired = loop {
+            let found = source.find_trigger(ProbeType::X1, &trigger, 0.25, reached, 2.0, DT);
+            searches += 1;
+            if found.1.is_some() {
+                break found;
+            }
+            assert!(found.0 > reached);
+            reached = found.0;
+        };
+        assert_fires_at(fired, 1.0);
+        assert_eq!(searches, 8);
+    }
+
+    #[test]
+    fn digital_triggers_follow_the_counter() {
+        // D0 toggles every half period, D1 every period
+        let source = source("sim://square-1k?noise=0");
+        let mut bits = [BitState::DontCare; 9];
+        bits[0] = BitState::High;
+        bits[1] = BitState::High;
+        let search = |behavior| {
+            let trigger = Trigger::Digital(DigitalTrigger::new(bits, behavior));
+            source.find_trigger(ProbeType::X1, &trigger, 0.0, 0.0, 0.01, DT)
+        };
+
+        assert_fires_at(search(DigitalTriggerBehavior::Start), 1.5e-3);
+        assert_fires_at(search(DigitalTriggerBehavior::While), 1.5e-3);
+        assert_fires_at(search(DigitalTriggerBehavior::Stop), 2e-3);
+    }
+}
@@ -0,0 +1,442 @@
+use egui::{Color32, RichText};
+use egui_plot::{Line, Plot, PlotPoints, Points, Text};
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::control_panel::pretty_print_number;
+use crate::device::AnalogSamples;
+
+/// Longest transform; longer windows use their most recent samples
+const MAX_FFT_SIZE: usize = 65_536;
+
+/// Floor of the dB scale, so empty bins do not stretch the plot to -inf
+const DB_FLOOR: f64 = -160.0;
+
+const MAX_AVERAGES: usize = 64;
+
+/// Shortest time between two transforms, continuous input changes with every batch
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFunction {
+    Hann,
+    Hamming,
+    Blackman,
+    FlatTop,
+}
+
+impl WindowFunction {
+    pub const ALL: [WindowFunction; 4] = [
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+        WindowFunction::Blackman,
+        WindowFunction::FlatTop,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindowFunction::Hann => "Hann",
+            WindowFunction::Hamming => "Hamming",
+            WindowFunction::Blackman => "Blackman",
+            WindowFunction::FlatTop => "Flat-top",
+        }
+    }
+
+    /// Cosine-sum coefficients a0, a1, ... of the window
+    fn coefficients(&self) -> &'static [f64] {
+        match self {
+            WindowFunction::Hann => &[0.5, 0.5],
+            WindowFunction::Hamming => &[0.54, 0.46],
+            WindowFunction::Blackman => &[0.42, 0.5, 0.08],
+            WindowFunction::FlatTop => &[
+                0.215_578_95,
+                0.416_631_58,
+                0.277_263_158,
+                0.083_578_947,
+                0.006_947_368,
+            ],
+        }
+    }
+
+    fn weights(&self, n: usize) -> Vec<f64> {
+        let coefficients = self.coefficients();
+        (0..n)
+            .map(|i| {
+                let phase = 2.0 * std::f64::consts::PI * i as f64 / (n.max(2) - 1) as f64;
+                coefficients
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * phase).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Single-sided amplitude spectrum in volts peak
+pub struct Spectrum {
+    pub frequencies: Vec<f64>,
+    pub amplitudes: Vec<f64>,
+}
+
+impl Spectrum {
+    /// `planner` keeps the plans, so repeated transforms of one size are only planned once
+    pub fn compute(
+        planner: &mut FftPlanner<f64>,
+        time: &[f64],
+        volts: &[f64],
+        window: WindowFunction,
+    ) -> Option<Self> {
+        profiling::scope!("Spectrum::compute");
+
+        let n = time.len().min(volts.len()).min(MAX_FFT_SIZE);
+        if n < 4 {
+            return None;
+        }
+        let time = &time[time.len() - n..];
+        let volts = &volts[volts.len() - n..];
+        let sample_rate = (n - 1) as f64 / (time[n - 1] - time[0]);
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return None;
+        }
+
+        let weights = window.weights(n);
+        let coherent_gain = weights.iter().sum::<f64>();
+        let mut buffer: Vec<Complex<f64>> = volts
+            .iter()
+            .zip(&weights)
+            .map(|(v, w)| Complex::new(v * w, 0.0))
+            .collect();
+        planner.plan_fft_forward(n).process(&mut buffer);
+
+        let bins = n / 2 + 1;
+        let amplitudes = buffer[..bins]
+            .iter()
+            .enumerate()
+            .map(|(k, x)| {
+                // Energy of all other bins than DC (and Nyquist) is split over both halves
+                let scale = if k == 0 || 2 * k == n { 1.0 } else { 2.0 };
+                scale * x.norm() / coherent_gain
+            })
+            .collect();
+        let frequencies = (0..bins)
+            .map(|k| k as f64 * sample_rate / n as f64)
+            .collect();
+        Some(Self {
+            frequencies,
+            amplitudes,
+        })
+    }
+
+    /// Strongest bin outside the skirt of the DC component
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        let amplitudes = &self.amplitudes;
+        // Bin 1 gets twice the scale of DC, so the skirt can only start falling from bin 2
+        let skirt_end = (2..amplitudes.len())
+            .find(|&k| amplitudes[k] >= amplitudes[k - 1])
+            .unwrap_or(amplitudes.len());
+        (skirt_end..amplitudes.len())
+            .max_by(|&a, &b| self.amplitudes[a].total_cmp(&self.amplitudes[b]))
+            .map(|k| (self.frequencies[k], self.amplitudes[k]))
+    }
+}
+
+/// Spectrum settings and averaging state of one device
+pub struct SpectrumView {
+    pub enabled: bool,
+    pub window: WindowFunction,
+    pub db: bool,
+    pub averages: usize,
+    history: VecDeque<Vec<f64>>,
+    frequencies: Vec<f64>,
+    /// The last input, so an unchanged capture is not averaged twice
+    last_input: Option<AnalogSamples>,
+    last_update: Option<Instant>,
+    planner: FftPlanner<f64>,
+}
+
+impl Default for SpectrumView {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: WindowFunction::Hann,
+            db: true,
+            averages: 1,
+            history: VecDeque::new(),
+            frequencies: Vec::new(),
+            last_input: None,
+            last_update: None,
+            planner: FftPlanner::new(),
+        }
+    }
+}
+
+impl SpectrumView {
+    /// Add a new spectrum to the average. Unchanged input and changed settings are handled here.
+    ///
+    /// Runs at most every `MIN_UPDATE_INTERVAL`, input arriving in between is skipped.
+    fn update(&mut self, samples: &AnalogSamples) {
+        if self
+            .last_update
+            .is_some_and(|last| last.elapsed() < MIN_UPDATE_INTERVAL)
+        {
+            return;
+        }
+        if self
+            .last_input
+            .as_ref()
+            .is_some_and(|last| Arc::ptr_eq(last, samples))
+        {
+            return;
+        }
+        self.last_input = Some(samples.clone());
+        self.last_update = Some(Instant::now());
+
+        let (time, volts) = &**samples;
+        let Some(spectrum) = Spectrum::compute(&mut self.planner, time, volts, self.window) else {
+            return;
+        };
+        if spectrum.frequencies != self.frequencies {
+            // Different length or rate, earlier spectra do not line up anymore
+            self.history.clear();
+            self.frequencies = spectrum.frequencies;
+        }
+        self.history.push_back(spectrum.amplitudes);
+        while self.history.len() > self.averages {
+            self.history.pop_front();
+        }
+    }
+
+    /// RMS average of the kept spectra
+    fn averaged(&self) -> Option<Spectrum> {
+        let count = self.history.len();
+        let first = self.history.front()?;
+        let amplitudes = (0..first.len())
+            .map(|k| {
+                let power = self.history.iter().map(|s| s[k] * s[k]).sum::<f64>() / count as f64;
+                power.sqrt()
+            })
+            .collect();
+        Some(Spectrum {
+            frequencies: self.frequencies.clone(),
+            amplitudes,
+        })
+    }
+
+    fn scaled(&self, amplitude: f64) -> f64 {
+        if self.db {
+            (20.0 * amplitude.log10()).max(DB_FLOOR)
+        } else {
+            amplitude
+        }
+    }
+
+    fn format_amplitude(&self, amplitude: f64) -> String {
+        if self.db {
+            format!("{:.1} dBV", self.scaled(amplitude))
+        } else {
+            pretty_print_number(amplitude, Some("V"), 4)
+        }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        id_salt: &str,
+        samples: &AnalogSamples,
+        height: f32,
+        show_grid: bool,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        self.update(samples);
+        let spectrum = self.averaged();
+
+        ui.label(RichText::new("Spectrum").strong());
+        ui.horizontal(|ui| {
+            let previous = self.window;
+            egui::ComboBox::from_id_salt(format!("fft_window_{}", id_salt))
+                .selected_text(self.window.as_str())
+                .width(80.0)
+                .show_ui(ui, |ui| {
+                    for window in WindowFunction::ALL {
+                        ui.selectable_value(&mut self.window, window, window.as_str());
+                    }
+                });
+            if self.window != previous {
+                self.history.clear();
+                self.last_input = None;
+                self.last_update = None;
+            }
+
+            ui.selectable_value(&mut self.db, false, "Linear");
+            ui.selectable_value(&mut self.db, true, "dB");
+
+            ui.label("Avg:");
+            ui.add(egui::DragValue::new(&mut self.averages).range(1..=MAX_AVERAGES));
+
+            if let Some((frequency, amplitude)) = spectrum.as_ref().and_then(Spectrum::peak) {
+                ui.separator();
+                ui.label(RichText::new("Peak").size(9.0).color(Color32::LIGHT_GRAY));
+                ui.label(
+                    RichText::new(format!(
+                        "{} {}",
+                        pretty_print_number(frequency, Some("Hz"), 4),
+                        self.format_amplitude(amplitude)
+                    ))
+                    .size(10.0)
+                    .color(Color32::WHITE)
+                    .family(egui::FontFamily::Monospace),
+                );
+            }
+        });
+
+        let Some(spectrum) = spectrum else {
+            ui.label("No data available");
+            return;
+        };
+
+        let plot = Plot::new(format!("spectrum_plot_{}", id_salt))
+            .height(height)
+            .show_grid(show_grid)
+            .auto_bounds([true, true])
+            .allow_zoom(true)
+            .allow_drag(true)
+            .allow_scroll(false)
+            .x_axis_formatter(|mark, _| pretty_print_number(mark.value, Some("Hz"), 3));
+
+        plot.show(ui, |plot_ui| {
+            let points: Vec<[f64; 2]> = spectrum
+                .frequencies
+                .iter()
+                .zip(&spectrum.amplitudes)
+                .map(|(f, a)| [*f, self.scaled(*a)])
+                .collect();
+            plot_ui.line(
+                Line::new("Spectrum", PlotPoints::from(points))
+                    .color(Color32::LIGHT_GREEN)
+                    .width(1.5),
+            );
+
+            if let Some((frequency, amplitude)) = spectrum.peak() {
+                let position = [frequency, self.scaled(amplitude)];
+                plot_ui.points(
+                    Points::new("Peak", vec![position])
+                        .color(Color32::RED)
+                        .radius(4.0),
+                );
+                plot_ui.text(
+                    Text::new(
+                        "Peak label",
+                        position.into(),
+                        format!(
+                            "{}\n{}",
+                            pretty_print_number(frequency, Some("Hz"), 4),
+                            self.format_amplitude(amplitude)
+                        ),
+                    )
+                    .anchor(egui::Align2::LEFT_BOTTOM)
+                    .color(Color32::RED),
+                );
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const SAMPLE_RATE: f64 = 8192.0;
+    const N: usize = 4096;
+
+    /// `N` samples of an offset sine at `frequency`
+    fn sine(frequency: f64, amplitude: f64) -> AnalogSamples {
+        Arc::new(
+            (0..N)
+                .map(|i| {
+                    let t = i as f64 / SAMPLE_RATE;
+                    (t, 1.0 + amplitude * (2.0 * PI * frequency * t).sin())
+                })
+                .unzip(),
+        )
+    }
+
+    fn spectrum(samples: &AnalogSamples, window: WindowFunction) -> Spectrum {
+        let (time, volts) = &**samples;
+        Spectrum::compute(&mut FftPlanner::new(), time, volts, window).unwrap()
+    }
+
+    #[test]
+    fn finds_the_peak_of_a_sine_under_every_window() {
+        // Bin 250 with 2 Hz bins
+        let samples = sine(500.0, 0.5);
+        for window in WindowFunction::ALL {
+            let spectrum = spectrum(&samples, window);
+            assert_eq!(spectrum.frequencies.len(), N / 2 + 1);
+            assert_eq!(spectrum.frequencies[250], 500.0);
+
+            let (frequency, amplitude) = spectrum.peak().unwrap();
+            assert_eq!(frequency, 500.0, "{}", window.as_str());
+            assert!(
+                (amplitude - 0.5).abs() < 0.005,
+                "{}: {}",
+                window.as_str(),
+                amplitude
+            );
+            // The DC offset is there but not taken for the peak
+            assert!(
+                (spectrum.amplitudes[0] - 1.0).abs() < 0.01,
+                "{}",
+                window.as_str()
+            );
+        }
+    }
+
+    #[test]
+    fn flat_top_keeps_the_amplitude_between_bins() {
+        let spectrum = spectrum(&sine(501.0, 0.5), WindowFunction::FlatTop);
+        let (frequency, amplitude) = spectrum.peak().unwrap();
+        assert!(frequency == 500.0 || frequency == 502.0);
+        assert!((amplitude - 0.5).abs() < 0.005, "{}", amplitude);
+    }
+
+    #[test]
+    fn rejects_too_short_or_timeless_input() {
+        let mut planner = FftPlanner::new();
+        let window = WindowFunction::Hann;
+        assert!(Spectrum::compute(&mut planner, &[0.0, 1.0, 2.0], &[0.0; 3], window).is_none());
+        assert!(Spectrum::compute(&mut planner, &[0.0; 8], &[1.0; 8], window).is_none());
+    }
+
+    #[test]
+    fn averages_every_new_capture() {
+        let mut view = SpectrumView {
+            averages: 2,
+            ..SpectrumView::default()
+        };
+        let first = sine(500.0, 0.5);
+        // Same length, time axis and last sample, but another frequency
+        let mut second = sine(1000.0, 0.5);
+        Arc::make_mut(&mut second).1[N - 1] = first.1[N - 1];
+
+        view.update(&first);
+        view.last_update = None;
+        view.update(&second);
+        assert_eq!(view.history.len(), 2);
+        // Handing in the same capture again does not count twice
+        view.last_update = None;
+        view.update(&second);
+        let averaged = view.averaged().unwrap();
+        let at = |frequency: f64| averaged.amplitudes[(frequency / 2.0) as usize];
+        assert!((at(500.0) - 0.5 / 2f64.sqrt()).abs() < 0.005);
+        assert!((at(1000.0) - 0.5 / 2f64.sqrt()).abs() < 0.005);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::decoder::DecodedFrame;
+use crate::device::DataPoint;
+
+#[derive(Debug, Clone)]
+pub struct SpiConfig {
+    pub clk: usize,
+    pub mosi: Option<usize>,
+    pub miso: Option<usize>,
+    /// Without chip select every clock edge belongs to one long transaction
+    pub cs: Option<usize>,
+    pub cs_active_low: bool,
+    /// Clock level while idle
+    pub cpol: bool,
+    /// Sample on the second clock edge of a bit instead of the first
+    pub cpha: bool,
+    pub msb_first: bool,
+    pub word_bits: u8,
+}
+
+impl Default for SpiConfig {
+    fn default() -> Self {
+        Self {
+            clk: 0,
+            mosi: Some(1),
+            miso: Some(2),
+            cs: Some(3),
+            cs_active_low: true,
+            cpol: false,
+            cpha: false,
+            msb_first: true,
+            word_bits: 8,
+        }
+    }
+}
+
+impl SpiConfig {
+    /// SPI mode number 0-3
+    pub fn mode(&self) -> u8 {
+        (self.cpol as u8) << 1 | self.cpha as u8
+    }
+}
+
+/// Bits of the word currently being shifted in
+struct Word {
+    /// Time of the first sampling edge
+    start: f64,
+    edges: Vec<f64>,
+    mosi: u32,
+    miso: u32,
+}
+
+/// Decode SPI words on the MOSI and MISO lanes plus one transaction frame per chip select assertion
+pub fn decode(config: &SpiConfig, time: &[f64], points: &[DataPoint]) -> Vec<DecodedFrame> {
+    profiling::scope!("spi_decoder::decode");
+
+    let n = time.len().min(points.len());
+    if n < 2 || config.word_bits == 0 {
+        return Vec::new();
+    }
+    let selected = |i: usize| match config.cs {
+        Some(cs) => points[i].digital_channels[cs] != config.cs_active_low,
+        None => true,
+    };
+    let clk = |i: usize| points[i].digital_channels[config.clk];
+    // Modes 0 and 3 sample on the rising edge, modes 1 and 2 on the falling one
+    let sample_on_rising = config.cpol == config.cpha;
+
+    let mut frames = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut transaction: Option<(f64, usize)> = selected(0).then_some((time[0], 0));
+    for i in 1..n {
+        if selected(i) && !selected(i - 1) {
+            transaction = Some(((time[i - 1] + time[i]) / 2.0, 0));
+            word = None;
+        }
+        if !selected(i) {
+            if selected(i - 1) {
+                let end = (time[i - 1] + time[i]) / 2.0;
+                if let Some(word) = word.take() {
+                    // Chip select released in the middle of a word
+                    frames.extend(word_frames(
+                        config,
+                        &word,
+                        end,
+                        Some("incomplete".to_string()),
+                    ));
+                }
+                if let (Some(cs), Some((start, words))) = (config.cs, transaction.take()) {
+                    frames.push(DecodedFrame {
+                        start,
+                        end,
+                        channel: cs,
+                        value: "CS".to_string(),
+                        info: format!("{} words", words),
+                        error: None,
+                    });
+                }
+            }
+            continue;
+        }
+
+        if clk(i) == clk(i - 1) || clk(i) != sample_on_rising {
+            continue;
+        }
+        let edge = (time[i - 1] + time[i]) / 2.0;
+        let current = word.get_or_insert_with(|| Word {
+            start: edge,
+            edges: Vec::new(),
+            mosi: 0,
+            miso: 0,
+        });
+        let bit = current.edges.len() as u32;
+        let position = if config.msb_first {
+            config.word_bits as u32 - 1 - bit
+        } else {
+            bit
+        };
+        let level = |ch: Option<usize>| ch.is_some_and(|ch| points[i].digital_channels[ch]);
+        current.mosi |= (level(config.mosi) as u32) << position;
+        current.miso |= (level(config.miso) as u32) << position;
+        current.edges.push(edge);
+
+        if current.edges.len() == config.word_bits as usize {
+            let word = word.take().expect("word was just filled");
+            let end = word_end(&word);
+            frames.extend(word_frames(config, &word, end, None));
+            if let Some((_, words)) = &mut transaction {
+                *words += 1;
+            }
+        }
+    }
+    frames.sort_by(|a, b| a.start.total_cmp(&b.start));
+    frames
+}
+
+/// One bit period past the last sampling edge
+fn word_end(word: &Word) -> f64 {
+    match word.edges.as_slice() {
+        [first, .., last] => last + (last - first) / (word.edges.len() - 1) as f64,
+        _ => word.start,
+    }
+}
+
+fn word_frames(
+    config: &SpiConfig,
+    word: &Word,
+    end: f64,
+    error: Option<String>,
+) -> Vec<DecodedFrame> {
+    let digits = (config.word_bits as usize).div_ceil(4);
+    let lanes = [
+        (config.mosi, word.mosi, "MOSI"),
+        (config.miso, word.miso, "MISO"),
+    ];
+    let mut frames: Vec<DecodedFrame> = lanes
+        .iter()
+        .filter_map(|(channel, value, lane)| {
+            Some(DecodedFrame {
+                start: word.start,
+                end,
+                channel: (*channel)?,
+                value: format!("0x{:0digits$X}", value, digits = digits),
+                info: lane.to_string(),
+                error: error.clone(),
+            })
+        })
+        .collect();
+    if frames.is_empty() {
+        // Neither data line is set, still show the clocked word
+        frames.push(DecodedFrame {
+            start: word.start,
+            end,
+            channel: config.clk,
+            value: format!("{} bits", word.edges.len()),
+            info: String::new(),
+            error,
+        });
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLK: usize = 0;
+    const MOSI: usize = 1;
+    const MISO: usize = 2;
+    const CS: usize = 3;
+
+    /// Samples of a master sending `words` in one chip select, two samples per clock half period
+    struct Bus {
+        config: SpiConfig,
+        points: Vec<DataPoint>,
+    }
+
+    impl Bus {
+        fn new(config: SpiConfig) -> Self {
+            let mut bus = Self {
+                config,
+                points: Vec::new(),
+            };
+            bus.push(false, false, false, 4);
+            bus
+        }
+
+        fn push(&mut self, selected: bool, mosi: bool, miso: bool, samples: usize) {
+            self.push_clk(self.config.cpol, selected, mosi, miso, samples);
+        }
+
+        fn push_clk(&mut self, clk: bool, selected: bool, mosi: bool, miso: bool, samples: usize) {
+            let mut digital_channels = [false; 9];
+            digital_channels[CLK] = clk;
+            digital_channels[MOSI] = mosi;
+            digital_channels[MISO] = miso;
+            digital_channels[CS] = selected != self.config.cs_active_low;
+            for _ in 0..samples {
+                self.points.push(DataPoint {
+                    analog_channel: 0.0,
+                    digital_channels,
+                });
+            }
+        }
+
+        /// Clock out `bits` of each word pair, stopping after `bits` even mid-word
+        fn transaction(&mut self, words: &[(u32, u32)], bits: usize) {
+            self.push(true, false, false, 2);
+            let word_bits = self.config.word_bits as usize;
+            for (bit, (mosi, miso)) in (0..bits).map(|bit| (bit, words[bit / word_bits])) {
+                let index = bit % word_bits;
+                let position = if self.config.msb_first {
+                    word_bits - 1 - index
+                } else {
+                    index
+                };
+                let (mosi, miso) = (mosi >> position & 1 == 1, miso >> position & 1 == 1);
+                let idle = self.config.cpol;
+                if self.config.cpha {
+                    // Shift out on the leading edge, sample on the trailing one
+                    self.push_clk(!idle, true, mosi, miso, 2);
+                    self.push_clk(idle, true, mosi, miso, 2);
+                } else {
+                    // Data is set up before the leading edge, which samples it
+                    self.push_clk(idle, true, mosi, miso, 2);
+                    self.push_clk(!idle, true, mosi, miso, 2);
+                }
+            }
+            self.push(true, false, false, 2);
+            self.push(false, false, false, 4);
+        }
+
+        fn decode(&self) -> Vec<DecodedFrame> {
+            let time: Vec<f64> = (0..self.points.len()).map(|i| i as f64 * 1e-6).collect();
+            decode(&self.config, &time, &self.points)
+        }
+    }
+
+    fn lane(frames: &[DecodedFrame], channel: usize) -> Vec<&str> {
+        frames
+            .iter()
+            .filter(|f| f.channel == channel)
+            .map(|f| f.value.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_all_modes() {
+        for (cpol, cpha) in [(false, false), (false, true), (true, false), (true, true)] {
+            let config = SpiConfig {
+                cpol,
+                cpha,
+                ..SpiConfig::default()
+            };
+            let mut bus = Bus::new(config.clone());
+            bus.transaction(&[(0xA5, 0x3C), (0x01, 0x80)], 16);
+            let frames = bus.decode();
+
+            assert_eq!(
+                lane(&frames, MOSI),
+                ["0xA5", "0x01"],
+                "mode {}",
+                config.mode()
+            );
+            assert_eq!(
+                lane(&frames, MISO),
+                ["0x3C", "0x80"],
+                "mode {}",
+                config.mode()
+            );
+            let cs: Vec<&DecodedFrame> = frames.iter().filter(|f| f.channel == CS).collect();
+            assert_eq!(cs.len(), 1, "mode {}", config.mode());
+            assert_eq!(cs[0].info, "2 words");
+            assert!(frames.iter().all(|f| f.error.is_none()));
+        }
+    }
+
+    #[test]
+    fn decodes_lsb_first_and_wide_words() {
+        let config = SpiConfig {
+            msb_first: false,
+            word_bits: 12,
+            ..SpiConfig::default()
+        };
+        let mut bus = Bus::new(config);
+        bus.transaction(&[(0xABC, 0x123)], 12);
+        let frames = bus.decode();
+
+        assert_eq!(lane(&frames, MOSI), ["0xABC"]);
+        assert_eq!(lane(&frames, MISO), ["0x123"]);
+    }
+
+    #[test]
+    fn flags_words_cut_by_chip_select() {
+        let mut bus = Bus::new(SpiConfig::default());
+        bus.transaction(&[(0xFF, 0x00), (0xF0, 0x0F)], 12);
+        let frames = bus.decode();
+
+        let mosi: Vec<&DecodedFrame> = frames.iter().filter(|f| f.channel == MOSI).collect();
+        assert_eq!(mosi.len(), 2);
+        assert_eq!(mosi[0].error, None);
+        assert_eq!(mosi[1].error.as_deref(), Some("incomplete"));
+        let cs = frames.iter().find(|f| f.channel == CS).unwrap();
+        assert_eq!(cs.info, "1 words");
+    }
+}
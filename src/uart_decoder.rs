@@ -0,0 +1,295 @@
+use crate::decoder::DecodedFrame;
+
+/// Baud rates auto-baud snaps to when the measured rate is close enough
+const STANDARD_BAUD_RATES: [u32; 12] = [
+    300, 1200, 2400, 4800, 9600, 19_200, 38_400, 57_600, 115_200, 230_400, 460_800, 921_600,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+impl Parity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Parity::None => "None",
+            Parity::Even => "Even",
+            Parity::Odd => "Odd",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UartConfig {
+    pub channel: usize,
+    pub baud: u32,
+    pub auto_baud: bool,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: f64,
+    /// Line level between frames; true for plain UART, false for inverted (RS-232 level) signals
+    pub idle_high: bool,
+}
+
+impl Default for UartConfig {
+    fn default() -> Self {
+        Self {
+            channel: 0,
+            baud: 9600,
+            auto_baud: false,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1.0,
+            idle_high: true,
+        }
+    }
+}
+
+/// Guess the baud rate from the shortest pulse, snapped to a standard rate within 5%
+pub fn detect_baud(time: &[f64], bits: &[bool]) -> Option<u32> {
+    let mut shortest = f64::INFINITY;
+    let mut last_edge = None;
+    for i in 1..bits.len() {
+        if bits[i] != bits[i - 1] {
+            let edge = (time[i - 1] + time[i]) / 2.0;
+            if let Some(last) = last_edge {
+                shortest = shortest.min(edge - last);
+            }
+            last_edge = Some(edge);
+        }
+    }
+    if !shortest.is_finite() || shortest <= 0.0 {
+        return None;
+    }
+
+    let measured = 1.0 / shortest;
+    let standard = STANDARD_BAUD_RATES.iter().copied().min_by(|a, b| {
+        (*a as f64 - measured)
+            .abs()
+            .total_cmp(&(*b as f64 - measured).abs())
+    })?;
+    if (standard as f64 - measured).abs() / standard as f64 <= 0.05 {
+        Some(standard)
+    } else {
+        Some(measured.round() as u32)
+    }
+}
+
+/// Decode all complete UART frames; `bits` is the raw level of the configured channel
+pub fn decode(config: &UartConfig, baud: u32, time: &[f64], bits: &[bool]) -> Vec<DecodedFrame> {
+    profiling::scope!("uart_decoder::decode");
+
+    let n = time.len().min(bits.len());
+    if n < 2 || baud == 0 {
+        return Vec::new();
+    }
+    let bit_time = 1.0 / baud as f64;
+    // Idle is logical 1, the start bit logical 0
+    let level = |i: usize| bits[i] == config.idle_high;
+    let level_at = |t: f64| level(time[..n].partition_point(|&x| x < t).min(n - 1));
+
+    let parity_bits = if config.parity == Parity::None { 0 } else { 1 };
+    let frame_bits = 1.0 + config.data_bits as f64 + parity_bits as f64 + config.stop_bits;
+
+    let mut frames = Vec::new();
+    let mut i = 1;
+    while i < n {
+        // Wait for the falling edge of a start bit
+        if !level(i - 1) || level(i) {
+            i += 1;
+            continue;
+        }
+        let start = (time[i - 1] + time[i]) / 2.0;
+        let end = start + frame_bits * bit_time;
+        if end > time[n - 1] {
+            break;
+        }
+        let sample = |bit: f64| level_at(start + (bit + 0.5) * bit_time);
+
+        if sample(0.0) {
+            // Glitch, not a start bit
+            i += 1;
+            continue;
+        }
+
+        let mut value = 0u16;
+        for bit in 0..config.data_bits {
+            if sample(1.0 + bit as f64) {
+                value |= 1 << bit;
+            }
+        }
+
+        let mut error = None;
+        let parity_position = 1.0 + config.data_bits as f64;
+        if config.parity != Parity::None {
+            let ones = value.count_ones() + sample(parity_position) as u32;
+            let expected_odd = config.parity == Parity::Odd;
+            if (ones % 2 == 1) != expected_odd {
+                error = Some("parity".to_string());
+            }
+        }
+        let stop_position = parity_position + parity_bits as f64;
+        if !sample(stop_position) {
+            error = Some("framing".to_string());
+        }
+
+        frames.push(DecodedFrame {
+            start,
+            end,
+            channel: config.channel,
+            value: format!("0x{:02X}", value),
+            info: describe_byte(value),
+            error,
+        });
+
+        // Resume in the middle of the first stop bit, the line is idle from there
+        let resume = start + (stop_position + 0.5) * bit_time;
+        i = time[..n].partition_point(|&x| x < resume).max(i + 1);
+    }
+    frames
+}
+
+fn describe_byte(value: u16) -> String {
+    match u8::try_from(value).ok().map(char::from) {
+        Some(c) if c.is_ascii_graphic() || c == ' ' => format!("'{}'", c),
+        Some(c) => format!("'{}'", c.escape_default()),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES_PER_BIT: usize = 16;
+
+    /// A byte on the line, optionally with a flipped parity bit or a low stop bit
+    struct Byte {
+        value: u8,
+        bad_parity: bool,
+        bad_stop: bool,
+    }
+
+    fn byte(value: u8) -> Byte {
+        Byte {
+            value,
+            bad_parity: false,
+            bad_stop: false,
+        }
+    }
+
+    /// Sampled line sending `bytes` as `config` describes, idle before, between and after
+    fn encode(config: &UartConfig, bytes: &[Byte]) -> (Vec<f64>, Vec<bool>) {
+        let mut logical = vec![true; 3];
+        for byte in bytes {
+            logical.push(false);
+            for bit in 0..config.data_bits {
+                logical.push(byte.value >> bit & 1 == 1);
+            }
+            if config.parity != Parity::None {
+                let odd_ones = byte.value.count_ones() % 2 == 1;
+                let bit = match config.parity {
+                    Parity::Even => odd_ones,
+                    _ => !odd_ones,
+                };
+                logical.push(bit != byte.bad_parity);
+            }
+            logical.extend(std::iter::repeat_n(
+                !byte.bad_stop,
+                config.stop_bits.ceil() as usize,
+            ));
+            logical.extend([true; 2]);
+        }
+        let bits: Vec<bool> = logical
+            .iter()
+            .flat_map(|&bit| std::iter::repeat_n(bit == config.idle_high, SAMPLES_PER_BIT))
+            .collect();
+        let dt = 1.0 / (config.baud as f64 * SAMPLES_PER_BIT as f64);
+        let time = (0..bits.len()).map(|i| i as f64 * dt).collect();
+        (time, bits)
+    }
+
+    fn decode_all(config: &UartConfig, bytes: &[Byte]) -> Vec<DecodedFrame> {
+        let (time, bits) = encode(config, bytes);
+        decode(config, config.baud, &time, &bits)
+    }
+
+    #[test]
+    fn decodes_8n1() {
+        let config = UartConfig::default();
+        let frames = decode_all(&config, &[byte(b'H'), byte(b'i'), byte(0x00), byte(0xFF)]);
+
+        let values: Vec<&str> = frames.iter().map(|f| f.value.as_str()).collect();
+        assert_eq!(values, ["0x48", "0x69", "0x00", "0xFF"]);
+        assert_eq!(frames[0].info, "'H'");
+        assert!(frames.iter().all(|f| f.error.is_none()));
+    }
+
+    #[test]
+    fn checks_even_and_odd_parity() {
+        for parity in [Parity::Even, Parity::Odd] {
+            let config = UartConfig {
+                parity,
+                ..UartConfig::default()
+            };
+            let bad = Byte {
+                bad_parity: true,
+                ..byte(0x55)
+            };
+            let frames = decode_all(&config, &[byte(0x55), byte(0x01), bad]);
+
+            assert_eq!(frames.len(), 3, "{:?}", parity);
+            assert_eq!(frames[0].error, None, "{:?}", parity);
+            assert_eq!(frames[1].error, None, "{:?}", parity);
+            assert_eq!(frames[2].value, "0x55");
+            assert_eq!(frames[2].error.as_deref(), Some("parity"), "{:?}", parity);
+        }
+    }
+
+    #[test]
+    fn reports_framing_errors() {
+        let config = UartConfig::default();
+        let bad = Byte {
+            bad_stop: true,
+            ..byte(0x41)
+        };
+        let frames = decode_all(&config, &[bad, byte(0x42)]);
+
+        assert_eq!(frames[0].error.as_deref(), Some("framing"));
+        assert_eq!(frames.last().unwrap().value, "0x42");
+        assert_eq!(frames.last().unwrap().error, None);
+    }
+
+    #[test]
+    fn decodes_inverted_seven_bit_frames() {
+        let config = UartConfig {
+            data_bits: 7,
+            stop_bits: 2.0,
+            idle_high: false,
+            baud: 115_200,
+            ..UartConfig::default()
+        };
+        let frames = decode_all(&config, &[byte(0x7F), byte(0x2A)]);
+
+        let values: Vec<&str> = frames.iter().map(|f| f.value.as_str()).collect();
+        assert_eq!(values, ["0x7F", "0x2A"]);
+        assert!(frames.iter().all(|f| f.error.is_none()));
+    }
+
+    #[test]
+    fn detects_standard_baud_rates() {
+        for baud in [9600, 115_200] {
+            let config = UartConfig {
+                baud,
+                ..UartConfig::default()
+            };
+            // 0x55 alternates, so single bit pulses are on the line
+            let (time, bits) = encode(&config, &[byte(0x55)]);
+            assert_eq!(detect_baud(&time, &bits), Some(baud));
+        }
+        assert_eq!(detect_baud(&[0.0, 1.0], &[true, true]), None);
+    }
+}
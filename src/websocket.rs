@@ -0,0 +1,425 @@
+use anyhow::{Context, Result};
+use base64::Engine as _;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+};
+
+/// Appended to the client's key to prove the server speaks WebSocket, see RFC 6455 section 1.3
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The only protocol version there is, see RFC 6455 section 4.1
+const VERSION: &str = "13";
+
+/// Bounds of the upgrade request, browsers stay far below them
+const MAX_HEADER_LINE: usize = 8 * 1024;
+const MAX_HEADER_LINES: usize = 64;
+
+/// Clients only send short commands, anything longer is refused
+const MAX_MESSAGE_LEN: u64 = 64 * 1024;
+
+/// Payload limit of close, ping and pong frames
+const MAX_CONTROL_LEN: u64 = 125;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+pub enum Message {
+    Text(String),
+    /// Binary payloads are not used by any request, only their arrival is reported
+    Binary,
+    Ping(Vec<u8>),
+    Close,
+}
+
+/// Read the HTTP upgrade request and answer it, after which both sides speak WebSocket
+pub async fn accept<R, W>(reader: &mut R, writer: &mut W) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let request_line = read_header_line(reader).await?;
+    let mut parts = request_line.split_whitespace();
+    let valid_request = parts.next() == Some("GET")
+        && parts.next().is_some()
+        && parts.next() == Some("HTTP/1.1")
+        && parts.next().is_none();
+
+    let mut key = None;
+    let mut upgrade = false;
+    let mut version = None;
+    let mut lines = 0;
+    loop {
+        let line = read_header_line(reader).await?;
+        if line.is_empty() {
+            break;
+        }
+        lines += 1;
+        if lines > MAX_HEADER_LINES {
+            anyhow::bail!("More than {} header lines in handshake", MAX_HEADER_LINES);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("Sec-WebSocket-Version") {
+                version = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("Upgrade") {
+                upgrade = value.eq_ignore_ascii_case("websocket");
+            }
+        }
+    }
+
+    let Some(key) = key.filter(|_| upgrade && valid_request) else {
+        writer
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")
+            .await?;
+        anyhow::bail!("Not a WebSocket upgrade request: {}", request_line);
+    };
+    if version.as_deref() != Some(VERSION) {
+        // Tells the client which version to retry with, see RFC 6455 section 4.4
+        writer
+            .write_all(
+                format!(
+                    "HTTP/1.1 426 Upgrade Required\r\nSec-WebSocket-Version: {}\r\nContent-Length: 0\r\n\r\n",
+                    VERSION
+                )
+                .as_bytes(),
+            )
+            .await?;
+        anyhow::bail!("Unsupported WebSocket version {:?}", version);
+    }
+    writer
+        .write_all(
+            format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(&key)
+            )
+            .as_bytes(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Proof for the client that the server understood its handshake
+fn accept_key(key: &str) -> String {
+    let digest = ring::digest::digest(
+        &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{}{}", key, ACCEPT_GUID).as_bytes(),
+    );
+    base64::engine::general_purpose::STANDARD.encode(digest.as_ref())
+}
+
+/// One handshake line without its line ending, refusing lines longer than [`MAX_HEADER_LINE`]
+async fn read_header_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<String> {
+    let mut line = Vec::new();
+    let read = (&mut *reader)
+        .take(MAX_HEADER_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        anyhow::bail!("Connection closed during handshake");
+    }
+    if line.last() != Some(&b'\n') {
+        if line.len() > MAX_HEADER_LINE {
+            anyhow::bail!("Handshake line longer than {} bytes", MAX_HEADER_LINE);
+        }
+        anyhow::bail!("Connection closed during handshake");
+    }
+    let line = String::from_utf8(line).context("Handshake is not UTF-8")?;
+    Ok(line.trim_end().to_string())
+}
+
+/// Reads client messages, keeping a fragmented message across the control frames sent in between
+pub struct MessageReader<R> {
+    reader: R,
+    /// Opcode and payload of a fragmented message still waiting for its final frame
+    partial: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin> MessageReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            partial: None,
+        }
+    }
+
+    /// Read the next message, joining fragmented ones
+    pub async fn read(&mut self) -> Result<Message> {
+        loop {
+            let mut head = [0u8; 2];
+            self.reader.read_exact(&mut head).await?;
+            let fin = head[0] & 0x80 != 0;
+            let opcode = head[0] & 0x0F;
+            let masked = head[1] & 0x80 != 0;
+            // Clients have to mask every frame, see RFC 6455 section 5.1
+            if !masked {
+                anyhow::bail!("Unmasked frame from client");
+            }
+            let len = match head[1] & 0x7F {
+                126 => self.reader.read_u16().await? as u64,
+                127 => self.reader.read_u64().await?,
+                len => len as u64,
+            };
+            let control = opcode & 0x8 != 0;
+            if control && (!fin || len > MAX_CONTROL_LEN) {
+                anyhow::bail!(
+                    "Control frame fragmented or longer than {} bytes",
+                    MAX_CONTROL_LEN
+                );
+            }
+            if len > MAX_MESSAGE_LEN {
+                anyhow::bail!("Message of {} bytes is too long", len);
+            }
+            let mut mask = [0u8; 4];
+            self.reader.read_exact(&mut mask).await?;
+            let mut payload = vec![0u8; len as usize];
+            self.reader.read_exact(&mut payload).await?;
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+
+            match opcode {
+                OPCODE_CLOSE => return Ok(Message::Close),
+                OPCODE_PING => return Ok(Message::Ping(payload)),
+                OPCODE_PONG => continue,
+                OPCODE_TEXT | OPCODE_BINARY => {
+                    if self.partial.is_some() {
+                        anyhow::bail!("New message before the fragmented one ended");
+                    }
+                    self.partial = Some((opcode, payload));
+                }
+                OPCODE_CONTINUATION => {
+                    let (_, data) = self
+                        .partial
+                        .as_mut()
+                        .context("Continuation frame without a message")?;
+                    if (data.len() + payload.len()) as u64 > MAX_MESSAGE_LEN {
+                        anyhow::bail!("Fragmented message is too long");
+                    }
+                    data.extend_from_slice(&payload);
+                }
+                _ => anyhow::bail!("Unknown opcode {:#x}", opcode),
+            }
+
+            if fin {
+                return match self.partial.take() {
+                    Some((OPCODE_TEXT, data)) => Ok(Message::Text(
+                        String::from_utf8(data).context("Text message is not UTF-8")?,
+                    )),
+                    Some(_) => Ok(Message::Binary),
+                    None => anyhow::bail!("Final frame without a message"),
+                };
+            }
+        }
+    }
+}
+
+/// Encode a single unmasked server frame
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+pub fn text_frame(text: &str) -> Vec<u8> {
+    frame(OPCODE_TEXT, text.as_bytes())
+}
+
+pub fn binary_frame(data: &[u8]) -> Vec<u8> {
+    frame(OPCODE_BINARY, data)
+}
+
+pub fn pong_frame(payload: &[u8]) -> Vec<u8> {
+    frame(OPCODE_PONG, payload)
+}
+
+pub fn close_frame() -> Vec<u8> {
+    frame(OPCODE_CLOSE, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Masked client frame, extended lengths are chosen like a client would
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    async fn read_all(bytes: &[u8]) -> Result<Message> {
+        MessageReader::new(bytes).read().await
+    }
+
+    async fn handshake(request: &str) -> (Result<()>, String) {
+        let mut response = Vec::new();
+        let result = accept(&mut request.as_bytes(), &mut response).await;
+        (result, String::from_utf8(response).unwrap())
+    }
+
+    const UPGRADE: &str =
+        "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\n\
+        Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+        Sec-WebSocket-Version: 13\r\n\r\n";
+
+    #[tokio::test]
+    async fn handshake_answers_with_accept_key() {
+        let (result, response) = handshake(UPGRADE).await;
+        result.unwrap();
+        assert!(response.starts_with("HTTP/1.1 101"));
+        // Example from RFC 6455 section 1.3
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[tokio::test]
+    async fn handshake_rejects_other_versions() {
+        let request = UPGRADE.replace("Version: 13", "Version: 8");
+        let (result, response) = handshake(&request).await;
+        assert!(result.is_err());
+        assert!(response.starts_with("HTTP/1.1 426"));
+        assert!(response.contains("Sec-WebSocket-Version: 13"));
+    }
+
+    #[tokio::test]
+    async fn handshake_rejects_other_requests() {
+        let request = UPGRADE.replace("GET /chat HTTP/1.1", "POST /chat HTTP/1.1");
+        let (result, response) = handshake(&request).await;
+        assert!(result.is_err());
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        let request = UPGRADE.replace("GET /chat HTTP/1.1", "GET /chat HTTP/1.0");
+        assert!(handshake(&request).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn handshake_bounds_header_lines() {
+        let long = format!(
+            "GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n",
+            "a".repeat(MAX_HEADER_LINE)
+        );
+        let (result, response) = handshake(&long).await;
+        assert!(result.is_err());
+        assert!(response.is_empty());
+
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X-Header: 1\r\n".repeat(MAX_HEADER_LINES + 1)
+        );
+        assert!(handshake(&many).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn unmasks_text() {
+        // Masked "Hello" from RFC 6455 section 5.7
+        let frame = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        match read_all(&frame).await.unwrap() {
+            Message::Text(text) => assert_eq!(text, "Hello"),
+            _ => panic!("expected a text message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unmasked_frames() {
+        let frame = [0x81, 0x05, b'H', b'e', b'l', b'l', b'o'];
+        assert!(read_all(&frame).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_extended_lengths() {
+        let medium = "m".repeat(300);
+        match read_all(&client_frame(true, OPCODE_TEXT, medium.as_bytes()))
+            .await
+            .unwrap()
+        {
+            Message::Text(text) => assert_eq!(text, medium),
+            _ => panic!("expected a text message"),
+        }
+
+        let large = "l".repeat(MAX_MESSAGE_LEN as usize);
+        match read_all(&client_frame(true, OPCODE_TEXT, large.as_bytes()))
+            .await
+            .unwrap()
+        {
+            Message::Text(text) => assert_eq!(text.len(), large.len()),
+            _ => panic!("expected a text message"),
+        }
+
+        let too_large = vec![0u8; MAX_MESSAGE_LEN as usize + 1];
+        assert!(read_all(&client_frame(true, OPCODE_BINARY, &too_large))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn joins_fragments_around_control_frames() {
+        let mut bytes = client_frame(false, OPCODE_TEXT, b"Hel");
+        bytes.extend(client_frame(true, OPCODE_PING, b"hi"));
+        bytes.extend(client_frame(true, OPCODE_CONTINUATION, b"lo"));
+
+        let mut reader = MessageReader::new(bytes.as_slice());
+        match reader.read().await.unwrap() {
+            Message::Ping(payload) => assert_eq!(payload, b"hi"),
+            _ => panic!("expected the ping first"),
+        }
+        match reader.read().await.unwrap() {
+            Message::Text(text) => assert_eq!(text, "Hello"),
+            _ => panic!("expected a text message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_broken_fragmentation() {
+        let continuation = client_frame(true, OPCODE_CONTINUATION, b"lo");
+        assert!(read_all(&continuation).await.is_err());
+
+        let mut interleaved = client_frame(false, OPCODE_TEXT, b"Hel");
+        interleaved.extend(client_frame(true, OPCODE_TEXT, b"lo"));
+        assert!(read_all(&interleaved).await.is_err());
+
+        let fragmented_ping = client_frame(false, OPCODE_PING, b"hi");
+        assert!(read_all(&fragmented_ping).await.is_err());
+    }
+
+    #[test]
+    fn server_frames_use_shortest_length() {
+        assert_eq!(text_frame("Hi"), [0x81, 0x02, b'H', b'i']);
+        assert_eq!(&binary_frame(&[0; 300])[..4], [0x82, 126, 0x01, 0x2c]);
+        assert_eq!(
+            &binary_frame(&[0; 70_000])[..10],
+            [0x82, 127, 0, 0, 0, 0, 0, 0x01, 0x11, 0x70]
+        );
+    }
+}
@@ -1,13 +1,27 @@
 use anyhow::Result;
 use arc_swap::ArcSwap;
 use fleascope_rs::{ProbeType, Waveform};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tokio::sync::watch::{self, Sender};
 
+use crate::acquisition_mode::{Acquisition, AcquisitionMode};
+use crate::capture_error::{CaptureError, CaptureErrorLog};
+use crate::continuous_buffer::ContinuousBatch;
+use crate::cursors::Cursors;
+use crate::decoder::DecoderPanel;
 use crate::device::{
-    CaptureConfig, CaptureMode, ControlCommand, DeviceData, Notification, TriggerConfig,
-    WaveformConfig, MAX_TIME_FRAME, MIN_TIME_FRAME,
+    AcquisitionState, CaptureConfig, CaptureMode, ControlCommand, DeviceData, Notification,
+    TriggerConfig, WaveformConfig, CONTINUOUS_SAMPLE_RATE_HZ, MAX_TIME_FRAME, MAX_TRIGGER_OFFSET,
+    MAX_TRIGGER_POSITION, MIN_TIME_FRAME,
 };
+use crate::live_stream::StreamFrame;
+use crate::measurements::MeasurementPanel;
+use crate::persistence::Persistence;
+use crate::recorder::{BatchSender, Recorder, RecordingConfig, RecordingStatus};
+use crate::reference::ReferenceOverlay;
+use crate::spectrum::SpectrumView;
 
 #[derive(Clone)]
 pub struct TriggeredCaptureConfig {
@@ -19,7 +33,7 @@ pub struct ContinuousCaptureConfig {
     pub buffer_time: f64,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum CaptureModeFlat {
     Triggered,
     Continuous,
@@ -35,14 +49,31 @@ pub struct FleaScopeDevice {
     control_signal_tx: tokio::sync::mpsc::Sender<ControlCommand>, // Channel for calibration commands
     pub notification_rx: tokio::sync::mpsc::Receiver<Notification>, // Channel for calibration results
     waveform_tx: Sender<WaveformConfig>, // Channel for waveform configuration
-    pub batch_rx: tokio::sync::mpsc::UnboundedReceiver<Vec<f64>>, // Channel for continuous batches
+    pub batch_rx: tokio::sync::mpsc::UnboundedReceiver<ContinuousBatch>, // Channel for continuous batches
+    recording_tx: Sender<Option<BatchSender>>, // Hands the recorder's channel to the worker
+    state_rx: watch::Receiver<AcquisitionState>, // Acquisition state reported by the worker
+    pub error_rx: tokio::sync::mpsc::UnboundedReceiver<CaptureError>, // Frames dropped by the worker
+    stream_tx: broadcast::Sender<StreamFrame>, // Captures and batches for WebSocket subscribers
+    captures: Option<broadcast::Receiver<StreamFrame>>, // Every capture, while modes combine them
+    pub errors: CaptureErrorLog,
+    recorder: Option<Recorder>,
     triggered_config: TriggeredCaptureConfig,
     continuous_config: ContinuousCaptureConfig,
     capture_mode: CaptureModeFlat,
     pub wrap: bool,
+    /// Draw the min/max band of the continuous view, so short glitches stay visible
+    pub envelope: bool,
+    pub cursors: Cursors,
+    pub measurements: MeasurementPanel,
+    pub spectrum: SpectrumView,
+    pub decoder: DecoderPanel,
+    pub reference: ReferenceOverlay,
+    pub persistence: Persistence,
+    pub acquisition: Acquisition,
 }
 
 impl FleaScopeDevice {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: String,
         config_change_tx: Sender<CaptureConfig>,
@@ -52,7 +83,11 @@ impl FleaScopeDevice {
         initial_config: CaptureConfig,
         waveform_tx: Sender<WaveformConfig>,
         initial_waveform: WaveformConfig,
-        batch_rx: tokio::sync::mpsc::UnboundedReceiver<Vec<f64>>,
+        batch_rx: tokio::sync::mpsc::UnboundedReceiver<ContinuousBatch>,
+        recording_tx: Sender<Option<BatchSender>>,
+        state_rx: watch::Receiver<AcquisitionState>,
+        error_rx: tokio::sync::mpsc::UnboundedReceiver<CaptureError>,
+        stream_tx: broadcast::Sender<StreamFrame>,
     ) -> Self {
         let mut triggered_config = TriggeredCaptureConfig {
             time_frame: 0.1,
@@ -84,12 +119,31 @@ impl FleaScopeDevice {
             notification_rx,
             waveform_tx,
             batch_rx,
+            recording_tx,
+            state_rx,
+            error_rx,
+            stream_tx,
+            captures: None,
+            errors: CaptureErrorLog::default(),
+            recorder: None,
             wrap: true,
+            envelope: true,
+            cursors: Cursors::default(),
+            measurements: MeasurementPanel::default(),
+            spectrum: SpectrumView::default(),
+            decoder: DecoderPanel::default(),
+            reference: ReferenceOverlay::default(),
+            persistence: Persistence::default(),
+            acquisition: Acquisition::default(),
         }
     }
 
     /// Signal that configuration has changed and data generation should restart
-    fn signal_config_change(&self) {
+    fn signal_config_change(&mut self) {
+        // Captures taken with other settings do not average with the new ones, including
+        // those still queued for `combine_captures`
+        self.acquisition.reset();
+        self.captures = None;
         let cm = match self.capture_mode {
             CaptureModeFlat::Triggered => CaptureMode::Triggered {
                 trigger_config: self.triggered_config.trigger_config.clone(),
@@ -105,22 +159,103 @@ impl FleaScopeDevice {
             .expect("Failed to send config change signal");
     }
 
-    pub fn pause(&mut self) {
+    /// Fails if the worker is gone or its queue is full, e.g. from a remote client flooding it
+    pub fn pause(&mut self) -> Result<()> {
         self.control_signal_tx
             .try_send(ControlCommand::Pause)
-            .expect("Failed to send resume command");
+            .map_err(|e| anyhow::anyhow!("Failed to send pause command: {}", e))
     }
 
-    pub fn stop(self) {
-        self.control_signal_tx
-            .try_send(ControlCommand::Exit)
-            .expect("Failed to send exit command");
+    pub fn stop(mut self) {
+        self.stop_recording();
+        if let Err(e) = self.control_signal_tx.try_send(ControlCommand::Exit) {
+            tracing::warn!("Failed to send exit command to {}: {}", self.name, e);
+        }
     }
 
-    pub fn resume(&mut self) {
+    /// Fails if the worker is gone or its queue is full, e.g. from a remote client flooding it
+    pub fn resume(&mut self) -> Result<()> {
         self.control_signal_tx
             .try_send(ControlCommand::Resume)
-            .expect("Failed to send resume command");
+            .map_err(|e| anyhow::anyhow!("Failed to send resume command: {}", e))
+    }
+
+    /// Arm the trigger for a single capture; the worker pauses once it arrives
+    pub fn single(&mut self) -> Result<()> {
+        self.control_signal_tx
+            .try_send(ControlCommand::Step)
+            .map_err(|e| anyhow::anyhow!("Failed to send single command: {}", e))
+    }
+
+    pub fn acquisition_state(&self) -> AcquisitionState {
+        *self.state_rx.borrow()
+    }
+
+    /// Receive every capture and continuous batch from now on; falls behind rather than blocking the worker
+    pub fn subscribe_stream(&self) -> broadcast::Receiver<StreamFrame> {
+        self.stream_tx.subscribe()
+    }
+
+    /// Feed every triggered capture since the last call to the acquisition mode and persistence
+    ///
+    /// Captures come from the stream rather than `data`, so none is skipped when the UI renders
+    /// slower than the device captures. The subscription only lives while they are needed.
+    pub fn combine_captures(&mut self, columns: u32) {
+        profiling::scope!("FleaScopeDevice::combine_captures");
+
+        let wanted = matches!(self.capture_mode, CaptureModeFlat::Triggered)
+            && (self.persistence.enabled || self.acquisition.mode != AcquisitionMode::Normal);
+        if !wanted {
+            self.captures = None;
+            return;
+        }
+        let captures = self
+            .captures
+            .get_or_insert_with(|| self.stream_tx.subscribe());
+        loop {
+            match captures.try_recv() {
+                Ok(StreamFrame::Capture(data)) => {
+                    let volts: Vec<f64> =
+                        data.data_points.iter().map(|p| p.analog_channel).collect();
+                    self.acquisition.accumulate(&data.x_values, &volts, columns);
+                    if self.persistence.enabled {
+                        self.persistence.accumulate(&data.x_values, &volts);
+                    }
+                }
+                Ok(StreamFrame::Batch(_)) => {}
+                Err(broadcast::error::TryRecvError::Lagged(skipped)) => {
+                    tracing::warn!("{}: {} captures not combined", self.name, skipped);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Start writing the continuous stream to disk; batches flow whenever continuous mode runs
+    pub fn start_recording(&mut self, config: RecordingConfig) -> Result<()> {
+        if self.recorder.is_some() {
+            anyhow::bail!("{} is already recording", self.name);
+        }
+        let (recorder, tx) = Recorder::start(
+            &self.name,
+            self.probe_multiplier,
+            CONTINUOUS_SAMPLE_RATE_HZ,
+            config,
+        )?;
+        self.recording_tx.send_replace(Some(tx));
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Stop recording and return the final status, or `None` if not recording
+    pub fn stop_recording(&mut self) -> Option<RecordingStatus> {
+        // Drop the worker's sender first so the recorder sees the end of the stream
+        self.recording_tx.send_replace(None);
+        self.recorder.take().map(Recorder::stop)
+    }
+
+    pub fn recording_status(&self) -> Option<Arc<RecordingStatus>> {
+        self.recorder.as_ref().map(Recorder::status)
     }
 
     pub fn set_waveform(&mut self, waveform_type: Waveform, frequency_hz: i32) {
@@ -134,11 +269,15 @@ impl FleaScopeDevice {
 
     pub fn set_probe_multiplier(&mut self, multiplier: ProbeType) {
         self.probe_multiplier = multiplier;
+        // Volts of the other probe do not belong in the same map
+        self.persistence.clear();
         self.signal_config_change();
     }
 
-    pub fn set_trigger_config(&mut self, trigger_config: TriggerConfig) {
+    pub fn set_trigger_config(&mut self, mut trigger_config: TriggerConfig) {
         tracing::debug!("Setting trigger config: {:?}", trigger_config);
+        trigger_config.position = trigger_config.position.clamp(0.0, MAX_TRIGGER_POSITION);
+        trigger_config.offset = trigger_config.offset.clamp(0.0, MAX_TRIGGER_OFFSET);
         self.triggered_config.trigger_config = trigger_config;
         self.signal_config_change();
     }
@@ -207,3 +346,50 @@ impl FleaScopeDevice {
             .map_err(|e| anyhow::anyhow!("Failed to send storage command: {}", e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{DataPoint, DeviceManager};
+    use std::time::Instant;
+
+    fn capture(volts: f64) -> StreamFrame {
+        StreamFrame::Capture(Arc::new(DeviceData {
+            x_values: vec![0.0, 1e-3],
+            data_points: vec![
+                DataPoint {
+                    analog_channel: volts,
+                    digital_channels: [false; 9],
+                };
+                2
+            ],
+            last_update: Instant::now(),
+            update_rate: 0.0,
+            connected: true,
+            running: true,
+            settings: None,
+        }))
+    }
+
+    #[tokio::test]
+    async fn drops_captures_queued_before_a_config_change() {
+        let mut manager = DeviceManager::default();
+        manager.add_device("sim://sine-1k".to_string()).unwrap();
+        let device = &mut manager.get_devices_mut()[0];
+        device.acquisition.set_mode(AcquisitionMode::Average);
+        device.combine_captures(100);
+
+        // The worker does not run before the test yields, these are the only captures
+        assert!(device.stream_tx.send(capture(1.0)).is_ok());
+        assert!(device.stream_tx.send(capture(3.0)).is_ok());
+        device.set_time_frame(0.05);
+        device.combine_captures(100);
+        assert!(device.acquisition.traces().is_empty());
+
+        assert!(device.stream_tx.send(capture(5.0)).is_ok());
+        device.combine_captures(100);
+        let average = &device.acquisition.traces()[0];
+        assert_eq!(average.name, "Average (1)");
+        assert_eq!(average.points[0][1], 5.0);
+    }
+}